@@ -1,4 +1,5 @@
 use crate::environment::MovementGrid;
+use crate::galaxy::{JumpRoute, SystemGrids};
 use crate::movable::{
     get_neighbours, heuristical_distance, inertia_based_inter_cell_movement, Heading, MoveCommand,
     MovementPath, NodeCoords, PathNode, DISTANCE_FACTOR,
@@ -9,11 +10,20 @@ use strum::IntoEnumIterator;
 
 #[derive(Component)]
 pub struct AStarParams {
+    /// Star system whose grid the path runs on
+    system: usize,
     movement_grid: Vec<Vec<HashMap<Heading, AStarNode>>>,
     open_set: HashSet<NodeCoords>,
     target: UVec2,
+    /// The world position `target` was taken from
+    destination: Vec2,
     came_from: HashMap<NodeCoords, NodeCoords>,
 }
+impl AStarParams {
+    pub fn destination(&self) -> Vec2 {
+        self.destination
+    }
+}
 #[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
 struct AStarNode {
     f_score: i32,
@@ -46,7 +56,7 @@ fn reconstruct_path(
 }
 pub fn a_star(
     movables: Query<(Entity, &mut Transform, &mut MoveCommand), Without<MovementPath>>,
-    gridmap: Res<MovementGrid>,
+    grids: SystemGrids,
     mut commands: Commands,
 ) {
     for (entity, transform, movcmd) in movables.iter() {
@@ -55,8 +65,42 @@ pub fn a_star(
             commands.entity(entity).remove::<MoveCommand>();
             continue;
         }
+        let (Some(system), Some(target_system)) = (
+            grids.galaxy.system_at(transform.translation.xz()),
+            grids.galaxy.system_at(movcmd.target),
+        ) else {
+            commands.entity(entity).remove::<MoveCommand>();
+            continue;
+        };
+        // Other systems are reached one jump at a time, the path only leads to the next gate
+        let mut leg_target: Vec2 = movcmd.target;
+        if system == target_system {
+            commands.entity(entity).remove::<JumpRoute>();
+        } else {
+            let next_gate: Option<(usize, Vec2)> = grids
+                .galaxy
+                .route(system, target_system)
+                .and_then(|gates| gates.first().copied())
+                .and_then(|gate| {
+                    let (entrance, _, _) = grids.galaxy.jump_gates[gate].from_system(system)?;
+                    Some((gate, entrance))
+                });
+            let Some((gate, entrance)) = next_gate else {
+                println!("No jump gates lead to {}", movcmd.target);
+                commands.entity(entity).remove::<MoveCommand>();
+                continue;
+            };
+            leg_target = entrance;
+            commands.entity(entity).insert(JumpRoute {
+                gate,
+                target: movcmd.target,
+            });
+        }
+        let Some(gridmap) = grids.grid(system) else {
+            continue;
+        };
         let target: UVec2 =
-            (movcmd.target / gridmap.settings.cell_size + gridmap.settings.xy_offset).as_uvec2();
+            (leg_target / gridmap.settings.cell_size + gridmap.settings.xy_offset).as_uvec2();
         let start: UVec2 = UVec2 {
             x: (transform.translation.x / gridmap.settings.cell_size + gridmap.settings.xy_offset.x)
                 as u32,
@@ -86,6 +130,7 @@ pub fn a_star(
         commands
             .entity(entity)
             .insert(AStarParams {
+                system,
                 movement_grid,
                 open_set: HashSet::from([NodeCoords {
                     xy: start,
@@ -93,30 +138,41 @@ pub fn a_star(
                 }]),
                 came_from: HashMap::new(),
                 target,
+                destination: leg_target,
             })
             .remove::<MoveCommand>();
     }
 }
 pub fn calculate_a_star(
     mut movables: Query<(Entity, &mut AStarParams), Without<MovementPath>>,
-    gridmap: Res<MovementGrid>,
+    grids: SystemGrids,
     mut commands: Commands,
     // mut meshes: ResMut<Assets<Mesh>>,
     // mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
     for (entity, mut params) in movables.iter_mut() {
+        let Some(gridmap) = grids.grid(params.system) else {
+            commands.entity(entity).remove::<AStarParams>();
+            continue;
+        };
         let mut current: NodeCoords = NodeCoords {
             xy: UVec2::ZERO,
             h: Some(Heading::N),
         };
         let mut current_cost = 0;
+        let order = |cell: &NodeCoords| (cell.xy.x, cell.xy.y, cell.h.map(|h| h as u8));
         for open_cell in &params.open_set {
             let cell: &AStarNode = params.movement_grid[open_cell.xy.x as usize]
                 [open_cell.xy.y as usize]
                 .get(&open_cell.h.unwrap_or_default())
                 .unwrap();
             let cell_f_score: i32 = cell.f_score;
-            if current_cost == 0 || cell_f_score < current_cost {
+            // The set hands out its cells in a different order every run, so ties go to the
+            // lowest one
+            if current_cost == 0
+                || cell_f_score < current_cost
+                || (cell_f_score == current_cost && order(open_cell) < order(&current))
+            {
                 current = *open_cell;
                 current_cost = cell_f_score;
             }
@@ -142,7 +198,7 @@ pub fn calculate_a_star(
         if current.xy == params.target {
             let mut movementpath: MovementPath = MovementPath { path: Vec::new() };
 
-            reconstruct_path(&params.came_from, current, &gridmap)
+            reconstruct_path(&params.came_from, current, gridmap)
                 .iter()
                 .enumerate()
                 .for_each(|(i, x)| {
@@ -155,7 +211,7 @@ pub fn calculate_a_star(
             return;
         }
         params.open_set.remove(&current);
-        let neighbours = get_neighbours(current.xy, &gridmap);
+        let neighbours = get_neighbours(current.xy, gridmap);
         let target: UVec2 = params.target;
         for neighbour in neighbours {
             let neighbour_node: &mut AStarNode = params.movement_grid[neighbour.xy.x as usize]
@@ -163,7 +219,8 @@ pub fn calculate_a_star(
                 .get_mut(&neighbour.h.unwrap_or_default())
                 .unwrap();
             let tentative_g_score: i32 = current_node.g_score
-                + (inertia_based_inter_cell_movement(current, neighbour) * DISTANCE_FACTOR) as i32;
+                + (inertia_based_inter_cell_movement(current, neighbour) * DISTANCE_FACTOR) as i32
+                + gridmap.cost(neighbour.xy) as i32;
 
             if tentative_g_score < neighbour_node.g_score || neighbour_node.g_score == -1 {
                 neighbour_node.g_score = tentative_g_score;
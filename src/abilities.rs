@@ -0,0 +1,64 @@
+use crate::a_star::AStarParams;
+use crate::movable::{MoveCommand, MovementPath};
+use crate::ownable::{Owner, Selected};
+use crate::player_controller::{Ability, ContextMenuAction, DeselectEvent, PlayerInfo};
+use crate::research::TechTree;
+use crate::resource_collection::Collector;
+use crate::spawner::UnitInformation;
+use bevy::prelude::*;
+
+pub struct Abilities;
+impl Plugin for Abilities {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UnitActionRequest>()
+            .add_systems(FixedUpdate, perform_unit_actions);
+    }
+}
+/// Ask a unit to carry out a context menu action that doesn't go through a production queue
+#[derive(Event)]
+pub struct UnitActionRequest {
+    pub unit: Entity,
+    pub player: Entity,
+    pub action: ContextMenuAction,
+}
+
+fn perform_unit_actions(
+    mut commands: Commands,
+    mut unit_action_requests: EventReader<UnitActionRequest>,
+    units: Query<(&UnitInformation, &Owner, Has<Selected>)>,
+    player_infos: Query<&PlayerInfo>,
+    tech_tree: Res<TechTree>,
+    mut deselect_events: EventWriter<DeselectEvent>,
+) {
+    for request in unit_action_requests.read() {
+        let Ok((unit_information, owner, selected)) = units.get(request.unit) else {
+            continue;
+        };
+        let Ok(player_info) = player_infos.get(request.player) else {
+            continue;
+        };
+        if owner.player != request.player {
+            continue;
+        }
+        if let Some(reason) =
+            tech_tree.lock_reason(&request.action, &unit_information.unit_type, player_info)
+        {
+            println!("Can't perform {:?}: {}", request.action, reason);
+            continue;
+        }
+        match request.action {
+            ContextMenuAction::Ability(Ability::Stop) => {
+                commands
+                    .entity(request.unit)
+                    .remove::<(MoveCommand, MovementPath, AStarParams, Collector)>();
+            }
+            ContextMenuAction::SelfDestruct => {
+                commands.entity(request.unit).despawn_recursive();
+                if selected {
+                    deselect_events.send(DeselectEvent);
+                }
+            }
+            ContextMenuAction::Build(_) | ContextMenuAction::Research(_) => {}
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use crate::a_star::AStarParams;
+use crate::ai_script::{AiScript, Instruction, ScriptRunner};
+use crate::environment::MovementGrid;
+use crate::movable::{MoveCommand, MovementPath};
+use crate::ownable::Owner;
+use crate::player_controller::{ContextMenuAction, PlayerInfo};
+use crate::production::{production_cost, ProductionQueue, ProductionRequest, ProductionStarted};
+use crate::research::TechTree;
+use crate::resource_collection::{CollectionCommand, Collector};
+use crate::resources::{IncomeModifier, ResourceLevel, ResourceSource, ResourceStockpiles};
+use crate::save::{SavedAi, SavedSighting};
+use crate::spawner::{UnitInformation, UnitSpecifications, UnitType};
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub struct AiOpponent;
+impl Plugin for AiOpponent {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                apply_difficulty,
+                think.after(apply_difficulty),
+                pursue_attack_targets.after(think),
+            ),
+        );
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+impl Difficulty {
+    /// Time between two decisions of the AI
+    pub fn reaction_time(&self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(4000),
+            Difficulty::Normal => Duration::from_millis(2000),
+            Difficulty::Hard => Duration::from_millis(750),
+        }
+    }
+    /// Multiplier on everything the AI collects
+    pub fn resource_bonus(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+        }
+    }
+    fn mining_stations(&self) -> usize {
+        match self {
+            Difficulty::Easy => 2,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 5,
+        }
+    }
+    fn attack_strength(&self) -> usize {
+        match self {
+            Difficulty::Easy => 8,
+            Difficulty::Normal => 6,
+            Difficulty::Hard => 4,
+        }
+    }
+}
+#[derive(Component)]
+pub struct AiPlayer {
+    pub difficulty: Difficulty,
+    pub runner: ScriptRunner,
+    reaction_timer: Timer,
+    scout: Option<Entity>,
+    scout_waypoint: usize,
+    /// Enemy stations seen so far. They don't move, so the AI can go back for them later.
+    seen_stations: Vec<Sighting>,
+}
+impl AiPlayer {
+    pub fn new(difficulty: Difficulty) -> Self {
+        AiPlayer::with_script(
+            difficulty,
+            AiScript::built_in(difficulty.mining_stations(), difficulty.attack_strength()),
+        )
+    }
+    pub fn with_script(difficulty: Difficulty, script: AiScript) -> Self {
+        AiPlayer {
+            difficulty,
+            runner: ScriptRunner::new(script),
+            reaction_timer: Timer::new(difficulty.reaction_time(), TimerMode::Repeating),
+            scout: None,
+            scout_waypoint: 0,
+            seen_stations: Vec::new(),
+        }
+    }
+    /// The state to save, with units referred to by their index in the save
+    pub fn to_saved(&self, unit_index: impl Fn(Entity) -> Option<usize>) -> SavedAi {
+        let (step, performed): (usize, usize) = self.runner.progress();
+        SavedAi {
+            difficulty: self.difficulty,
+            script: self.runner.script.clone(),
+            step,
+            performed,
+            reaction_elapsed: self.reaction_timer.elapsed_secs(),
+            scout: self.scout.and_then(&unit_index),
+            scout_waypoint: self.scout_waypoint,
+            seen_stations: self
+                .seen_stations
+                .iter()
+                .filter_map(|station| {
+                    Some(SavedSighting {
+                        unit: unit_index(station.entity)?,
+                        position: station.position,
+                    })
+                })
+                .collect(),
+        }
+    }
+    pub fn from_saved(saved: &SavedAi, unit: impl Fn(usize) -> Option<Entity>) -> Self {
+        let mut ai_player: AiPlayer = AiPlayer::with_script(saved.difficulty, saved.script.clone());
+        ai_player.runner.resume(saved.step, saved.performed);
+        ai_player
+            .reaction_timer
+            .set_elapsed(Duration::from_secs_f32(saved.reaction_elapsed));
+        ai_player.scout = saved.scout.and_then(&unit);
+        ai_player.scout_waypoint = saved.scout_waypoint;
+        ai_player.seen_stations = saved
+            .seen_stations
+            .iter()
+            .filter_map(|station| {
+                Some(Sighting {
+                    entity: unit(station.unit)?,
+                    position: station.position,
+                    station: true,
+                })
+            })
+            .collect();
+        ai_player
+    }
+    /// The enemies in sight of its own units (position, vision range), and the stations it has
+    /// seen before and not found gone since. The AI knows no more than a player would.
+    fn known_enemies(&mut self, viewers: &[(Vec3, f32)], enemies: &[Sighting]) -> Vec<Sighting> {
+        let in_sight = |position: Vec3| {
+            viewers
+                .iter()
+                .any(|(viewer, range)| viewer.xz().distance(position.xz()) <= *range)
+        };
+        let mut known: Vec<Sighting> = enemies
+            .iter()
+            .filter(|enemy| in_sight(enemy.position))
+            .copied()
+            .collect();
+        let visible: Vec<Entity> = known.iter().map(|enemy| enemy.entity).collect();
+        // A station that should be in sight but isn't has been destroyed
+        self.seen_stations
+            .retain(|station| visible.contains(&station.entity) || !in_sight(station.position));
+        for enemy in known.iter().filter(|enemy| enemy.station) {
+            if !self
+                .seen_stations
+                .iter()
+                .any(|station| station.entity == enemy.entity)
+            {
+                self.seen_stations.push(*enemy);
+            }
+        }
+        known.extend(
+            self.seen_stations
+                .iter()
+                .filter(|station| !visible.contains(&station.entity)),
+        );
+        known
+    }
+}
+/// Where the AI has last seen an enemy unit
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Sighting {
+    entity: Entity,
+    position: Vec3,
+    station: bool,
+}
+/// Prefers the enemy's stations, otherwise goes for whatever is closest
+fn choose_target(origin: Vec3, known_enemies: &[Sighting]) -> Option<Sighting> {
+    known_enemies.iter().copied().min_by(|a, b| {
+        b.station.cmp(&a.station).then(
+            a.position
+                .distance(origin)
+                .total_cmp(&b.position.distance(origin)),
+        )
+    })
+}
+/// A unit sent after an enemy, following it for as long as it can see it. Its weapon opens fire
+/// once the enemy is in range.
+#[derive(Component)]
+pub struct AttackOrder {
+    pub target: Entity,
+    /// Where the target was when the attackers last set course
+    pub destination: Vec2,
+}
+/// How far a pursued target has to move before the attackers change course
+const REPATH_DISTANCE: f32 = 2.0;
+struct OwnUnit {
+    entity: Entity,
+    position: Vec3,
+    unit_type: UnitType,
+    vision_range: f32,
+    idle: bool,
+    attacking: bool,
+    collector: bool,
+    collecting: bool,
+    queued: Vec<ContextMenuAction>,
+}
+
+fn apply_difficulty(
+    mut commands: Commands,
+    ai_players: Query<(Entity, &AiPlayer), Added<AiPlayer>>,
+) {
+    for (entity, ai_player) in ai_players.iter() {
+        commands
+            .entity(entity)
+            .insert(IncomeModifier(ai_player.difficulty.resource_bonus()));
+    }
+}
+
+fn send_move(commands: &mut Commands, entity: Entity, target: Vec2) {
+    commands.entity(entity).remove::<MoveCommand>();
+    commands.entity(entity).insert(MoveCommand { target });
+}
+
+pub fn think(
+    time: Res<Time>,
+    mut ai_players: Query<(Entity, &mut AiPlayer, &PlayerInfo, &ResourceStockpiles)>,
+    units: Query<(
+        Entity,
+        &Transform,
+        &UnitInformation,
+        &Owner,
+        Has<Collector>,
+        Has<MoveCommand>,
+        Has<MovementPath>,
+        Has<AStarParams>,
+        Has<AttackOrder>,
+        Option<&ProductionQueue>,
+    )>,
+    resource_sources: Query<(Entity, &Transform, &ResourceLevel), With<ResourceSource>>,
+    unit_specifications: Res<UnitSpecifications>,
+    tech_tree: Res<TechTree>,
+    movement_grid: Res<MovementGrid>,
+    mut production_requests: EventWriter<ProductionRequest>,
+    mut production_started: EventReader<ProductionStarted>,
+    mut collection_commands: EventWriter<CollectionCommand>,
+    mut commands: Commands,
+) {
+    // Read every tick, so no confirmation is missed while the AI isn't deciding
+    let started: Vec<ProductionStarted> = production_started.read().cloned().collect();
+    for (player_entity, mut ai_player, player_info, stockpiles) in ai_players.iter_mut() {
+        // Only what was actually queued counts towards the build order
+        for started in started
+            .iter()
+            .filter(|started| started.player == player_entity)
+        {
+            ai_player.runner.report_performed(&started.action);
+        }
+        if !ai_player.reaction_timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let mut own_units: Vec<OwnUnit> = Vec::new();
+        let mut enemies: Vec<Sighting> = Vec::new();
+        for (
+            entity,
+            transform,
+            unit_information,
+            owner,
+            collecting,
+            moving,
+            has_path,
+            pathing,
+            attacking,
+            production_queue,
+        ) in units.iter()
+        {
+            if owner.player == player_entity {
+                own_units.push(OwnUnit {
+                    entity,
+                    position: transform.translation,
+                    unit_type: unit_information.unit_type.clone(),
+                    vision_range: unit_information.stats.vision_range(),
+                    idle: !moving && !has_path && !pathing,
+                    attacking,
+                    collector: unit_information.stats.is_collector(),
+                    collecting,
+                    queued: production_queue.map_or(Vec::new(), |queue| {
+                        queue.items.iter().map(|item| item.action.clone()).collect()
+                    }),
+                });
+            } else {
+                enemies.push(Sighting {
+                    entity,
+                    position: transform.translation,
+                    station: unit_information.unit_type == UnitType::Spacestation,
+                });
+            }
+        }
+        let mut unit_counts: HashMap<UnitType, usize> = HashMap::new();
+        for unit in &own_units {
+            *unit_counts.entry(unit.unit_type.clone()).or_insert(0) += 1;
+            // Units still in production count as well, so they aren't ordered twice
+            for action in &unit.queued {
+                if let ContextMenuAction::Build(unit_type) = action {
+                    *unit_counts.entry(unit_type.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+        let viewers: Vec<(Vec3, f32)> = own_units
+            .iter()
+            .map(|unit| (unit.position, unit.vision_range))
+            .collect();
+        let known_enemies: Vec<Sighting> = ai_player.known_enemies(&viewers, &enemies);
+        let cruisers: Vec<&OwnUnit> = own_units
+            .iter()
+            .filter(|unit| unit.unit_type == UnitType::Cruiser)
+            .collect();
+        let instructions: Vec<Instruction> =
+            ai_player.runner.next_instructions(stockpiles, &unit_counts);
+        let mut attempted: bool = false;
+        for instruction in instructions {
+            match instruction {
+                // Only the most important action gets a chance to spend resources
+                Instruction::Perform(action) => {
+                    if attempted {
+                        continue;
+                    }
+                    attempted = true;
+                    let Some((cost, _)) = production_cost(
+                        &action,
+                        &player_info.civilisation,
+                        &unit_specifications,
+                        &tech_tree,
+                    ) else {
+                        continue;
+                    };
+                    let Some(producer) = own_units
+                        .iter()
+                        .filter(|unit| {
+                            tech_tree
+                                .lock_reason(&action, &unit.unit_type, player_info)
+                                .is_none()
+                        })
+                        .min_by_key(|unit| unit.queued.len())
+                    else {
+                        continue;
+                    };
+                    if stockpiles.can_afford(cost) {
+                        production_requests.send(ProductionRequest {
+                            producer: producer.entity,
+                            player: player_entity,
+                            action: action.clone(),
+                        });
+                    }
+                }
+                Instruction::Collect(resource) => {
+                    for collector in own_units
+                        .iter()
+                        .filter(|unit| unit.collector && !unit.collecting)
+                    {
+                        let closest_source = resource_sources
+                            .iter()
+                            .filter(|(_, _, resource_level)| {
+                                resource_level.resource_type == resource
+                                    && resource_level.resource_amount > 0
+                            })
+                            .min_by(|(_, a, _), (_, b, _)| {
+                                a.translation
+                                    .distance(collector.position)
+                                    .total_cmp(&b.translation.distance(collector.position))
+                            });
+                        if let Some((source_entity, source_transform, _)) = closest_source {
+                            let approach: Vec3 = source_transform.translation
+                                + (collector.position - source_transform.translation)
+                                    .normalize_or_zero();
+                            send_move(&mut commands, collector.entity, approach.xz());
+                            collection_commands.send(CollectionCommand {
+                                collector: collector.entity,
+                                resource_entity: source_entity,
+                                player: player_entity,
+                            });
+                            ai_player.runner.report_collecting(resource);
+                        }
+                    }
+                }
+                Instruction::Scout => {
+                    if !cruisers
+                        .iter()
+                        .any(|unit| Some(unit.entity) == ai_player.scout)
+                    {
+                        ai_player.scout = cruisers.first().map(|unit| unit.entity);
+                    }
+                    let half_extent: Vec2 = Vec2::new(
+                        movement_grid.settings.grid_width as f32,
+                        movement_grid.settings.grid_height as f32,
+                    ) * movement_grid.settings.cell_size
+                        * 0.4;
+                    let waypoints: [Vec2; 4] = [
+                        Vec2::new(half_extent.x, half_extent.y),
+                        Vec2::new(-half_extent.x, half_extent.y),
+                        Vec2::new(-half_extent.x, -half_extent.y),
+                        Vec2::new(half_extent.x, -half_extent.y),
+                    ];
+                    if let Some(scout) = cruisers
+                        .iter()
+                        .find(|unit| Some(unit.entity) == ai_player.scout && unit.idle)
+                    {
+                        send_move(
+                            &mut commands,
+                            scout.entity,
+                            waypoints[ai_player.scout_waypoint],
+                        );
+                        ai_player.scout_waypoint = (ai_player.scout_waypoint + 1) % waypoints.len();
+                    }
+                }
+                Instruction::Attack(strength) => {
+                    let attackers: Vec<&&OwnUnit> = cruisers
+                        .iter()
+                        .filter(|unit| {
+                            Some(unit.entity) != ai_player.scout && unit.idle && !unit.attacking
+                        })
+                        .collect();
+                    if attackers.is_empty() || attackers.len() < strength {
+                        continue;
+                    }
+                    let Some(target) = choose_target(attackers[0].position, &known_enemies) else {
+                        continue;
+                    };
+                    for attacker in attackers {
+                        send_move(&mut commands, attacker.entity, target.position.xz());
+                        commands.entity(attacker.entity).insert(AttackOrder {
+                            target: target.entity,
+                            destination: target.position.xz(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn pursue_attack_targets(
+    mut commands: Commands,
+    mut attackers: Query<(Entity, &Transform, &UnitInformation, &mut AttackOrder)>,
+    targets: Query<&Transform, With<Owner>>,
+) {
+    for (entity, transform, unit_information, mut attack_order) in attackers.iter_mut() {
+        let Ok(target) = targets.get(attack_order.target) else {
+            commands.entity(entity).remove::<AttackOrder>();
+            continue;
+        };
+        let position: Vec2 = target.translation.xz();
+        // Out of sight, the attackers keep heading for where the target was last seen
+        if transform.translation.xz().distance(position) > unit_information.stats.vision_range() {
+            continue;
+        }
+        if position.distance(attack_order.destination) > REPATH_DISTANCE {
+            attack_order.destination = position;
+            send_move(&mut commands, entity, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sighting(index: u32, position: Vec3, station: bool) -> Sighting {
+        Sighting {
+            entity: Entity::from_raw(index),
+            position,
+            station,
+        }
+    }
+
+    #[test]
+    fn unseen_enemies_are_not_targeted() {
+        let mut ai_player: AiPlayer = AiPlayer::new(Difficulty::Normal);
+        let cruiser: Sighting = sighting(1, Vec3::new(10.0, 2.0, 0.0), false);
+        let station: Sighting = sighting(2, Vec3::new(100.0, 2.0, 0.0), true);
+        let viewers: [(Vec3, f32); 1] = [(Vec3::new(0.0, 2.0, 0.0), 15.0)];
+        let known: Vec<Sighting> = ai_player.known_enemies(&viewers, &[cruiser, station]);
+        assert_eq!(known, vec![cruiser]);
+        // Stations are preferred, but only once they have been found
+        assert_eq!(choose_target(Vec3::ZERO, &known), Some(cruiser));
+        assert_eq!(
+            choose_target(Vec3::ZERO, &[cruiser, station]),
+            Some(station)
+        );
+        assert_eq!(ai_player.known_enemies(&[], &[cruiser, station]), vec![]);
+    }
+
+    #[test]
+    fn seen_stations_are_remembered_until_found_gone() {
+        let mut ai_player: AiPlayer = AiPlayer::new(Difficulty::Normal);
+        let station: Sighting = sighting(2, Vec3::new(100.0, 2.0, 0.0), true);
+        let scout: [(Vec3, f32); 1] = [(Vec3::new(95.0, 2.0, 0.0), 15.0)];
+        assert_eq!(ai_player.known_enemies(&scout, &[station]), vec![station]);
+        // The scout moved on
+        assert_eq!(ai_player.known_enemies(&[], &[station]), vec![station]);
+        // Back again, but the station has been destroyed in the meantime
+        assert_eq!(ai_player.known_enemies(&scout, &[]), vec![]);
+        assert_eq!(ai_player.known_enemies(&[], &[station]), vec![]);
+    }
+}
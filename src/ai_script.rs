@@ -0,0 +1,567 @@
+use crate::civilisation::CivilisationBoniMap;
+use crate::player_controller::{ContextMenuAction, PlayerInfo};
+use crate::production::{production_cost, ProductionItem, ProductionQueue};
+use crate::research::TechTree;
+use crate::resource_collection::collection_rate;
+use crate::resources::{ResourceStockpiles, ResourceType};
+use crate::spawner::{UnitSpecifications, UnitType};
+use bevy::{
+    ecs::{entity::Entity, world::World},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, fs};
+
+/// A step of the opening. Steps are executed strictly in order.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BuildStep {
+    /// Perform a context menu action the given number of times
+    Perform {
+        action: ContextMenuAction,
+        count: usize,
+    },
+    /// Send all idle collectors to the closest source of the resource. Waits until there is at
+    /// least one.
+    CollectNearest(ResourceType),
+    /// Block until the stockpile holds at least the given amount
+    WaitFor { resource: ResourceType, amount: i32 },
+}
+/// Standing orders, evaluated in order of priority once the opening is done
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Behaviour {
+    KeepCollecting(ResourceType),
+    Maintain {
+        action: ContextMenuAction,
+        count: usize,
+    },
+    Produce(ContextMenuAction),
+    Scout,
+    AttackWith(usize),
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AiScript {
+    pub name: String,
+    pub build_order: Vec<BuildStep>,
+    pub behaviours: Vec<Behaviour>,
+}
+/// What the AI should do during the current decision
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Perform(ContextMenuAction),
+    Collect(ResourceType),
+    Scout,
+    Attack(usize),
+}
+
+impl AiScript {
+    pub fn load(path: &str) -> Result<AiScript, Box<dyn Error>> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+    /// Loads the scripts in order, with `None` in place of any that can't be read
+    pub fn load_all(paths: &[String]) -> Vec<Option<AiScript>> {
+        paths
+            .iter()
+            .map(|path| match AiScript::load(path) {
+                Ok(script) => Some(script),
+                Err(e) => {
+                    println!("Could not load AI script {}: {}", path, e);
+                    None
+                }
+            })
+            .collect()
+    }
+    /// The script the AI falls back to if none is given
+    pub fn built_in(mining_stations: usize, attack_strength: usize) -> AiScript {
+        AiScript {
+            name: "Built-in".into(),
+            build_order: Vec::new(),
+            behaviours: vec![
+                Behaviour::KeepCollecting(ResourceType::Plotanium),
+                Behaviour::Maintain {
+                    action: ContextMenuAction::Build(UnitType::MiningStation),
+                    count: mining_stations,
+                },
+                Behaviour::Produce(ContextMenuAction::Build(UnitType::Cruiser)),
+                Behaviour::Scout,
+                Behaviour::AttackWith(attack_strength),
+            ],
+        }
+    }
+}
+
+pub struct ScriptRunner {
+    pub script: AiScript,
+    step: usize,
+    performed: usize,
+}
+impl ScriptRunner {
+    pub fn new(script: AiScript) -> Self {
+        ScriptRunner {
+            script,
+            step: 0,
+            performed: 0,
+        }
+    }
+    pub fn completed_steps(&self) -> usize {
+        self.step
+    }
+    /// The current step, and how often its instruction was carried out so far
+    pub fn progress(&self) -> (usize, usize) {
+        (self.step, self.performed)
+    }
+    pub fn resume(&mut self, step: usize, performed: usize) {
+        self.step = step;
+        self.performed = performed;
+    }
+    fn advance(&mut self) {
+        self.step += 1;
+        self.performed = 0;
+    }
+    /// Instructions for the current decision, ordered by priority.
+    /// Only the first `Perform` is meant to be attempted, so that lower priorities can't spend
+    /// resources a higher priority is saving up for.
+    pub fn next_instructions(
+        &mut self,
+        stockpiles: &ResourceStockpiles,
+        unit_counts: &HashMap<UnitType, usize>,
+    ) -> Vec<Instruction> {
+        let mut instructions: Vec<Instruction> = Vec::new();
+        while let Some(step) = self.script.build_order.get(self.step) {
+            match step {
+                BuildStep::Perform { action, count } => {
+                    if self.performed >= *count {
+                        self.advance();
+                        continue;
+                    }
+                    instructions.push(Instruction::Perform(action.clone()));
+                    return instructions;
+                }
+                // Pending until a collector was actually sent, which may have to wait until the
+                // first one is built
+                BuildStep::CollectNearest(resource) => {
+                    instructions.push(Instruction::Collect(*resource));
+                    return instructions;
+                }
+                BuildStep::WaitFor { resource, amount } => {
+                    if *stockpiles.get(resource).unwrap_or(&0) < *amount {
+                        return instructions;
+                    }
+                    self.advance();
+                }
+            }
+        }
+        for behaviour in &self.script.behaviours {
+            match behaviour {
+                Behaviour::KeepCollecting(resource) => {
+                    instructions.push(Instruction::Collect(*resource))
+                }
+                Behaviour::Maintain { action, count } => {
+                    if let ContextMenuAction::Build(unit_type) = action {
+                        if *unit_counts.get(unit_type).unwrap_or(&0) < *count {
+                            instructions.push(Instruction::Perform(action.clone()));
+                        }
+                    }
+                }
+                Behaviour::Produce(action) => {
+                    instructions.push(Instruction::Perform(action.clone()))
+                }
+                Behaviour::Scout => instructions.push(Instruction::Scout),
+                Behaviour::AttackWith(strength) => {
+                    instructions.push(Instruction::Attack(*strength))
+                }
+            }
+        }
+        instructions
+    }
+    /// Tell the runner an action it asked for has been carried out
+    pub fn report_performed(&mut self, action: &ContextMenuAction) {
+        if let Some(BuildStep::Perform {
+            action: step_action,
+            ..
+        }) = self.script.build_order.get(self.step)
+        {
+            if step_action == action {
+                self.performed += 1;
+            }
+        }
+    }
+    /// Tell the runner an idle collector was sent after a resource
+    pub fn report_collecting(&mut self, resource: ResourceType) {
+        if self.script.build_order.get(self.step) == Some(&BuildStep::CollectNearest(resource)) {
+            self.advance();
+        }
+    }
+}
+
+pub struct BuildOrderReport {
+    pub name: String,
+    pub seconds: u32,
+    pub completed_steps: usize,
+    pub total_steps: usize,
+    pub stockpiles: HashMap<ResourceType, i32>,
+    pub units: HashMap<UnitType, usize>,
+    pub timeline: Vec<(u32, String)>,
+}
+impl fmt::Display for BuildOrderReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} after {}:{:02}",
+            self.name,
+            self.seconds / 60,
+            self.seconds % 60
+        )?;
+        writeln!(
+            f,
+            "Build order steps completed: {}/{}",
+            self.completed_steps, self.total_steps
+        )?;
+        for (resource_type, amount) in &self.stockpiles {
+            writeln!(f, "{:?}: {}", resource_type, amount)?;
+        }
+        for (unit_type, count) in &self.units {
+            writeln!(f, "{}: {}", unit_type, count)?;
+        }
+        for (second, event) in &self.timeline {
+            writeln!(f, "{:>3}:{:02} {}", second / 60, second % 60, event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a script against a simplified economy without a world: collectors mine from the moment
+/// they are ordered to, producers work through their queues in real time and the AI decides
+/// once per second.
+pub fn simulate_build_order(
+    script: AiScript,
+    player_info: &PlayerInfo,
+    starting_units: &[UnitType],
+    starting_stockpiles: ResourceStockpiles,
+    unit_specifications: &UnitSpecifications,
+    civilisation_boni_map: &CivilisationBoniMap,
+    tech_tree: &TechTree,
+    minutes: u32,
+) -> BuildOrderReport {
+    let total_steps: usize = script.build_order.len();
+    // Owns the queued items, like the player entity in a match
+    let player: Entity = World::new().spawn_empty().id();
+    let mut runner: ScriptRunner = ScriptRunner::new(script);
+    let mut player_info: PlayerInfo = player_info.clone();
+    let mut stockpiles: ResourceStockpiles = starting_stockpiles;
+    let mut units: Vec<(UnitType, Option<ResourceType>, ProductionQueue)> = starting_units
+        .iter()
+        .map(|unit_type| (unit_type.clone(), None, ProductionQueue::default()))
+        .collect();
+    let mut timeline: Vec<(u32, String)> = Vec::new();
+    let civilisation_boni = &civilisation_boni_map.map[&player_info.civilisation];
+    for second in 0..minutes * 60 {
+        for (unit_type, collecting, _) in &units {
+            if let (Some(resource), Some(unit_specification)) = (
+                collecting,
+                unit_specifications
+                    .unit_specifications
+                    .get(&(player_info.civilisation.clone(), unit_type.clone())),
+            ) {
+                let mut stats = unit_specification.base_stats.clone();
+                for (_, modifier) in civilisation_boni
+                    .combat_boni
+                    .stat_modifiers
+                    .iter()
+                    .filter(|(boosted, _)| boosted == unit_type)
+                {
+                    modifier.apply(&mut stats);
+                }
+                for modifier in tech_tree.stat_modifiers(&player_info, unit_type) {
+                    modifier.apply(&mut stats);
+                }
+                let rate: f32 = collection_rate(&stats, *resource, civilisation_boni, 1.0);
+                if let Some(stockpile) = stockpiles.0.get_mut(resource) {
+                    *stockpile += rate as i32;
+                }
+            }
+        }
+        let mut finished: Vec<ContextMenuAction> = Vec::new();
+        for (_, _, queue) in units.iter_mut() {
+            finished.extend(queue.tick(1.0).map(|item| item.action));
+        }
+        for action in finished {
+            match action {
+                ContextMenuAction::Build(unit_type) => {
+                    timeline.push((second, format!("Built {}", unit_type)));
+                    units.push((unit_type, None, ProductionQueue::default()));
+                }
+                ContextMenuAction::Research(tech) => {
+                    tech_tree.complete_research(&tech, &mut player_info);
+                    timeline.push((second, format!("Researched {}", tech_tree.name(&tech))));
+                }
+                ContextMenuAction::Ability(_) | ContextMenuAction::SelfDestruct => {}
+            }
+        }
+        let mut unit_counts: HashMap<UnitType, usize> = HashMap::new();
+        for (unit_type, _, queue) in &units {
+            *unit_counts.entry(unit_type.clone()).or_insert(0) += 1;
+            for item in &queue.items {
+                if let ContextMenuAction::Build(queued) = &item.action {
+                    *unit_counts.entry(queued.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut attempted: bool = false;
+        for instruction in runner.next_instructions(&stockpiles, &unit_counts) {
+            match instruction {
+                Instruction::Perform(action) => {
+                    if attempted {
+                        continue;
+                    }
+                    attempted = true;
+                    let Some((cost, duration)) = production_cost(
+                        &action,
+                        &player_info.civilisation,
+                        unit_specifications,
+                        tech_tree,
+                    ) else {
+                        continue;
+                    };
+                    let Some((_, _, queue)) = units
+                        .iter_mut()
+                        .filter(|(unit_type, _, _)| {
+                            tech_tree
+                                .lock_reason(&action, unit_type, &player_info)
+                                .is_none()
+                        })
+                        .min_by_key(|(_, _, queue)| queue.items.len())
+                    else {
+                        continue;
+                    };
+                    if stockpiles.try_pay(cost) {
+                        if let ContextMenuAction::Research(tech) = &action {
+                            player_info.researching.insert(tech.clone());
+                        }
+                        queue.items.push_back(ProductionItem {
+                            action: action.clone(),
+                            player,
+                            remaining: duration,
+                            total: duration,
+                        });
+                        timeline.push((second, format!("Queued {:?}", action)));
+                        runner.report_performed(&action);
+                    }
+                }
+                Instruction::Collect(resource) => {
+                    for (unit_type, collecting, _) in units.iter_mut() {
+                        let is_collector: bool = unit_specifications
+                            .unit_specifications
+                            .get(&(player_info.civilisation.clone(), unit_type.clone()))
+                            .is_some_and(|specification| specification.base_stats.is_collector());
+                        if is_collector && collecting.is_none() {
+                            *collecting = Some(resource);
+                            timeline
+                                .push((second, format!("{} collecting {:?}", unit_type, resource)));
+                            runner.report_collecting(resource);
+                        }
+                    }
+                }
+                Instruction::Scout | Instruction::Attack(_) => {}
+            }
+        }
+    }
+    let mut unit_counts: HashMap<UnitType, usize> = HashMap::new();
+    for (unit_type, _, _) in &units {
+        *unit_counts.entry(unit_type.clone()).or_insert(0) += 1;
+    }
+    BuildOrderReport {
+        name: runner.script.name.clone(),
+        seconds: minutes * 60,
+        completed_steps: runner.completed_steps(),
+        total_steps,
+        stockpiles: stockpiles.0,
+        units: unit_counts,
+        timeline,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::civilisation::{CivilisationBoni, CombatBoni, EcoBoni};
+    use crate::player_controller::{Civilisation, TechLevel};
+    use crate::research::TechTree;
+    use crate::spawner::{UnitSpecification, UnitStat, UnitStats};
+    use crate::utils::ShapeTypeSerializable;
+    use bevy::math::Vec3;
+    use bevy::utils::HashSet;
+    use bevy_rapier3d::rapier::prelude::ShapeType;
+
+    fn civilisation() -> Civilisation {
+        Civilisation("Test".into())
+    }
+    fn unit_specification(
+        base_stats: Vec<UnitStat>,
+        cost: f32,
+        build_time: f32,
+    ) -> UnitSpecification {
+        UnitSpecification {
+            file_path: String::new(),
+            scene: String::new(),
+            icon_path: String::new(),
+            unit_name: String::new(),
+            movable: true,
+            shape: ShapeTypeSerializable(ShapeType::Ball),
+            dimensions: Vec3::ONE,
+            prescaling: 1.0,
+            base_stats: UnitStats(base_stats),
+            unit_info: String::new(),
+            unit_cost: [(ResourceType::Plotanium, cost)].into_iter().collect(),
+            build_time,
+        }
+    }
+    fn player_info() -> PlayerInfo {
+        PlayerInfo {
+            civilisation: civilisation(),
+            tech_level: TechLevel::L0,
+            context_menu_actions: HashMap::from([(
+                UnitType::Spacestation,
+                vec![ContextMenuAction::Build(UnitType::MiningStation)],
+            )]),
+            researched: HashSet::new(),
+            researching: HashSet::new(),
+        }
+    }
+    fn stockpiles(plotanium: i32) -> ResourceStockpiles {
+        ResourceStockpiles(HashMap::from([(ResourceType::Plotanium, plotanium)]))
+    }
+
+    #[test]
+    fn the_build_order_comes_before_the_behaviours() {
+        let mut runner: ScriptRunner = ScriptRunner::new(AiScript {
+            name: "Test".into(),
+            build_order: vec![
+                BuildStep::WaitFor {
+                    resource: ResourceType::Plotanium,
+                    amount: 50,
+                },
+                BuildStep::Perform {
+                    action: ContextMenuAction::Build(UnitType::Cruiser),
+                    count: 2,
+                },
+            ],
+            behaviours: vec![Behaviour::Scout],
+        });
+        let no_units: HashMap<UnitType, usize> = HashMap::new();
+        assert!(runner
+            .next_instructions(&stockpiles(10), &no_units)
+            .is_empty());
+        let cruiser: Instruction =
+            Instruction::Perform(ContextMenuAction::Build(UnitType::Cruiser));
+        for _ in 0..2 {
+            assert_eq!(
+                runner.next_instructions(&stockpiles(50), &no_units),
+                vec![cruiser.clone()]
+            );
+            runner.report_performed(&ContextMenuAction::Build(UnitType::Cruiser));
+        }
+        assert_eq!(
+            runner.next_instructions(&stockpiles(0), &no_units),
+            vec![Instruction::Scout]
+        );
+        assert_eq!(runner.completed_steps(), 2);
+    }
+
+    #[test]
+    fn collecting_waits_for_an_idle_collector() {
+        let mut runner: ScriptRunner = ScriptRunner::new(AiScript {
+            name: "Test".into(),
+            build_order: vec![
+                BuildStep::CollectNearest(ResourceType::Plotanium),
+                BuildStep::Perform {
+                    action: ContextMenuAction::Build(UnitType::Cruiser),
+                    count: 1,
+                },
+            ],
+            behaviours: Vec::new(),
+        });
+        let no_units: HashMap<UnitType, usize> = HashMap::new();
+        let collect: Instruction = Instruction::Collect(ResourceType::Plotanium);
+        // Nobody took the order yet
+        for _ in 0..2 {
+            assert_eq!(
+                runner.next_instructions(&stockpiles(0), &no_units),
+                vec![collect.clone()]
+            );
+        }
+        assert_eq!(runner.completed_steps(), 0);
+        runner.report_collecting(ResourceType::Plotanium);
+        assert_eq!(
+            runner.next_instructions(&stockpiles(0), &no_units),
+            vec![Instruction::Perform(ContextMenuAction::Build(
+                UnitType::Cruiser
+            ))]
+        );
+        assert_eq!(runner.completed_steps(), 1);
+    }
+
+    #[test]
+    fn build_orders_are_timed_against_the_economy() {
+        let unit_specifications: UnitSpecifications = UnitSpecifications {
+            unit_specifications: [
+                (
+                    (civilisation(), UnitType::Spacestation),
+                    unit_specification(Vec::new(), 100.0, 45.0),
+                ),
+                (
+                    (civilisation(), UnitType::MiningStation),
+                    unit_specification(vec![UnitStat::BaseMiningRate(10.0)], 50.0, 5.0),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let civilisation_boni_map: CivilisationBoniMap = CivilisationBoniMap {
+            map: [(
+                civilisation(),
+                CivilisationBoni {
+                    eco_boni: EcoBoni {
+                        resource_boni: Default::default(),
+                    },
+                    combat_boni: CombatBoni::default(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let script: AiScript = AiScript {
+            name: "One miner".into(),
+            build_order: vec![BuildStep::Perform {
+                action: ContextMenuAction::Build(UnitType::MiningStation),
+                count: 1,
+            }],
+            behaviours: vec![Behaviour::KeepCollecting(ResourceType::Plotanium)],
+        };
+        let report: BuildOrderReport = simulate_build_order(
+            script,
+            &player_info(),
+            &[UnitType::Spacestation],
+            stockpiles(50),
+            &unit_specifications,
+            &civilisation_boni_map,
+            &TechTree {
+                techs: Default::default(),
+            },
+            1,
+        );
+        assert_eq!(
+            report.timeline,
+            vec![
+                (0, "Queued Build(MiningStation)".to_string()),
+                (5, "Built Mining Station".to_string()),
+                (5, "Mining Station collecting Plotanium".to_string()),
+            ]
+        );
+        assert_eq!(report.completed_steps, 1);
+        assert_eq!(report.total_steps, 1);
+        // Mining from the sixth second to the end of the minute
+        assert_eq!(report.stockpiles[&ResourceType::Plotanium], 54 * 10);
+        assert_eq!(report.units[&UnitType::MiningStation], 1);
+        assert_eq!(report.units[&UnitType::Spacestation], 1);
+    }
+}
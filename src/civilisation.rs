@@ -1,38 +1,195 @@
 use std::collections::HashMap;
+use std::{error::Error, fs};
 
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
+use serde::{Deserialize, Serialize};
 
-use crate::{player_controller::Civilisation, resources::ResourceType};
+use crate::{
+    player_controller::{Civilisation, ContextMenuAction, PlayerInfo, TechLevel},
+    research::{StatModifier, TechTree},
+    resources::ResourceType,
+    spawner::{UnitSpecification, UnitSpecifications, UnitType},
+};
 
+pub const CIVILISATIONS_PATH: &str = "./assets/civilisations";
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct EcoBoni {
     pub resource_boni: HashMap<ResourceType, f32>,
 }
+/// Applied to every unit of the civilisation on top of its base stats
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CombatBoni {
+    pub stat_modifiers: Vec<(UnitType, StatModifier)>,
+}
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CivilisationBoni {
     pub eco_boni: EcoBoni,
+    #[serde(default)]
+    pub combat_boni: CombatBoni,
 }
 #[derive(Resource)]
 pub struct CivilisationBoniMap {
     pub map: HashMap<Civilisation, CivilisationBoni>,
 }
+/// An entry of a unit's context menu
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ContextMenuEntry {
+    pub action: ContextMenuAction,
+    #[serde(default)]
+    pub hotkey: Option<KeyCode>,
+    /// Overrides the icon of the unit or tech behind the action
+    #[serde(default)]
+    pub icon_path: Option<String>,
+}
+/// Everything that makes up a civilisation, as read from its file in `CIVILISATIONS_PATH`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CivilisationDefinition {
+    pub name: String,
+    pub boni: CivilisationBoni,
+    /// The units this civilisation can field, including their models and thumbnails
+    pub units: HashMap<UnitType, UnitSpecification>,
+    /// Context menu of each unit type, including entries research has yet to unlock
+    pub context_menu: HashMap<UnitType, Vec<ContextMenuEntry>>,
+}
+#[derive(Resource, Clone)]
+pub struct Civilisations {
+    pub definitions: Vec<CivilisationDefinition>,
+}
 
-fn setup_civilisations(mut commands: Commands) {
-    let mut civ_boni_map = CivilisationBoniMap {
-        map: HashMap::new(),
-    };
-    civ_boni_map.map.insert(
-        Civilisation::Greek,
-        CivilisationBoni {
-            eco_boni: EcoBoni {
-                resource_boni: HashMap::from_iter([(ResourceType::Plotanium, 5.0)]),
-            },
-        },
-    );
-    commands.insert_resource(civ_boni_map);
+impl Civilisations {
+    /// Reads every civilisation file in the directory
+    pub fn load(path: &str) -> Result<Civilisations, Box<dyn Error>> {
+        let mut definitions: Vec<CivilisationDefinition> = Vec::new();
+        let mut paths: Vec<_> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let definition: CivilisationDefinition = ron::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            definitions.push(definition);
+        }
+        Ok(Civilisations { definitions })
+    }
+    pub fn get(&self, civilisation: &Civilisation) -> Option<&CivilisationDefinition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == civilisation.0)
+    }
+    pub fn context_menu(
+        &self,
+        civilisation: &Civilisation,
+        unit_type: &UnitType,
+    ) -> &[ContextMenuEntry] {
+        self.get(civilisation)
+            .and_then(|definition| definition.context_menu.get(unit_type))
+            .map_or(&[], |entries| entries.as_slice())
+    }
+    /// How a player of the civilisation starts out, before any research
+    pub fn player_info(&self, tech_tree: &TechTree, civilisation: &Civilisation) -> PlayerInfo {
+        // Entries that some tech unlocks only become available through research
+        let context_menu_actions = match self.get(civilisation) {
+            Some(definition) => definition
+                .context_menu
+                .iter()
+                .map(|(unit_type, entries)| {
+                    let actions: Vec<ContextMenuAction> = entries
+                        .iter()
+                        .filter(|entry| tech_tree.unlocked_by(&entry.action, unit_type).is_empty())
+                        .map(|entry| entry.action.clone())
+                        .collect();
+                    (unit_type.clone(), actions)
+                })
+                .collect(),
+            None => {
+                println!("Unknown civilisation {}", civilisation);
+                bevy::utils::HashMap::new()
+            }
+        };
+        PlayerInfo {
+            civilisation: civilisation.clone(),
+            tech_level: TechLevel::L0,
+            context_menu_actions,
+            researched: HashSet::new(),
+            researching: HashSet::new(),
+        }
+    }
+    pub fn civilisation_boni(&self) -> CivilisationBoniMap {
+        CivilisationBoniMap {
+            map: self
+                .definitions
+                .iter()
+                .map(|definition| {
+                    (
+                        Civilisation(definition.name.clone()),
+                        definition.boni.clone(),
+                    )
+                })
+                .collect(),
+        }
+    }
+    pub fn unit_specifications(&self) -> UnitSpecifications {
+        let mut unit_specifications: UnitSpecifications = UnitSpecifications {
+            unit_specifications: Default::default(),
+        };
+        for definition in &self.definitions {
+            for (unit_type, unit_specification) in &definition.units {
+                unit_specifications.unit_specifications.insert(
+                    (Civilisation(definition.name.clone()), unit_type.clone()),
+                    unit_specification.clone(),
+                );
+            }
+        }
+        unit_specifications
+    }
 }
+
 pub struct CivilisationPlugin;
 
 impl Plugin for CivilisationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_civilisations);
+        // Loaded right away, so that every startup system can rely on the civilisations
+        let civilisations: Civilisations = match Civilisations::load(CIVILISATIONS_PATH) {
+            Ok(civilisations) => civilisations,
+            Err(e) => {
+                println!("Could not load civilisations {}: {}", CIVILISATIONS_PATH, e);
+                Civilisations {
+                    definitions: Vec::new(),
+                }
+            }
+        };
+        app.insert_resource(civilisations.civilisation_boni())
+            .insert_resource(civilisations.unit_specifications())
+            .insert_resource(civilisations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn every_civilisation_brings_its_own_models() {
+        let civilisations: Civilisations = Civilisations::load(CIVILISATIONS_PATH).unwrap();
+        assert!(civilisations.definitions.len() > 1);
+        let mut models: HashSet<&str> = HashSet::new();
+        for definition in &civilisations.definitions {
+            for unit_specification in definition.units.values() {
+                let model: &str = &unit_specification.file_path;
+                assert!(Path::new(model).exists(), "{} is missing", model);
+                // Thumbnails go through the asset server, so they are relative to the assets
+                let icon: &str = &unit_specification.icon_path;
+                assert!(
+                    Path::new("./assets").join(icon).exists(),
+                    "{} is missing",
+                    icon
+                );
+                assert!(models.insert(model), "{} is used twice", model);
+            }
+        }
     }
 }
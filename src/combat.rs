@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+
+use crate::ownable::Owner;
+use crate::player_controller::Alert;
+use crate::spawner::{Health, Shield, UnitInformation, Weapon};
+
+pub struct Combat;
+impl Plugin for Combat {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, fire_weapons);
+    }
+}
+
+/// Every armed unit hits the closest enemy within range, shields first. The hits of a tick are
+/// added up before any of them land, so it doesn't matter which unit fires first.
+fn fire_weapons(
+    mut commands: Commands,
+    time: Res<Time>,
+    armed: Query<(&Transform, &Owner, &Weapon)>,
+    mut targets: Query<(
+        Entity,
+        &Transform,
+        &Owner,
+        &UnitInformation,
+        &mut Health,
+        Option<&mut Shield>,
+    )>,
+    mut alerts: EventWriter<Alert>,
+) {
+    let delta: f32 = time.delta_seconds();
+    let positions: Vec<(Entity, Vec3, Entity)> = targets
+        .iter()
+        .map(|(entity, transform, owner, ..)| (entity, transform.translation, owner.player))
+        .collect();
+    let mut hits: Vec<f32> = vec![0.0; positions.len()];
+    for (transform, owner, weapon) in armed.iter() {
+        let closest: Option<(usize, f32)> = positions
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, player))| *player != owner.player)
+            .map(|(index, (_, position, _))| (index, position.distance(transform.translation)))
+            .filter(|(_, distance)| *distance <= weapon.range)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, _)) = closest {
+            hits[index] += weapon.damage * delta;
+        }
+    }
+    for ((entity, ..), mut damage) in positions.iter().zip(hits) {
+        if damage <= 0.0 {
+            continue;
+        }
+        let Ok((_, transform, owner, unit_information, mut health, shield)) =
+            targets.get_mut(*entity)
+        else {
+            continue;
+        };
+        if let Some(mut shield) = shield {
+            damage = shield.absorb(damage);
+        }
+        health.current -= damage;
+        if health.current <= 0.0 {
+            alerts.send(Alert {
+                player: owner.player,
+                position: transform.translation,
+                message: format!("{} was destroyed", unit_information.unit_name),
+            });
+            commands.entity(*entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::player_controller::Civilisation;
+    use crate::spawner::{UnitStats, UnitType};
+
+    fn unit(world: &mut World, player: Entity, x: f32, health: f32) -> Entity {
+        world
+            .spawn((
+                Transform::from_xyz(x, 0.0, 0.0),
+                Owner { player },
+                Health::full(health),
+                UnitInformation {
+                    unit_name: "Cruiser".into(),
+                    unit_type: UnitType::Cruiser,
+                    civilisation: Civilisation("Greek".into()),
+                    thumbnail: String::new(),
+                    stats: UnitStats(Vec::new()),
+                    unit_info: String::new(),
+                    unit_cost: default(),
+                },
+            ))
+            .id()
+    }
+
+    #[test]
+    fn weapons_wear_down_the_closest_enemy_shield_first() {
+        let mut world: World = World::new();
+        let mut time: Time = Time::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+        world.init_resource::<Events<Alert>>();
+        let attacker: Entity = world.spawn_empty().id();
+        let defender: Entity = world.spawn_empty().id();
+        let gunship: Entity = unit(&mut world, attacker, 0.0, 100.0);
+        world.entity_mut(gunship).insert(Weapon {
+            damage: 10.0,
+            range: 5.0,
+        });
+        let escort: Entity = unit(&mut world, attacker, 1.0, 100.0);
+        let near: Entity = unit(&mut world, defender, 3.0, 15.0);
+        world.entity_mut(near).insert(Shield {
+            current: 5.0,
+            max: 5.0,
+        });
+        let far: Entity = unit(&mut world, defender, 4.0, 100.0);
+        let out_of_range: Entity = unit(&mut world, defender, 6.0, 100.0);
+
+        world.run_system_once(fire_weapons);
+        assert_eq!(world.get::<Shield>(near).unwrap().current, 0.0);
+        assert_eq!(world.get::<Health>(near).unwrap().current, 10.0);
+        assert_eq!(world.get::<Health>(far).unwrap().current, 100.0);
+        assert_eq!(world.get::<Health>(escort).unwrap().current, 100.0);
+
+        world.run_system_once(fire_weapons);
+        assert!(world.get_entity(near).is_none());
+        assert_eq!(world.resource::<Events<Alert>>().len(), 1);
+
+        world.run_system_once(fire_weapons);
+        assert_eq!(world.get::<Health>(far).unwrap().current, 90.0);
+        assert_eq!(world.get::<Health>(out_of_range).unwrap().current, 100.0);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::key_bindings::{InputAction, InputActions};
+use crate::ownable::{Owner, Selected};
+use crate::player_controller::{centre_camera_on, CameraControllerSettings, LocalPlayer};
+use crate::selection::{SelectionMode, SelectionRequest};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const CONTROL_GROUPS: usize = 9;
+/// Recalling the same group twice within this time centres the camera on it
+const DOUBLE_TAP: Duration = Duration::from_millis(400);
+
+pub struct ControlGroups;
+impl Plugin for ControlGroups {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, control_group_hotkeys);
+    }
+}
+/// The control groups (0 to 8) a unit belongs to, one bit per group.
+/// Kept on the unit itself so groups are saved with it and vanish when it is despawned.
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ControlGroupMembership(pub u16);
+impl ControlGroupMembership {
+    pub fn contains(&self, group: usize) -> bool {
+        self.0 & (1 << group) != 0
+    }
+    pub fn insert(&mut self, group: usize) {
+        self.0 |= 1 << group;
+    }
+    pub fn remove(&mut self, group: usize) {
+        self.0 &= !(1 << group);
+    }
+}
+#[derive(Default)]
+struct LastRecall {
+    group: Option<usize>,
+    at: Duration,
+}
+
+fn control_group_hotkeys(
+    mut commands: Commands,
+    time: Res<Time>,
+    actions: InputActions,
+    mut last_recall: Local<LastRecall>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+    mut units: Query<(
+        Entity,
+        &Owner,
+        &GlobalTransform,
+        Option<&mut ControlGroupMembership>,
+        Has<Selected>,
+    )>,
+    mut cameras: Query<&mut Transform, With<CameraControllerSettings>>,
+    mut selection_requests: EventWriter<SelectionRequest>,
+) {
+    let Ok(local_player) = local_player.get_single() else {
+        return;
+    };
+    let Some(group) =
+        (0..CONTROL_GROUPS).find(|group| actions.just_pressed(InputAction::ControlGroup(*group)))
+    else {
+        return;
+    };
+    if actions.pressed(InputAction::AssignControlGroup) {
+        for (entity, owner, _, membership, selected) in units.iter_mut() {
+            let assign: bool = selected && owner.player == local_player;
+            match membership {
+                Some(mut membership) => {
+                    if assign {
+                        membership.insert(group);
+                    } else {
+                        membership.remove(group);
+                    }
+                }
+                None if assign => {
+                    let mut membership: ControlGroupMembership = ControlGroupMembership::default();
+                    membership.insert(group);
+                    commands.entity(entity).insert(membership);
+                }
+                None => {}
+            }
+        }
+        return;
+    }
+    let members: Vec<(Entity, Vec3)> = units
+        .iter()
+        .filter(|(_, owner, _, membership, _)| {
+            owner.player == local_player
+                && membership
+                    .as_ref()
+                    .is_some_and(|membership| membership.contains(group))
+        })
+        .map(|(entity, _, transform, _, _)| (entity, transform.translation()))
+        .collect();
+    if members.is_empty() {
+        return;
+    }
+    let now: Duration = time.elapsed();
+    if last_recall.group == Some(group) && now - last_recall.at < DOUBLE_TAP {
+        let centre: Vec3 =
+            members.iter().map(|(_, position)| *position).sum::<Vec3>() / members.len() as f32;
+        if let Ok(mut camera_transform) = cameras.get_single_mut() {
+            centre_camera_on(&mut camera_transform, centre);
+        }
+    }
+    *last_recall = LastRecall {
+        group: Some(group),
+        at: now,
+    };
+    selection_requests.send(SelectionRequest {
+        entities: members.into_iter().map(|(entity, _)| entity).collect(),
+        mode: SelectionMode::from_modifiers(actions.pressed(InputAction::AddToSelection), false),
+    });
+}
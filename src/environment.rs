@@ -1,4 +1,8 @@
-use crate::resources::{ResourceLevel, ResourceSource, ResourceType};
+use crate::hazards::hazard_cost_zones;
+use crate::large_world::{Impostor, LargeWorldPosition};
+use crate::orbits::Orbiting;
+use crate::resources::{ResourceLevel, ResourceSource, ResourceSourceId};
+use crate::scenario::{Lighting, Prop, Scenario, ScenarioProp, ScenarioResourceSource};
 use crate::spawner::{UnitSpecification, UnitStats};
 use crate::utils::ShapeTypeSerializable;
 use crate::{player_controller::RenderLayerMap, spawner::EntityWrapper};
@@ -16,23 +20,21 @@ use bevy_rapier3d::{
     geometry::Collider,
     prelude::{GravityScale, RigidBody},
 };
+use serde::{Deserialize, Serialize};
 
 pub struct Environment;
 
 impl Plugin for Environment {
     fn build(&self, app: &mut App) {
+        // The scenario is inserted before the plugins are built, so that every startup system
+        // already sees the grid of its map
+        let settings: GridSettings = app
+            .world()
+            .get_resource::<Scenario>()
+            .map_or_else(GridSettings::default, |scenario| scenario.grid.clone());
         app.add_plugins(MaterialPlugin::<CustomMaterial>::default())
             .add_systems(Startup, (environment_setup, setup_movement_grid))
-            .insert_resource(MovementGrid {
-                settings: GridSettings {
-                    cell_size: 0.2,
-                    grid_width: 1000,
-                    grid_height: 1000,
-                    xy_offset: Vec2::new(500.0, 500.0),
-                    density: 0.2,
-                },
-                grid: Vec::new(),
-            });
+            .insert_resource(MovementGrid::new(settings));
     }
 }
 // This is the struct that will be passed to your shader
@@ -44,18 +46,174 @@ pub struct CustomMaterial {
     alpha_mode: AlphaMode,
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct GridSettings {
     pub cell_size: f32,
     pub grid_width: u32,
     pub grid_height: u32,
     pub xy_offset: Vec2,
-    pub density: f64, // TODO put into map generation
+    /// Asteroid belt density the map was generated with
+    pub density: f64,
+}
+impl Default for GridSettings {
+    fn default() -> Self {
+        GridSettings {
+            cell_size: 0.2,
+            grid_width: 1000,
+            grid_height: 1000,
+            xy_offset: Vec2::new(500.0, 500.0),
+            density: 0.2,
+        }
+    }
+}
+impl GridSettings {
+    /// Grid cell containing the world position (x, z), if it lies on the grid
+    pub fn world_to_cell(&self, position: Vec2) -> Option<UVec2> {
+        let cell: Vec2 = (position / self.cell_size + self.xy_offset).floor();
+        if cell.x < 0.0
+            || cell.y < 0.0
+            || cell.x >= self.grid_width as f32
+            || cell.y >= self.grid_height as f32
+        {
+            return None;
+        }
+        Some(cell.as_uvec2())
+    }
+    /// World position (x, z) of the cell centre
+    pub fn cell_to_world(&self, cell: UVec2) -> Vec2 {
+        (cell.as_vec2() + Vec2::splat(0.5) - self.xy_offset) * self.cell_size
+    }
+    /// The same grid moved by the offset (x, z) in the world
+    pub fn shifted(&self, offset: Vec2) -> GridSettings {
+        GridSettings {
+            xy_offset: self.xy_offset - offset / self.cell_size,
+            ..self.clone()
+        }
+    }
+    /// The area (x, z) covered by the grid
+    pub fn world_bounds(&self) -> Rect {
+        Rect::from_corners(
+            -self.xy_offset * self.cell_size,
+            (Vec2::new(self.grid_width as f32, self.grid_height as f32) - self.xy_offset)
+                * self.cell_size,
+        )
+    }
 }
+/// Cells with this value can't be passed. Lower values are added to the cost of crossing a cell.
+pub const OBSTACLE: u8 = u8::MAX;
 #[derive(Resource)]
 pub struct MovementGrid {
     pub settings: GridSettings,
     pub grid: Vec<Vec<u8>>,
+    // What the grid was painted from, so parts of it can be restored
+    obstacles: Vec<GridArea>,
+    cost_zones: Vec<CostZone>,
+    /// Obstacles that move around, like orbiting planets
+    moving_obstacles: Vec<GridArea>,
+}
+/// A round area (x, z) of the map
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GridArea {
+    pub centre: Vec2,
+    pub radius: f32,
+}
+/// An area that is slower to cross, e.g. a dust cloud
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CostZone {
+    pub area: GridArea,
+    pub cost: u8,
+}
+impl MovementGrid {
+    /// An empty grid, `rebuild` fills it
+    pub fn new(settings: GridSettings) -> Self {
+        MovementGrid {
+            settings,
+            grid: Vec::new(),
+            obstacles: Vec::new(),
+            cost_zones: Vec::new(),
+            moving_obstacles: Vec::new(),
+        }
+    }
+    pub fn is_blocked(&self, cell: UVec2) -> bool {
+        self.grid[cell.x as usize][cell.y as usize] == OBSTACLE
+    }
+    /// Extra cost of crossing a passable cell
+    pub fn cost(&self, cell: UVec2) -> u8 {
+        self.grid[cell.x as usize][cell.y as usize]
+    }
+    /// Cells whose centre lies within the area
+    fn cells_in(&self, area: &GridArea) -> Vec<UVec2> {
+        if self.grid.is_empty() {
+            return Vec::new();
+        }
+        let last: UVec2 = UVec2::new(self.grid.len() as u32, self.grid[0].len() as u32) - 1;
+        let to_cell = |position: Vec2| {
+            (position / self.settings.cell_size + self.settings.xy_offset)
+                .floor()
+                .max(Vec2::ZERO)
+                .as_uvec2()
+                .min(last)
+        };
+        let min: UVec2 = to_cell(area.centre - area.radius);
+        let max: UVec2 = to_cell(area.centre + area.radius);
+        let mut cells: Vec<UVec2> = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                let cell: UVec2 = UVec2::new(x, y);
+                if self.settings.cell_to_world(cell).distance(area.centre) <= area.radius {
+                    cells.push(cell);
+                }
+            }
+        }
+        cells
+    }
+    /// Sets every cell whose centre lies within the area
+    pub fn paint(&mut self, area: &GridArea, value: u8) {
+        for cell in self.cells_in(area) {
+            self.grid[cell.x as usize][cell.y as usize] = value;
+        }
+    }
+    /// Clears the grid, then paints the cost zones and the obstacles on top of them
+    pub fn rebuild(&mut self, obstacles: &[GridArea], cost_zones: &[CostZone]) {
+        self.obstacles = obstacles.to_vec();
+        self.cost_zones = cost_zones.to_vec();
+        self.grid =
+            vec![vec![0; self.settings.grid_height as usize]; self.settings.grid_width as usize];
+        for zone in cost_zones {
+            self.paint(&zone.area, zone.cost.min(OBSTACLE - 1));
+        }
+        let moving_obstacles: Vec<GridArea> = self.moving_obstacles.clone();
+        for obstacle in obstacles.iter().chain(&moving_obstacles) {
+            self.paint(obstacle, OBSTACLE);
+        }
+    }
+    /// What the cell would be without anything that moves
+    fn static_value(&self, cell: UVec2) -> u8 {
+        let centre: Vec2 = self.settings.cell_to_world(cell);
+        let within = |area: &GridArea| centre.distance(area.centre) <= area.radius;
+        if self.obstacles.iter().any(within) {
+            return OBSTACLE;
+        }
+        // Later zones are painted over earlier ones
+        self.cost_zones
+            .iter()
+            .rev()
+            .find(|zone| within(&zone.area))
+            .map_or(0, |zone| zone.cost.min(OBSTACLE - 1))
+    }
+    /// Lifts the moving obstacles from where they were and puts them down at their new areas
+    pub fn move_obstacles(&mut self, moving_obstacles: Vec<GridArea>) {
+        let previous: Vec<GridArea> =
+            std::mem::replace(&mut self.moving_obstacles, moving_obstacles);
+        for area in &previous {
+            for cell in self.cells_in(area) {
+                self.grid[cell.x as usize][cell.y as usize] = self.static_value(cell);
+            }
+        }
+        for area in self.moving_obstacles.clone() {
+            self.paint(&area, OBSTACLE);
+        }
+    }
 }
 
 /// The Material trait is very configurable, but comes with sensible defaults for all methods.
@@ -75,125 +233,147 @@ pub fn environment_setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut custom_materials: ResMut<Assets<CustomMaterial>>,
     asset_server: Res<AssetServer>,
+    scenario: Res<Scenario>,
+    movement_grid: Res<MovementGrid>,
 ) {
+    let lighting: &Lighting = &scenario.lighting;
     // directional 'sun' light
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
-            illuminance: 3200.0,
+            illuminance: lighting.illuminance,
             ..default()
         },
         transform: Transform {
-            translation: Vec3::new(0.0, 20.0, 0.0),
-            rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_4),
+            translation: lighting.position,
+            rotation: Quat::from_rotation_x(lighting.pitch.to_radians()),
             ..default()
         },
         ..default()
     });
+    if let Some(brightness) = lighting.ambient_brightness {
+        commands.insert_resource(AmbientLight {
+            brightness,
+            ..default()
+        });
+    }
+    spawn_ground(
+        &mut commands,
+        &mut meshes,
+        &mut custom_materials,
+        movement_grid.settings.world_bounds(),
+    );
+    for prop in &scenario.props {
+        spawn_prop(&mut commands, &asset_server, prop);
+    }
+    for (index, resource_source) in scenario.resource_sources.iter().enumerate() {
+        spawn_resource_source(
+            &mut commands,
+            &asset_server,
+            resource_source,
+            ResourceSourceId { system: 0, index },
+        );
+    }
+}
+/// The plane of a star system, along with the sensor that mouse clicks are aimed at
+pub fn spawn_ground(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    custom_materials: &mut Assets<CustomMaterial>,
+    bounds: Rect,
+) {
     commands.spawn((
         MaterialMeshBundle {
-            mesh: meshes.add(Plane3d::default().mesh().size(200.0, 200.0)),
+            mesh: meshes.add(
+                Plane3d::default()
+                    .mesh()
+                    .size(bounds.width(), bounds.height()),
+            ),
             material: custom_materials.add(CustomMaterial {
                 color: LinearRgba::new(0.5, 0.5, 0.5, 1.0),
                 alpha_mode: AlphaMode::Add,
             }),
+            transform: Transform::from_translation(bounds.center().extend(0.0).xzy()),
             ..default()
         },
         RenderLayers::layer(RenderLayerMap::Main as usize),
     ));
     commands.spawn((
-        Transform::from_xyz(0.0, 2.0, 0.0),
-        Collider::cuboid(100.0, 2.0, 100.0),
+        Transform::from_translation(bounds.center().extend(2.0).xzy()),
+        Collider::cuboid(bounds.half_size().x, 2.0, bounds.half_size().y),
         Sensor,
     ));
-
-    commands.spawn((
-        SceneBundle {
-            scene: asset_server.load("3d_models/environment/planet.glb#Scene0"),
-            transform: Transform::from_xyz(0.0, 2.0, 6772.0)
-                .with_rotation(Quat::from_rotation_y((75.0_f32).to_radians())),
-            // transform: Transform::from_scale(Vec3::splat(0.5)),
-            ..default()
+}
+pub fn spawn_resource_source(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    resource_source: &ScenarioResourceSource,
+    id: ResourceSourceId,
+) -> Entity {
+    let entity: Entity = spawn_prop(commands, asset_server, &resource_source.prop);
+    commands.entity(entity).insert((
+        Sensor,
+        Collider::ball(resource_source.radius),
+        ResourceSource { id },
+        ResourceLevel {
+            resource_type: resource_source.resource_type,
+            resource_amount: resource_source.resource_amount,
         },
-        RigidBody::KinematicPositionBased,
-        GravityScale(0.0),
-        RenderLayers::layer(RenderLayerMap::Main as usize),
-        // ContextMenuActions {},
     ));
+    entity
+}
+/// Spawns the model of a prop along with a description of it
+pub fn spawn_prop(commands: &mut Commands, asset_server: &AssetServer, prop: &Prop) -> Entity {
+    let transform: Transform = Transform::from_translation(prop.position.as_vec3())
+        .with_rotation(Quat::from_rotation_y(prop.rotation.to_radians()))
+        .with_scale(Vec3::splat(prop.scale));
     let parent: Entity = commands
         .spawn((
-            SceneBundle {
-                scene: asset_server.load("3d_models/environment/asteroid_01.glb#Scene0"),
-                transform: Transform::from_xyz(-5.0, 2.0, 5.0), //.with_scale(Vec3::splat(0.001)),
-                // transform: Transform::from_scale(Vec3::splat(0.5)),
-                ..default()
-            },
+            SpatialBundle::from_transform(transform),
             RigidBody::KinematicPositionBased,
-            Sensor,
             GravityScale(0.0),
-            RenderLayers::layer(RenderLayerMap::Main as usize),
-            Collider::ball(1.0),
-            ResourceSource,
-            ResourceLevel {
-                resource_type: ResourceType::Plotanium,
-                resource_amount: 42_000,
-            }, // ContextMenuActions {},
+            ScenarioProp(prop.clone()),
+            LargeWorldPosition {
+                position: prop.position,
+                scale: prop.scale,
+            },
         ))
         .id();
     commands.spawn((
-        EntityWrapper { entity: parent },
-        UnitSpecification {
-            file_path: "assets/3d_models/environment/asteroid_01.glb".to_owned(),
-            scene: "Scene0".to_owned(),
-            icon_path: "".to_owned(),
-            unit_name: "Asteroid".to_owned(),
-            movable: true,
-            shape: ShapeTypeSerializable(bevy_rapier3d::rapier::prelude::ShapeType::Ball),
-            dimensions: Vec3::splat(1.0),
-            prescaling: 1.0,
-            base_stats: UnitStats(Vec::new()),
-            unit_info: "Asteroid. Rich in Plotanium".into(),
-            unit_cost: HashMap::new(),
+        SceneBundle {
+            scene: asset_server.load(format!("{}#{}", prop.model, prop.scene)),
+            transform,
+            ..default()
         },
+        RenderLayers::layer(RenderLayerMap::Main as usize),
+        Impostor { body: parent },
     ));
-
-    let parent: Entity = commands
-        .spawn((
-            SceneBundle {
-                scene: asset_server.load("3d_models/environment/sun.glb#Scene0"),
-                transform: Transform::from_xyz(150_000_000_000.0, 2.0, 5.0)
-                    .with_scale(Vec3::splat(100000.0)),
-
-                // transform: Transform::from_scale(Vec3::splat(0.5)),
-                ..default()
-            },
-            RigidBody::KinematicPositionBased,
-            GravityScale(0.0),
-            RenderLayers::layer(RenderLayerMap::Main as usize),
-            // ContextMenuActions {},
-        ))
-        .id();
+    if let Some(orbit) = prop.orbit {
+        commands.entity(parent).insert(Orbiting(orbit));
+    }
     commands.spawn((
         EntityWrapper { entity: parent },
         UnitSpecification {
-            file_path: "assets/3d_models/environment/sun.glb".to_owned(),
-            scene: "Scene0".to_owned(),
+            file_path: format!("assets/{}", prop.model),
+            scene: prop.scene.clone(),
             icon_path: "".to_owned(),
-            unit_name: "Sun".to_owned(),
+            unit_name: prop.name.clone(),
             movable: true,
             shape: ShapeTypeSerializable(bevy_rapier3d::rapier::prelude::ShapeType::Ball),
             dimensions: Vec3::splat(1.0),
             prescaling: 1.0,
             base_stats: UnitStats(Vec::new()),
-            unit_info: "The central star of the system. Don't come to close".into(),
+            unit_info: prop.info.clone(),
             unit_cost: HashMap::new(),
+            build_time: 0.0,
         },
     ));
+    parent
 }
-fn setup_movement_grid(mut movement_grid: ResMut<MovementGrid>) {
-    for i in 0..movement_grid.settings.grid_width as usize {
-        movement_grid.grid.push(Vec::new());
-        for _ in 0..movement_grid.settings.grid_height as usize {
-            movement_grid.grid[i].push(0);
-        }
-    }
+pub fn setup_movement_grid(mut movement_grid: ResMut<MovementGrid>, scenario: Res<Scenario>) {
+    let cost_zones: Vec<CostZone> = [
+        scenario.cost_zones.clone(),
+        hazard_cost_zones(&scenario.hazards),
+    ]
+    .concat();
+    movement_grid.rebuild(&scenario.obstacles, &cost_zones);
 }
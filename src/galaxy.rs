@@ -0,0 +1,573 @@
+use std::collections::VecDeque;
+
+use bevy::ecs::system::SystemParam;
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::a_star::AStarParams;
+use crate::environment::{
+    spawn_ground, spawn_prop, spawn_resource_source, CostZone, CustomMaterial, GridArea,
+    MovementGrid,
+};
+use crate::hazards::hazard_cost_zones;
+use crate::key_bindings::{InputAction, InputActions};
+use crate::movable::{MoveCommand, MovementPath};
+use crate::player_controller::{
+    camera_controller, camera_focus, camera_follow, centre_camera_on, CameraControllerSettings,
+};
+use crate::resources::ResourceSourceId;
+use crate::scenario::{Prop, Scenario, ScenarioResourceSource, ScenarioSystem};
+
+/// Systems are laid out next to each other along x, this far apart
+pub const SYSTEM_SPACING: f32 = 10_000.0;
+/// Units this close to a jump gate pass through it
+const JUMP_RANGE: f32 = 3.0;
+const JUMP_GATE_RADIUS: f32 = 1.5;
+const JUMP_GATE: Color = Color::srgb(0.3, 0.7, 1.0);
+const GALAXY_VIEW_SIZE: Vec2 = Vec2::new(480.0, 360.0);
+const GALAXY_VIEW_BACKGROUND: Color = Color::srgba(12.0 / 256.0, 11.0 / 256.0, 13.0 / 256.0, 0.95);
+const GALAXY_VIEW_TEXT: Color = Color::srgb(
+    0x81 as f32 / 256.0,
+    0xC1 as f32 / 256.0,
+    0x14 as f32 / 256.0,
+);
+const SYSTEM_BUTTON: Color = Color::srgb(0.1, 0.1, 0.12);
+const HOVERED_SYSTEM_BUTTON: Color = Color::srgb(64.0 / 256.0, 99.0 / 256.0, 64.0 / 256.0);
+const ACTIVE_SYSTEM_BUTTON: Color = Color::srgb(0.25, 0.4, 0.25);
+const HYPERLANE: Color = Color::srgb(0.3, 0.5, 0.7);
+
+pub struct GalaxyPlugin;
+impl Plugin for GalaxyPlugin {
+    fn build(&self, app: &mut App) {
+        // Like the movement grid of the first system, the others are known before startup
+        let galaxy: Galaxy = app
+            .world()
+            .get_resource::<Scenario>()
+            .map_or_else(Galaxy::default, Galaxy::from_scenario);
+        app.insert_resource(galaxy)
+            .add_systems(Startup, spawn_star_systems)
+            .add_systems(
+                Update,
+                (
+                    sync_active_system.after(camera_follow),
+                    toggle_galaxy_view,
+                    // Otherwise the camera is clamped straight back into the old system
+                    galaxy_view_buttons
+                        .after(toggle_galaxy_view)
+                        .after(camera_controller)
+                        .before(sync_active_system),
+                    draw_jump_gates,
+                ),
+            )
+            .add_systems(FixedUpdate, use_jump_gates);
+    }
+}
+
+pub struct StarSystem {
+    pub name: String,
+    /// On the galaxy view, from (0, 0) at the top left to (1, 1)
+    pub galaxy_position: Vec2,
+    pub bounds: Rect,
+    /// Taken out while the system is the one on screen, which has its grid in `MovementGrid`
+    grid: Option<MovementGrid>,
+}
+#[derive(Clone, Copy, Debug)]
+pub struct JumpGate {
+    pub systems: [usize; 2],
+    /// World positions (x, z) of the two ends
+    pub positions: [Vec2; 2],
+}
+impl JumpGate {
+    /// Entrance, exit and the system the gate leads to when jumping out of the given system
+    pub fn from_system(&self, system: usize) -> Option<(Vec2, Vec2, usize)> {
+        if self.systems[0] == system {
+            Some((self.positions[0], self.positions[1], self.systems[1]))
+        } else if self.systems[1] == system {
+            Some((self.positions[1], self.positions[0], self.systems[0]))
+        } else {
+            None
+        }
+    }
+}
+/// All star systems of the map. The one on screen is `active`.
+#[derive(Resource)]
+pub struct Galaxy {
+    pub systems: Vec<StarSystem>,
+    pub jump_gates: Vec<JumpGate>,
+    pub active: usize,
+}
+impl Default for Galaxy {
+    fn default() -> Self {
+        Galaxy {
+            systems: vec![StarSystem {
+                name: "Home".into(),
+                galaxy_position: Vec2::splat(0.5),
+                bounds: Rect::from_center_size(Vec2::ZERO, Vec2::splat(200.0)),
+                grid: None,
+            }],
+            jump_gates: Vec::new(),
+            active: 0,
+        }
+    }
+}
+impl Galaxy {
+    /// Where a system's own coordinates start in the world
+    pub fn system_origin(system: usize) -> Vec2 {
+        Vec2::new(system as f32 * SYSTEM_SPACING, 0.0)
+    }
+    pub fn from_scenario(scenario: &Scenario) -> Galaxy {
+        let mut systems: Vec<StarSystem> = vec![StarSystem {
+            name: scenario.name.clone(),
+            galaxy_position: scenario.galaxy_position,
+            bounds: scenario.grid.world_bounds(),
+            grid: None,
+        }];
+        for (index, system) in scenario.systems.iter().enumerate() {
+            let origin: Vec2 = Galaxy::system_origin(index + 1);
+            let mut grid: MovementGrid = MovementGrid::new(system.grid.shifted(origin));
+            let hazards: Vec<CostZone> = hazard_cost_zones(&system.hazards);
+            grid.rebuild(
+                &shift_areas(&system.obstacles, origin),
+                &shift_cost_zones(&[system.cost_zones.clone(), hazards].concat(), origin),
+            );
+            systems.push(StarSystem {
+                name: system.name.clone(),
+                galaxy_position: system.galaxy_position,
+                bounds: grid.settings.world_bounds(),
+                grid: Some(grid),
+            });
+        }
+        Galaxy {
+            systems,
+            jump_gates: scenario
+                .jump_gates
+                .iter()
+                .map(|gate| JumpGate {
+                    systems: gate.systems,
+                    positions: [
+                        gate.positions[0] + Galaxy::system_origin(gate.systems[0]),
+                        gate.positions[1] + Galaxy::system_origin(gate.systems[1]),
+                    ],
+                })
+                .collect(),
+            active: 0,
+        }
+    }
+    pub fn system_at(&self, position: Vec2) -> Option<usize> {
+        self.systems
+            .iter()
+            .position(|system| system.bounds.contains(position))
+    }
+    /// Jump gates to pass through on the way from one system to another, fewest jumps first
+    pub fn route(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        // The gate each system was first reached through, and the system before it
+        let mut came_through: Vec<Option<(usize, usize)>> = vec![None; self.systems.len()];
+        let mut visited: Vec<bool> = vec![false; self.systems.len()];
+        let mut open: VecDeque<usize> = VecDeque::from([from]);
+        visited[from] = true;
+        while let Some(system) = open.pop_front() {
+            if system == to {
+                let mut gates: Vec<usize> = Vec::new();
+                let mut current: usize = to;
+                while let Some((gate, previous)) = came_through[current] {
+                    gates.push(gate);
+                    current = previous;
+                }
+                gates.reverse();
+                return Some(gates);
+            }
+            for (index, gate) in self.jump_gates.iter().enumerate() {
+                let Some((_, _, next)) = gate.from_system(system) else {
+                    continue;
+                };
+                if next < self.systems.len() && !visited[next] {
+                    visited[next] = true;
+                    came_through[next] = Some((index, system));
+                    open.push_back(next);
+                }
+            }
+        }
+        None
+    }
+    /// Runs the function on the grid of every system, the one on screen included
+    pub fn for_each_grid(
+        &mut self,
+        active: &mut MovementGrid,
+        mut function: impl FnMut(usize, &mut MovementGrid),
+    ) {
+        for (index, system) in self.systems.iter_mut().enumerate() {
+            if index == self.active {
+                function(index, active);
+            } else if let Some(grid) = system.grid.as_mut() {
+                function(index, grid);
+            }
+        }
+    }
+    /// Puts the grid of another system on screen
+    pub fn switch_to(&mut self, system: usize, movement_grid: &mut MovementGrid) {
+        if system == self.active || system >= self.systems.len() {
+            return;
+        }
+        let Some(incoming) = self.systems[system].grid.take() else {
+            return;
+        };
+        self.systems[self.active].grid = Some(std::mem::replace(movement_grid, incoming));
+        self.active = system;
+    }
+}
+pub fn shift_areas(areas: &[GridArea], origin: Vec2) -> Vec<GridArea> {
+    areas
+        .iter()
+        .map(|area| GridArea {
+            centre: area.centre + origin,
+            ..*area
+        })
+        .collect()
+}
+pub fn shift_cost_zones(cost_zones: &[CostZone], origin: Vec2) -> Vec<CostZone> {
+    cost_zones
+        .iter()
+        .map(|zone| CostZone {
+            area: GridArea {
+                centre: zone.area.centre + origin,
+                ..zone.area
+            },
+            ..*zone
+        })
+        .collect()
+}
+fn shift_prop(prop: &Prop, origin: Vec2) -> Prop {
+    let origin: DVec2 = origin.as_dvec2();
+    prop.shifted(origin.extend(0.0).xzy())
+}
+
+/// The movement grids of every system, wherever they are kept at the moment
+#[derive(SystemParam)]
+pub struct SystemGrids<'w> {
+    pub galaxy: Res<'w, Galaxy>,
+    active: Res<'w, MovementGrid>,
+}
+impl SystemGrids<'_> {
+    pub fn grid(&self, system: usize) -> Option<&MovementGrid> {
+        if system == self.galaxy.active {
+            return Some(&self.active);
+        }
+        self.galaxy.systems.get(system)?.grid.as_ref()
+    }
+}
+
+/// The system a prop or resource source belongs to, when it isn't the first one
+#[derive(Component, Clone, Copy)]
+pub struct InSystem(pub usize);
+
+/// Where a unit is headed once it has passed through the jump gate it is flying to
+#[derive(Component, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct JumpRoute {
+    pub gate: usize,
+    pub target: Vec2,
+}
+
+fn spawn_star_systems(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut custom_materials: ResMut<Assets<CustomMaterial>>,
+    asset_server: Res<AssetServer>,
+    scenario: Res<Scenario>,
+    galaxy: Res<Galaxy>,
+) {
+    // The first system is the map the environment sets up
+    for (index, system) in scenario.systems.iter().enumerate() {
+        spawn_ground(
+            &mut commands,
+            &mut meshes,
+            &mut custom_materials,
+            galaxy.systems[index + 1].bounds,
+        );
+        spawn_system_contents(&mut commands, &asset_server, index + 1, system);
+    }
+}
+/// Props and resource sources of one of the further systems, moved to where it lies in the world
+pub fn spawn_system_contents(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    index: usize,
+    system: &ScenarioSystem,
+) {
+    let origin: Vec2 = Galaxy::system_origin(index);
+    for prop in &system.props {
+        let entity: Entity = spawn_prop(commands, asset_server, &shift_prop(prop, origin));
+        commands.entity(entity).insert(InSystem(index));
+    }
+    for (resource_index, resource_source) in system.resource_sources.iter().enumerate() {
+        let entity: Entity = spawn_resource_source(
+            commands,
+            asset_server,
+            &ScenarioResourceSource {
+                prop: shift_prop(&resource_source.prop, origin),
+                ..resource_source.clone()
+            },
+            ResourceSourceId {
+                system: index,
+                index: resource_index,
+            },
+        );
+        commands.entity(entity).insert(InSystem(index));
+    }
+}
+
+/// Whatever moved the camera into another system, that system's grid is now the one in use
+fn sync_active_system(
+    mut galaxy: ResMut<Galaxy>,
+    mut movement_grid: ResMut<MovementGrid>,
+    camera: Query<&Transform, (With<CameraControllerSettings>, Changed<Transform>)>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    if let Some(system) = galaxy.system_at(camera_focus(camera, 0.0).xz()) {
+        if system != galaxy.active {
+            galaxy.switch_to(system, &mut movement_grid);
+        }
+    }
+}
+
+pub fn use_jump_gates(
+    mut commands: Commands,
+    galaxy: Res<Galaxy>,
+    mut travellers: Query<
+        (Entity, &mut Transform, &JumpRoute),
+        (
+            Without<MoveCommand>,
+            Without<AStarParams>,
+            Without<MovementPath>,
+        ),
+    >,
+) {
+    for (entity, mut transform, jump_route) in travellers.iter_mut() {
+        commands.entity(entity).remove::<JumpRoute>();
+        let position: Vec2 = transform.translation.xz();
+        let Some(system) = galaxy.system_at(position) else {
+            continue;
+        };
+        let Some((entrance, exit, _)) = galaxy
+            .jump_gates
+            .get(jump_route.gate)
+            .and_then(|gate| gate.from_system(system))
+        else {
+            continue;
+        };
+        // Stopped short of the gate, e.g. because no path led there
+        if position.distance(entrance) > JUMP_RANGE {
+            continue;
+        }
+        transform.translation.x = exit.x;
+        transform.translation.z = exit.y;
+        commands.entity(entity).insert(MoveCommand {
+            target: jump_route.target,
+        });
+    }
+}
+
+fn draw_jump_gates(mut gizmos: Gizmos, galaxy: Res<Galaxy>) {
+    for gate in &galaxy.jump_gates {
+        for position in gate.positions {
+            gizmos.circle(
+                position.extend(2.0).xzy(),
+                Dir3::Y,
+                JUMP_GATE_RADIUS,
+                JUMP_GATE,
+            );
+        }
+    }
+}
+
+#[derive(Component)]
+struct GalaxyViewPanel;
+#[derive(Component)]
+struct SystemButton(usize);
+
+fn toggle_galaxy_view(
+    mut commands: Commands,
+    actions: InputActions,
+    asset_server: Res<AssetServer>,
+    galaxy: Res<Galaxy>,
+    panels: Query<Entity, With<GalaxyViewPanel>>,
+) {
+    if !actions.just_pressed(InputAction::ToggleGalaxyView) {
+        return;
+    }
+    if !panels.is_empty() {
+        for panel in panels.iter() {
+            commands.entity(panel).despawn_recursive();
+        }
+        return;
+    }
+    commands
+        .spawn((
+            GalaxyViewPanel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(50.0),
+                    top: Val::Percent(50.0),
+                    margin: UiRect {
+                        left: Val::Px(-GALAXY_VIEW_SIZE.x / 2.0),
+                        top: Val::Px(-GALAXY_VIEW_SIZE.y / 2.0),
+                        ..default()
+                    },
+                    width: Val::Px(GALAXY_VIEW_SIZE.x),
+                    height: Val::Px(GALAXY_VIEW_SIZE.y),
+                    ..default()
+                },
+                background_color: GALAXY_VIEW_BACKGROUND.into(),
+                z_index: ZIndex::Global(15),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            let on_view = |galaxy_position: Vec2| galaxy_position * GALAXY_VIEW_SIZE;
+            // Hyperlanes first, so the systems are drawn on top of them
+            for gate in &galaxy.jump_gates {
+                let from: Vec2 = on_view(galaxy.systems[gate.systems[0]].galaxy_position);
+                let to: Vec2 = on_view(galaxy.systems[gate.systems[1]].galaxy_position);
+                let length: f32 = from.distance(to);
+                let centre: Vec2 = (from + to) / 2.0;
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(centre.x - length / 2.0),
+                        top: Val::Px(centre.y - 1.0),
+                        width: Val::Px(length),
+                        height: Val::Px(2.0),
+                        ..default()
+                    },
+                    background_color: HYPERLANE.into(),
+                    // Layout only ever sets the translation of a node
+                    transform: Transform::from_rotation(Quat::from_rotation_z(
+                        (to.y - from.y).atan2(to.x - from.x),
+                    )),
+                    ..default()
+                });
+            }
+            for (index, system) in galaxy.systems.iter().enumerate() {
+                let position: Vec2 = on_view(system.galaxy_position);
+                parent
+                    .spawn((
+                        SystemButton(index),
+                        ButtonBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                left: Val::Px(position.x),
+                                top: Val::Px(position.y),
+                                padding: UiRect::all(Val::Px(4.0)),
+                                ..default()
+                            },
+                            background_color: if index == galaxy.active {
+                                ACTIVE_SYSTEM_BUTTON.into()
+                            } else {
+                                SYSTEM_BUTTON.into()
+                            },
+                            ..default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            system.name.clone(),
+                            TextStyle {
+                                font: asset_server
+                                    .load("fonts/android-insomnia-font/AndroidInsomniaRegular.ttf"),
+                                font_size: 14.0,
+                                color: GALAXY_VIEW_TEXT,
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn galaxy_view_buttons(
+    mut commands: Commands,
+    galaxy: Res<Galaxy>,
+    mut buttons: Query<(&Interaction, &SystemButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut camera: Query<(&mut Transform, &mut CameraControllerSettings)>,
+    panels: Query<Entity, With<GalaxyViewPanel>>,
+) {
+    for (interaction, system_button, mut background_color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => {
+                // Moving the camera there is enough, the system becomes active on its own
+                if let Ok((mut transform, mut options)) = camera.get_single_mut() {
+                    options.follow = None;
+                    let centre: Vec2 = galaxy.systems[system_button.0].bounds.center();
+                    centre_camera_on(&mut transform, centre.extend(0.0).xzy());
+                }
+                for panel in panels.iter() {
+                    commands.entity(panel).despawn_recursive();
+                }
+            }
+            Interaction::Hovered => *background_color = HOVERED_SYSTEM_BUTTON.into(),
+            Interaction::None => {
+                *background_color = if system_button.0 == galaxy.active {
+                    ACTIVE_SYSTEM_BUTTON.into()
+                } else {
+                    SYSTEM_BUTTON.into()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(index: usize) -> StarSystem {
+        StarSystem {
+            name: format!("System {}", index),
+            galaxy_position: Vec2::ZERO,
+            bounds: Rect::from_center_size(Galaxy::system_origin(index), Vec2::splat(200.0)),
+            grid: None,
+        }
+    }
+    fn gate(from: usize, to: usize) -> JumpGate {
+        JumpGate {
+            systems: [from, to],
+            positions: [
+                Galaxy::system_origin(from) + Vec2::new(50.0, to as f32),
+                Galaxy::system_origin(to) - Vec2::new(50.0, from as f32),
+            ],
+        }
+    }
+
+    #[test]
+    fn routes_take_the_fewest_jumps() {
+        // 0 - 1 - 2 - 3 and a shortcut 0 - 3, gates work both ways
+        let galaxy: Galaxy = Galaxy {
+            systems: (0..5).map(system).collect(),
+            jump_gates: vec![gate(0, 1), gate(2, 1), gate(2, 3), gate(0, 3)],
+            active: 0,
+        };
+        assert_eq!(galaxy.route(0, 0), Some(vec![]));
+        assert_eq!(galaxy.route(0, 2), Some(vec![0, 1]));
+        assert_eq!(galaxy.route(2, 0), Some(vec![1, 0]));
+        assert_eq!(galaxy.route(1, 3), Some(vec![0, 3]));
+        assert_eq!(galaxy.route(3, 1), Some(vec![2, 1]));
+        assert_eq!(galaxy.route(0, 4), None);
+    }
+
+    #[test]
+    fn systems_are_found_by_position() {
+        let galaxy: Galaxy = Galaxy {
+            systems: (0..3).map(system).collect(),
+            jump_gates: Vec::new(),
+            active: 0,
+        };
+        assert_eq!(galaxy.system_at(Vec2::new(-5.0, 5.0)), Some(0));
+        assert_eq!(
+            galaxy.system_at(Galaxy::system_origin(2) + Vec2::new(99.0, -99.0)),
+            Some(2)
+        );
+        assert_eq!(galaxy.system_at(Vec2::new(SYSTEM_SPACING / 2.0, 0.0)), None);
+    }
+}
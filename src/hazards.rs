@@ -0,0 +1,336 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::environment::{CostZone, GridArea};
+use crate::galaxy::Galaxy;
+use crate::ownable::{Owner, Selected};
+use crate::player_controller::Alert;
+use crate::scenario::Scenario;
+use crate::spawner::{Health, Shield, UnitInformation};
+
+/// What crossing a hazard costs the pathfinder if the scenario doesn't say
+const DEFAULT_PATH_COST: u8 = 100;
+/// Share of its maximum a shield recharges per second
+const SHIELD_RECHARGE: f32 = 0.05;
+const HAZARD_HEIGHT: f32 = 2.0;
+
+pub struct Hazards;
+impl Plugin for Hazards {
+    fn build(&self, app: &mut App) {
+        // Built before startup so the movement grids can avoid them from the start
+        let hazards: HazardZones = app
+            .world()
+            .get_resource::<Scenario>()
+            .map_or_else(HazardZones::default, HazardZones::from_scenario);
+        app.insert_resource(hazards)
+            .add_systems(
+                FixedUpdate,
+                (
+                    find_units_in_hazards,
+                    recharge_shields.after(find_units_in_hazards),
+                    apply_hazard_effects.after(recharge_shields),
+                ),
+            )
+            // Nothing to draw with in the headless simulation
+            .add_systems(
+                Update,
+                draw_hazards.run_if(resource_exists::<GizmoConfigStore>),
+            );
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum HazardKind {
+    StellarCorona,
+    RadiationBelt,
+    IonStorm,
+}
+impl fmt::Display for HazardKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HazardKind::StellarCorona => write!(f, "Stellar corona"),
+            HazardKind::RadiationBelt => write!(f, "Radiation belt"),
+            HazardKind::IonStorm => write!(f, "Ion storm"),
+        }
+    }
+}
+impl HazardKind {
+    fn colour(&self) -> Color {
+        match self {
+            HazardKind::StellarCorona => Color::srgb(1.0, 0.55, 0.1),
+            HazardKind::RadiationBelt => Color::srgb(0.6, 1.0, 0.2),
+            HazardKind::IonStorm => Color::srgb(0.5, 0.4, 1.0),
+        }
+    }
+}
+/// A part of the map that harms or hinders the units within it
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Hazard {
+    pub kind: HazardKind,
+    pub area: GridArea,
+    /// Taken from the shield first, then from the hull
+    #[serde(default)]
+    pub damage_per_second: f32,
+    #[serde(default)]
+    pub disables_shields: bool,
+    /// Multiplier on the speed of units inside
+    #[serde(default = "full_speed")]
+    pub speed: f32,
+    /// Cost of crossing the area, replacing that of any cost zone underneath, so paths only lead
+    /// through it if there is no way around
+    #[serde(default = "default_path_cost")]
+    pub path_cost: u8,
+}
+fn full_speed() -> f32 {
+    1.0
+}
+fn default_path_cost() -> u8 {
+    DEFAULT_PATH_COST
+}
+impl Hazard {
+    pub fn shifted(&self, origin: Vec2) -> Hazard {
+        Hazard {
+            area: GridArea {
+                centre: self.area.centre + origin,
+                ..self.area
+            },
+            ..*self
+        }
+    }
+}
+/// Cost zones for the pathfinder, to be painted after the map's own zones
+pub fn hazard_cost_zones(hazards: &[Hazard]) -> Vec<CostZone> {
+    hazards
+        .iter()
+        .map(|hazard| CostZone {
+            area: hazard.area,
+            cost: hazard.path_cost,
+        })
+        .collect()
+}
+/// The hazards of all systems, in world coordinates
+#[derive(Resource, Default)]
+pub struct HazardZones(pub Vec<Hazard>);
+impl HazardZones {
+    pub fn from_scenario(scenario: &Scenario) -> HazardZones {
+        let mut hazards: Vec<Hazard> = scenario.hazards.clone();
+        for (index, system) in scenario.systems.iter().enumerate() {
+            let origin: Vec2 = Galaxy::system_origin(index + 1);
+            hazards.extend(system.hazards.iter().map(|hazard| hazard.shifted(origin)));
+        }
+        HazardZones(hazards)
+    }
+    /// Indices of the hazards covering the position (x, z)
+    pub fn at(&self, position: Vec2) -> Vec<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, hazard)| hazard.area.centre.distance(position) <= hazard.area.radius)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+/// The combined effects of the hazards a unit is in
+#[derive(Component, Clone, PartialEq, Debug)]
+pub struct InHazard {
+    pub hazards: Vec<usize>,
+    pub kinds: Vec<HazardKind>,
+    pub damage_per_second: f32,
+    pub shields_disabled: bool,
+    /// The slowest of the hazards wins, they don't stack
+    pub speed: f32,
+}
+impl InHazard {
+    pub fn new(zones: &HazardZones, hazards: Vec<usize>) -> InHazard {
+        let mut in_hazard: InHazard = InHazard {
+            hazards: Vec::new(),
+            kinds: Vec::new(),
+            damage_per_second: 0.0,
+            shields_disabled: false,
+            speed: 1.0,
+        };
+        for index in &hazards {
+            let hazard: &Hazard = &zones.0[*index];
+            if !in_hazard.kinds.contains(&hazard.kind) {
+                in_hazard.kinds.push(hazard.kind);
+            }
+            in_hazard.damage_per_second += hazard.damage_per_second;
+            in_hazard.shields_disabled |= hazard.disables_shields;
+            in_hazard.speed = in_hazard.speed.min(hazard.speed.max(0.0));
+        }
+        in_hazard.hazards = hazards;
+        in_hazard
+    }
+}
+
+fn find_units_in_hazards(
+    mut commands: Commands,
+    zones: Res<HazardZones>,
+    units: Query<
+        (
+            Entity,
+            &Transform,
+            &Owner,
+            &UnitInformation,
+            Option<&InHazard>,
+            Has<Selected>,
+        ),
+        With<Health>,
+    >,
+    mut alerts: EventWriter<Alert>,
+) {
+    for (entity, transform, owner, unit_information, in_hazard, selected) in units.iter() {
+        let hazards: Vec<usize> = zones.at(transform.translation.xz());
+        let before: &[usize] = in_hazard.map_or(&[], |in_hazard| &in_hazard.hazards);
+        if hazards == before {
+            continue;
+        }
+        if selected {
+            for index in hazards.iter().filter(|index| !before.contains(index)) {
+                alerts.send(Alert {
+                    player: owner.player,
+                    position: transform.translation,
+                    message: format!(
+                        "{} entered a {}",
+                        unit_information.unit_name,
+                        zones.0[*index].kind.to_string().to_lowercase()
+                    ),
+                });
+            }
+        }
+        if hazards.is_empty() {
+            commands.entity(entity).remove::<InHazard>();
+        } else {
+            commands
+                .entity(entity)
+                .insert(InHazard::new(&zones, hazards));
+        }
+    }
+}
+
+/// Shields recharge everywhere, except in a hazard that disables them
+fn recharge_shields(time: Res<Time>, mut shields: Query<(&mut Shield, Option<&InHazard>)>) {
+    let delta: f32 = time.delta_seconds();
+    for (mut shield, in_hazard) in shields.iter_mut() {
+        if in_hazard.is_some_and(|in_hazard| in_hazard.shields_disabled) {
+            shield.current = 0.0;
+        } else if shield.current < shield.max {
+            shield.current =
+                (shield.current + shield.max * SHIELD_RECHARGE * delta).min(shield.max);
+        }
+    }
+}
+
+fn apply_hazard_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut units: Query<(
+        Entity,
+        &UnitInformation,
+        &Owner,
+        &Transform,
+        &mut Health,
+        Option<&mut Shield>,
+        &InHazard,
+    )>,
+    mut alerts: EventWriter<Alert>,
+) {
+    let delta: f32 = time.delta_seconds();
+    for (entity, unit_information, owner, transform, mut health, shield, in_hazard) in
+        units.iter_mut()
+    {
+        let mut damage: f32 = in_hazard.damage_per_second * delta;
+        if let Some(mut shield) = shield {
+            damage = shield.absorb(damage);
+        }
+        if damage <= 0.0 {
+            continue;
+        }
+        health.current -= damage;
+        if health.current <= 0.0 {
+            alerts.send(Alert {
+                player: owner.player,
+                position: transform.translation,
+                message: format!("{} was destroyed by a hazard", unit_information.unit_name),
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn draw_hazards(mut gizmos: Gizmos, zones: Res<HazardZones>) {
+    for hazard in &zones.0 {
+        gizmos.circle(
+            hazard.area.centre.extend(HAZARD_HEIGHT).xzy(),
+            Dir3::Y,
+            hazard.area.radius,
+            hazard.kind.colour(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{GridSettings, MovementGrid, OBSTACLE};
+
+    fn hazard(kind: HazardKind, centre: Vec2, speed: f32) -> Hazard {
+        Hazard {
+            kind,
+            area: GridArea {
+                centre,
+                radius: 5.0,
+            },
+            damage_per_second: 2.0,
+            disables_shields: kind == HazardKind::IonStorm,
+            speed,
+            path_cost: DEFAULT_PATH_COST,
+        }
+    }
+
+    #[test]
+    fn overlapping_hazards_combine_their_effects() {
+        let zones: HazardZones = HazardZones(vec![
+            hazard(HazardKind::RadiationBelt, Vec2::ZERO, 0.8),
+            hazard(HazardKind::IonStorm, Vec2::new(6.0, 0.0), 0.5),
+            hazard(HazardKind::StellarCorona, Vec2::new(40.0, 0.0), 1.0),
+        ]);
+        assert_eq!(zones.at(Vec2::new(-2.0, 0.0)), vec![0]);
+        assert_eq!(zones.at(Vec2::new(3.0, 0.0)), vec![0, 1]);
+        assert!(zones.at(Vec2::new(20.0, 0.0)).is_empty());
+        let in_hazard: InHazard = InHazard::new(&zones, zones.at(Vec2::new(3.0, 0.0)));
+        assert_eq!(
+            in_hazard.kinds,
+            vec![HazardKind::RadiationBelt, HazardKind::IonStorm]
+        );
+        assert_eq!(in_hazard.damage_per_second, 4.0);
+        assert!(in_hazard.shields_disabled);
+        assert_eq!(in_hazard.speed, 0.5);
+    }
+
+    #[test]
+    fn hazards_are_costly_but_passable() {
+        let mut grid: MovementGrid = MovementGrid::new(GridSettings {
+            cell_size: 1.0,
+            grid_width: 20,
+            grid_height: 20,
+            xy_offset: Vec2::splat(10.0),
+            density: 0.0,
+        });
+        let zone: CostZone = CostZone {
+            area: GridArea {
+                centre: Vec2::ZERO,
+                radius: 8.0,
+            },
+            cost: 5,
+        };
+        let storm: Hazard = hazard(HazardKind::IonStorm, Vec2::new(0.5, 0.5), 1.0);
+        grid.rebuild(&[], &[vec![zone], hazard_cost_zones(&[storm])].concat());
+        let cell = |x: f32| grid.settings.world_to_cell(Vec2::new(x, 0.5)).unwrap();
+        assert_eq!(grid.cost(cell(0.5)), DEFAULT_PATH_COST);
+        assert!(!grid.is_blocked(cell(0.5)));
+        const { assert!(DEFAULT_PATH_COST < OBSTACLE) };
+        assert_eq!(grid.cost(cell(7.5)), 5);
+    }
+}
@@ -0,0 +1,581 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use bevy::time::TimeUpdateStrategy;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use orthros_be::abilities::Abilities;
+use orthros_be::ai::{AiOpponent, Difficulty};
+use orthros_be::ai_script::AiScript;
+use orthros_be::civilisation::{CivilisationPlugin, Civilisations};
+use orthros_be::combat::Combat;
+use orthros_be::environment::{
+    setup_movement_grid, spawn_prop, spawn_resource_source, MovementGrid,
+};
+use orthros_be::galaxy::{spawn_system_contents, use_jump_gates, Galaxy};
+use orthros_be::hazards::Hazards;
+use orthros_be::large_world::LargeWorld;
+use orthros_be::map_generation::{generate_map, MapParameters};
+use orthros_be::movable::UnitMovement;
+use orthros_be::orbits::Orbits;
+use orthros_be::ownable::Owner;
+use orthros_be::player_controller::{Alert, Civilisation, DeselectEvent, PlayerInfo, PlayerNumber};
+use orthros_be::production::Production;
+use orthros_be::research::{Research, TechTree};
+use orthros_be::resource_collection::ResourceCollection;
+use orthros_be::resources::{ResourceSourceId, ResourceStockpiles, ResourceType};
+use orthros_be::scenario::{spawn_players, PlayerOverrides, Scenario, DEFAULT_SCENARIO_PATH};
+use orthros_be::simulation::{Simulation, StateHash, DEFAULT_TICK_RATE};
+use orthros_be::spawner::{InstanceSpawnRequest, InstanceSpawner, UnitInformation, UnitType};
+
+/// Ten minutes of match time at the default tick rate
+const DEFAULT_TICKS: u64 = 18_000;
+/// Ticks between two rows of the curves, one second at the default tick rate
+const DEFAULT_SAMPLE_EVERY: u64 = 30;
+const UNIT_TYPES: [UnitType; 3] = [
+    UnitType::Spacestation,
+    UnitType::Cruiser,
+    UnitType::MiningStation,
+];
+const RESOURCE_TYPES: [ResourceType; 1] = [ResourceType::Plotanium];
+/// Every flag is followed by its value
+const VALUE_FLAGS: [&str; 15] = [
+    "--scenario",
+    "--ai-script",
+    "--civilisation",
+    "--curves",
+    "--outcomes",
+    "--difficulty",
+    "--runs",
+    "--ticks",
+    "--sample-every",
+    "--tick-rate",
+    "--seed",
+    "--players",
+    "--map-size",
+    "--resource-richness",
+    "--belt-density",
+];
+
+/// Runs matches without a window or renderer, as fast as the simulation allows, with every
+/// player in the hands of the AI. Meant for comparing balance changes over many runs.
+#[derive(Resource, Clone)]
+struct HeadlessOptions {
+    scenario: String,
+    /// Generate the map instead of loading the scenario. Every run takes the next seed.
+    map_generation: Option<MapParameters>,
+    runs: u64,
+    ticks: u64,
+    tick_rate: f64,
+    sample_every: u64,
+    /// Build order for each player in scenario order, instead of the built-in one of its
+    /// difficulty. The last one is repeated for any players beyond the list.
+    ai_scripts: Vec<String>,
+    /// For the players the scenario doesn't give an AI
+    difficulty: Difficulty,
+    civilisation: Option<Civilisation>,
+    /// CSV file for the resource and unit curves
+    curves: Option<String>,
+    /// CSV file for the outcome of every run
+    outcomes: Option<String>,
+}
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        HeadlessOptions {
+            scenario: DEFAULT_SCENARIO_PATH.into(),
+            map_generation: None,
+            runs: 1,
+            ticks: DEFAULT_TICKS,
+            tick_rate: DEFAULT_TICK_RATE,
+            sample_every: DEFAULT_SAMPLE_EVERY,
+            ai_scripts: Vec::new(),
+            difficulty: Difficulty::Normal,
+            civilisation: None,
+            curves: None,
+            outcomes: None,
+        }
+    }
+}
+fn parse_options(mut args: impl Iterator<Item = String>) -> HeadlessOptions {
+    let mut options: HeadlessOptions = HeadlessOptions::default();
+    while let Some(arg) = args.next() {
+        // Anything else is left alone instead of taking the next argument with it
+        if !VALUE_FLAGS.contains(&arg.as_str()) {
+            println!("Unknown argument {}", arg);
+            continue;
+        }
+        let Some(value) = args.next() else {
+            println!("Missing value for {}", arg);
+            break;
+        };
+        let parsed: bool = match arg.as_str() {
+            "--scenario" => {
+                options.scenario = value.clone();
+                true
+            }
+            "--ai-script" => {
+                options.ai_scripts.push(value.clone());
+                true
+            }
+            "--civilisation" => {
+                options.civilisation = Some(Civilisation(value.clone()));
+                true
+            }
+            "--curves" => {
+                options.curves = Some(value.clone());
+                true
+            }
+            "--outcomes" => {
+                options.outcomes = Some(value.clone());
+                true
+            }
+            "--difficulty" => match value.as_str() {
+                "easy" => Some(Difficulty::Easy),
+                "normal" => Some(Difficulty::Normal),
+                "hard" => Some(Difficulty::Hard),
+                _ => None,
+            }
+            .map(|difficulty| options.difficulty = difficulty)
+            .is_some(),
+            "--runs" => value.parse().map(|runs| options.runs = runs).is_ok(),
+            "--ticks" => value.parse().map(|ticks| options.ticks = ticks).is_ok(),
+            "--sample-every" => match value.parse::<u64>() {
+                Ok(ticks) if ticks > 0 => {
+                    options.sample_every = ticks;
+                    true
+                }
+                _ => false,
+            },
+            "--tick-rate" => match value.parse::<f64>() {
+                Ok(rate) if rate > 0.0 && rate.is_finite() => {
+                    options.tick_rate = rate;
+                    true
+                }
+                _ => false,
+            },
+            "--seed" | "--players" | "--map-size" | "--resource-richness" | "--belt-density" => {
+                let parameters: &mut MapParameters = options
+                    .map_generation
+                    .get_or_insert_with(MapParameters::default);
+                parameters.set(&arg, &value)
+            }
+            _ => unreachable!("{} is not a flag with a value", arg),
+        };
+        if !parsed {
+            println!("Invalid value {} for {}", value, arg);
+        }
+    }
+    options
+}
+
+/// Everything a player had over the course of a run
+#[derive(Default)]
+struct PlayerTally {
+    starting_units: usize,
+    seen: HashSet<Entity>,
+    alive: HashSet<Entity>,
+}
+impl PlayerTally {
+    fn built(&self) -> usize {
+        self.seen.len().saturating_sub(self.starting_units)
+    }
+    /// Destroyed by enemy weapons or hazards
+    fn lost(&self) -> usize {
+        self.seen.len() - self.alive.len()
+    }
+}
+/// A player as it stands after a tick
+struct PlayerState {
+    entity: Entity,
+    number: usize,
+    civilisation: Civilisation,
+    resources: Vec<i32>,
+    unit_counts: Vec<usize>,
+}
+
+fn setup_players(
+    mut commands: Commands,
+    mut spawn_events: EventWriter<InstanceSpawnRequest>,
+    options: Res<HeadlessOptions>,
+    scenario: Res<Scenario>,
+    civilisations: Res<Civilisations>,
+    tech_tree: Res<TechTree>,
+) {
+    // The local player stays marked as such, nobody is watching but some systems expect
+    // someone to be
+    let overrides: PlayerOverrides = PlayerOverrides {
+        civilisation: options.civilisation.clone(),
+        ai_civilisation: options.civilisation.clone(),
+        ai_scripts: AiScript::load_all(&options.ai_scripts),
+        ai_for_all: Some(options.difficulty),
+    };
+    spawn_players(
+        &mut commands,
+        &mut spawn_events,
+        &scenario,
+        &overrides,
+        &civilisations,
+        &tech_tree,
+    );
+}
+
+/// The props and resources of every system, without the ground and lights around them
+fn setup_systems(mut commands: Commands, asset_server: Res<AssetServer>, scenario: Res<Scenario>) {
+    for prop in &scenario.props {
+        spawn_prop(&mut commands, &asset_server, prop);
+    }
+    for (index, resource_source) in scenario.resource_sources.iter().enumerate() {
+        spawn_resource_source(
+            &mut commands,
+            &asset_server,
+            resource_source,
+            ResourceSourceId { system: 0, index },
+        );
+    }
+    for (index, system) in scenario.systems.iter().enumerate() {
+        spawn_system_contents(&mut commands, &asset_server, index + 1, system);
+    }
+}
+
+fn build_app(options: &HeadlessOptions, scenario: Scenario) -> App {
+    let mut app: App = App::new();
+    let galaxy: Galaxy = Galaxy::from_scenario(&scenario);
+    // Every update is exactly one tick
+    let tick: Duration = Duration::from_secs_f64(1.0 / options.tick_rate);
+    app.insert_resource(MovementGrid::new(scenario.grid.clone()))
+        .insert_resource(galaxy)
+        // Before the plugins, which set up the map from it
+        .insert_resource(scenario)
+        .insert_resource(Time::<Fixed>::from_duration(tick))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(tick))
+        .insert_resource(options.clone())
+        // Props only need their scenes to be known, never loaded
+        .add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .init_asset::<Scene>()
+        .add_plugins((
+            UnitMovement,
+            ResourceCollection,
+            CivilisationPlugin,
+            AiOpponent,
+            Production,
+            Research,
+            Abilities,
+            Orbits,
+            LargeWorld,
+            Hazards,
+            Combat,
+            InstanceSpawner,
+            Simulation,
+        ))
+        .add_event::<DeselectEvent>()
+        .add_event::<Alert>()
+        .add_systems(Startup, (setup_movement_grid, setup_systems, setup_players))
+        .add_systems(FixedUpdate, use_jump_gates);
+    app
+}
+
+fn player_states(app: &mut App, tallies: &mut HashMap<Entity, PlayerTally>) -> Vec<PlayerState> {
+    let mut units = app
+        .world_mut()
+        .query::<(Entity, &Owner, &UnitInformation)>();
+    let mut unit_counts: HashMap<Entity, Vec<usize>> = HashMap::new();
+    for tally in tallies.values_mut() {
+        tally.alive.clear();
+    }
+    for (entity, owner, unit_information) in units.iter(app.world()) {
+        let tally: &mut PlayerTally = tallies.entry(owner.player).or_default();
+        tally.seen.insert(entity);
+        tally.alive.insert(entity);
+        let counts: &mut Vec<usize> = unit_counts
+            .entry(owner.player)
+            .or_insert_with(|| vec![0; UNIT_TYPES.len()]);
+        if let Some(index) = UNIT_TYPES
+            .iter()
+            .position(|unit_type| *unit_type == unit_information.unit_type)
+        {
+            counts[index] += 1;
+        }
+    }
+    let mut players = app
+        .world_mut()
+        .query::<(Entity, &PlayerNumber, &PlayerInfo, &ResourceStockpiles)>();
+    let mut states: Vec<PlayerState> = players
+        .iter(app.world())
+        .map(|(entity, number, player_info, stockpiles)| PlayerState {
+            entity,
+            number: number.0,
+            civilisation: player_info.civilisation.clone(),
+            resources: RESOURCE_TYPES
+                .iter()
+                .map(|resource_type| stockpiles.0.get(resource_type).copied().unwrap_or(0))
+                .collect(),
+            unit_counts: unit_counts
+                .remove(&entity)
+                .unwrap_or_else(|| vec![0; UNIT_TYPES.len()]),
+        })
+        .collect();
+    states.sort_by_key(|state| state.number);
+    states
+}
+
+fn curves_header() -> String {
+    let mut columns: Vec<String> = ["run", "tick", "seconds", "player", "civilisation"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    columns.extend(
+        RESOURCE_TYPES
+            .iter()
+            .map(|resource_type| format!("{:?}", resource_type)),
+    );
+    columns.extend(
+        UNIT_TYPES
+            .iter()
+            .map(|unit_type| format!("{:?}", unit_type)),
+    );
+    columns.extend(["built", "lost", "state_hash"].map(String::from));
+    columns.join(",")
+}
+fn outcomes_header() -> String {
+    let mut columns: Vec<String> = ["run", "ticks", "player", "civilisation"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    columns.extend(
+        RESOURCE_TYPES
+            .iter()
+            .map(|resource_type| format!("{:?}", resource_type)),
+    );
+    columns.extend(
+        [
+            "alive",
+            "built",
+            "lost",
+            "eliminated",
+            "winner",
+            "state_hash",
+        ]
+        .map(String::from),
+    );
+    columns.join(",")
+}
+/// The columns every row shares: resources, then units
+fn player_columns(state: &PlayerState) -> Vec<String> {
+    state
+        .resources
+        .iter()
+        .map(|amount| amount.to_string())
+        .chain(state.unit_counts.iter().map(|count| count.to_string()))
+        .collect()
+}
+
+/// Plays one match to the end, writes its rows and returns the state it ended in
+fn run_match(
+    options: &HeadlessOptions,
+    run: u64,
+    scenario: Scenario,
+    curves: &mut Option<impl Write>,
+    outcomes: &mut Option<impl Write>,
+) -> Result<StateHash, Box<dyn Error>> {
+    let starting_units: Vec<usize> = scenario
+        .players
+        .iter()
+        .map(|player| player.units.len())
+        .collect();
+    let mut app: App = build_app(options, scenario);
+    app.finish();
+    app.cleanup();
+    // Only runs the startup systems, the clock starts with the next update
+    app.update();
+    let mut players = app.world_mut().query::<(Entity, &PlayerNumber)>();
+    let mut tallies: HashMap<Entity, PlayerTally> = players
+        .iter(app.world())
+        .map(|(entity, number)| {
+            let tally: PlayerTally = PlayerTally {
+                starting_units: starting_units[number.0 - 1],
+                ..default()
+            };
+            (entity, tally)
+        })
+        .collect();
+    let started: Instant = Instant::now();
+    let mut next_sample: u64 = options.sample_every;
+    let mut state_hash: StateHash = StateHash::default();
+    let mut states: Vec<PlayerState> = Vec::new();
+    while state_hash.tick < options.ticks {
+        app.update();
+        let after: StateHash = *app.world().resource::<StateHash>();
+        if after.tick == state_hash.tick {
+            continue;
+        }
+        state_hash = after;
+        states = player_states(&mut app, &mut tallies);
+        if state_hash.tick < next_sample && state_hash.tick < options.ticks {
+            continue;
+        }
+        next_sample = state_hash.tick + options.sample_every;
+        let Some(curves) = curves else {
+            continue;
+        };
+        for state in &states {
+            let tally: &PlayerTally = &tallies[&state.entity];
+            let mut row: Vec<String> = vec![
+                run.to_string(),
+                state_hash.tick.to_string(),
+                format!("{:.3}", state_hash.tick as f64 / options.tick_rate),
+                state.number.to_string(),
+                state.civilisation.to_string(),
+            ];
+            row.extend(player_columns(state));
+            row.extend([
+                tally.built().to_string(),
+                tally.lost().to_string(),
+                format!("{:016x}", state_hash.hash),
+            ]);
+            writeln!(curves, "{}", row.join(","))?;
+        }
+    }
+    // The winner is whoever still has units left after the fighting, if it's only one of them
+    let survivors: Vec<usize> = states
+        .iter()
+        .filter(|state| state.unit_counts.iter().sum::<usize>() > 0)
+        .map(|state| state.number)
+        .collect();
+    println!(
+        "Run {}: {} ticks in {:.2}s, state hash {:016x}",
+        run,
+        state_hash.tick,
+        started.elapsed().as_secs_f64(),
+        state_hash.hash
+    );
+    for state in &states {
+        let tally: &PlayerTally = &tallies[&state.entity];
+        let alive: usize = state.unit_counts.iter().sum();
+        let eliminated: bool = !survivors.contains(&state.number);
+        let winner: bool = survivors == [state.number];
+        println!(
+            "  Player {} ({}): {:?} resources, {} units alive, {} built, {} lost{}{}",
+            state.number,
+            state.civilisation,
+            state.resources,
+            alive,
+            tally.built(),
+            tally.lost(),
+            if eliminated { ", eliminated" } else { "" },
+            if winner { ", winner" } else { "" },
+        );
+        if let Some(outcomes) = outcomes {
+            let mut row: Vec<String> = vec![
+                run.to_string(),
+                state_hash.tick.to_string(),
+                state.number.to_string(),
+                state.civilisation.to_string(),
+            ];
+            row.extend(state.resources.iter().map(|amount| amount.to_string()));
+            row.extend([
+                alive.to_string(),
+                tally.built().to_string(),
+                tally.lost().to_string(),
+                eliminated.to_string(),
+                winner.to_string(),
+                format!("{:016x}", state_hash.hash),
+            ]);
+            writeln!(outcomes, "{}", row.join(","))?;
+        }
+    }
+    if let Some(curves) = curves {
+        curves.flush()?;
+    }
+    if let Some(outcomes) = outcomes {
+        outcomes.flush()?;
+    }
+    Ok(state_hash)
+}
+
+fn create_report(path: &Option<String>, header: String) -> Option<BufWriter<File>> {
+    let path: &String = path.as_ref()?;
+    match File::create(path) {
+        Ok(file) => {
+            let mut writer: BufWriter<File> = BufWriter::new(file);
+            match writeln!(writer, "{}", header) {
+                Ok(()) => Some(writer),
+                Err(e) => {
+                    println!("Could not write {}: {}", path, e);
+                    None
+                }
+            }
+        }
+        Err(e) => {
+            println!("Could not create {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn main() {
+    let options: HeadlessOptions = parse_options(std::env::args().skip(1));
+    let mut curves: Option<BufWriter<File>> = create_report(&options.curves, curves_header());
+    let mut outcomes: Option<BufWriter<File>> = create_report(&options.outcomes, outcomes_header());
+    for run in 0..options.runs {
+        let scenario: Scenario = match &options.map_generation {
+            Some(parameters) => generate_map(&MapParameters {
+                seed: parameters.seed.wrapping_add(run),
+                ..parameters.clone()
+            }),
+            None => match Scenario::load(&options.scenario) {
+                Ok(scenario) => scenario,
+                Err(e) => {
+                    println!("Could not load scenario {}: {}", options.scenario, e);
+                    return;
+                }
+            },
+        };
+        if let Err(e) = run_match(&options, run, scenario, &mut curves, &mut outcomes) {
+            println!("Could not write the reports of run {}: {}", run, e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn short_match() -> (StateHash, String) {
+        let options: HeadlessOptions = HeadlessOptions {
+            ticks: 300,
+            ..default()
+        };
+        let scenario: Scenario = Scenario::load(&options.scenario).unwrap();
+        let mut curves: Option<Vec<u8>> = Some(Vec::new());
+        let state_hash: StateHash =
+            run_match(&options, 0, scenario, &mut curves, &mut None::<Vec<u8>>).unwrap();
+        (state_hash, String::from_utf8(curves.unwrap()).unwrap())
+    }
+
+    #[test]
+    fn runs_of_the_same_scenario_are_identical() {
+        let (state_hash, curves) = short_match();
+        assert_eq!(state_hash.tick, 300);
+        // A row per player for every sample
+        let players: usize = Scenario::load(DEFAULT_SCENARIO_PATH).unwrap().players.len();
+        assert_eq!(curves.lines().count(), 10 * players);
+        assert_eq!(short_match(), (state_hash, curves));
+    }
+
+    #[test]
+    fn unknown_flags_leave_the_next_one_alone() {
+        let args: Vec<String> = ["--verbose", "--runs", "3", "--ticks", "many", "--seed"]
+            .map(String::from)
+            .to_vec();
+        let options: HeadlessOptions = parse_options(args.into_iter());
+        assert_eq!(options.runs, 3);
+        assert_eq!(options.ticks, DEFAULT_TICKS);
+        assert!(options.map_generation.is_none());
+    }
+}
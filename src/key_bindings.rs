@@ -0,0 +1,790 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::{error::Error, fs, path::Path};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::control_groups::CONTROL_GROUPS;
+use crate::player_controller::CAMERA_BOOKMARKS;
+
+pub const KEY_BINDINGS_PATH: &str = "./settings/key_bindings.ron";
+/// The keys the civilisation files assign to context menu entries
+pub const CONTEXT_MENU_KEYS: [KeyCode; 8] = [
+    KeyCode::KeyQ,
+    KeyCode::KeyE,
+    KeyCode::KeyR,
+    KeyCode::KeyZ,
+    KeyCode::KeyX,
+    KeyCode::KeyC,
+    KeyCode::KeyV,
+    KeyCode::KeyH,
+];
+
+const SCREEN_BACKGROUND: Color = Color::srgba(12.0 / 256.0, 11.0 / 256.0, 13.0 / 256.0, 0.95);
+const SCREEN_TEXT: Color = Color::srgb(
+    0x81 as f32 / 256.0,
+    0xC1 as f32 / 256.0,
+    0x14 as f32 / 256.0,
+);
+const CONFLICT_TEXT: Color = Color::srgb(0.9, 0.2, 0.1);
+const LISTENING_TEXT: Color = Color::WHITE;
+const ROW_BUTTON: Color = Color::srgb(0.1, 0.1, 0.12);
+const HOVERED_ROW_BUTTON: Color = Color::srgb(64.0 / 256.0, 99.0 / 256.0, 64.0 / 256.0);
+
+/// Everything the player can do with keyboard and mouse, each bound to one or more buttons
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum InputAction {
+    CameraForward,
+    CameraBack,
+    CameraLeft,
+    CameraRight,
+    /// Held to turn the camera movement keys into rotation around the point in view
+    CameraRotate,
+    ToggleMouseMovement,
+    Select,
+    Command,
+    /// Held to add clicked or dragged units to the selection
+    AddToSelection,
+    /// Held to remove clicked or dragged units from the selection
+    SubtractFromSelection,
+    /// Held when clicking a portrait to keep every selected unit of its type
+    SelectWholeType,
+    /// Held when clicking a portrait to drop its unit from the selection
+    DropPortraitUnit,
+    SelectAllOfType,
+    SelectIdleCollectors,
+    SelectMilitary,
+    /// Held with a control group key to assign the selection to the group
+    AssignControlGroup,
+    ControlGroup(usize),
+    CameraBookmark(usize),
+    /// Held with a bookmark key to store the current view under it
+    SaveCameraBookmark,
+    FollowSelected,
+    JumpToAlert,
+    OpenKeyBindings,
+    QuickSave,
+    QuickLoad,
+    ToggleMapEditor,
+    ToggleGalaxyView,
+    SelfDestruct,
+    /// Triggers the context menu entry the game data assigns to this key
+    ContextMenuKey(KeyCode),
+    /// Closes the key binding screen or stops waiting for a new binding
+    Cancel,
+}
+impl InputAction {
+    pub fn all() -> Vec<InputAction> {
+        let mut actions: Vec<InputAction> = vec![
+            InputAction::CameraForward,
+            InputAction::CameraBack,
+            InputAction::CameraLeft,
+            InputAction::CameraRight,
+            InputAction::CameraRotate,
+            InputAction::ToggleMouseMovement,
+            InputAction::Select,
+            InputAction::Command,
+            InputAction::AddToSelection,
+            InputAction::SubtractFromSelection,
+            InputAction::SelectWholeType,
+            InputAction::DropPortraitUnit,
+            InputAction::SelectAllOfType,
+            InputAction::SelectIdleCollectors,
+            InputAction::SelectMilitary,
+            InputAction::AssignControlGroup,
+        ];
+        actions.extend((0..CONTROL_GROUPS).map(InputAction::ControlGroup));
+        actions.extend((0..CAMERA_BOOKMARKS).map(InputAction::CameraBookmark));
+        actions.extend([
+            InputAction::SaveCameraBookmark,
+            InputAction::FollowSelected,
+            InputAction::JumpToAlert,
+            InputAction::OpenKeyBindings,
+            InputAction::QuickSave,
+            InputAction::QuickLoad,
+            InputAction::ToggleMapEditor,
+            InputAction::ToggleGalaxyView,
+            InputAction::SelfDestruct,
+        ]);
+        actions.extend(CONTEXT_MENU_KEYS.map(InputAction::ContextMenuKey));
+        actions.push(InputAction::Cancel);
+        actions
+    }
+    /// Modifiers are only ever held together with another input, so they may share buttons
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self,
+            InputAction::CameraRotate
+                | InputAction::AddToSelection
+                | InputAction::SubtractFromSelection
+                | InputAction::SelectWholeType
+                | InputAction::DropPortraitUnit
+                | InputAction::AssignControlGroup
+                | InputAction::SaveCameraBookmark
+        )
+    }
+}
+impl fmt::Display for InputAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputAction::CameraForward => write!(f, "Camera forward"),
+            InputAction::CameraBack => write!(f, "Camera back"),
+            InputAction::CameraLeft => write!(f, "Camera left"),
+            InputAction::CameraRight => write!(f, "Camera right"),
+            InputAction::CameraRotate => write!(f, "Rotate camera (hold)"),
+            InputAction::ToggleMouseMovement => write!(f, "Toggle mouse movement"),
+            InputAction::Select => write!(f, "Select"),
+            InputAction::Command => write!(f, "Move / command"),
+            InputAction::AddToSelection => write!(f, "Add to selection (hold)"),
+            InputAction::SubtractFromSelection => write!(f, "Remove from selection (hold)"),
+            InputAction::SelectWholeType => write!(f, "Keep whole type (hold)"),
+            InputAction::DropPortraitUnit => write!(f, "Drop portrait unit (hold)"),
+            InputAction::SelectAllOfType => write!(f, "Select all of type"),
+            InputAction::SelectIdleCollectors => write!(f, "Select idle collectors"),
+            InputAction::SelectMilitary => write!(f, "Select military"),
+            InputAction::AssignControlGroup => write!(f, "Assign control group (hold)"),
+            InputAction::ControlGroup(group) => write!(f, "Control group {}", group + 1),
+            InputAction::CameraBookmark(bookmark) => {
+                write!(f, "Camera bookmark {}", bookmark + 1)
+            }
+            InputAction::SaveCameraBookmark => write!(f, "Save camera bookmark (hold)"),
+            InputAction::FollowSelected => write!(f, "Follow selected unit"),
+            InputAction::JumpToAlert => write!(f, "Jump to latest alert"),
+            InputAction::OpenKeyBindings => write!(f, "Key bindings"),
+            InputAction::QuickSave => write!(f, "Quick save"),
+            InputAction::QuickLoad => write!(f, "Quick load"),
+            InputAction::ToggleMapEditor => write!(f, "Map editor"),
+            InputAction::ToggleGalaxyView => write!(f, "Galaxy view"),
+            InputAction::SelfDestruct => write!(f, "Self-destruct"),
+            InputAction::ContextMenuKey(key) => {
+                write!(f, "Context menu key {}", Binding::Key(*key))
+            }
+            InputAction::Cancel => write!(f, "Cancel"),
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => {
+                let label: String = format!("{:?}", key);
+                write!(
+                    f,
+                    "{}",
+                    label
+                        .strip_prefix("Key")
+                        .or_else(|| label.strip_prefix("Digit"))
+                        .unwrap_or(&label)
+                )
+            }
+            Binding::Mouse(button) => write!(f, "Mouse {:?}", button),
+        }
+    }
+}
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub bindings: BTreeMap<InputAction, Vec<Binding>>,
+}
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut bindings: BTreeMap<InputAction, Vec<Binding>> = BTreeMap::from([
+            (
+                InputAction::CameraForward,
+                vec![Binding::Key(KeyCode::KeyW)],
+            ),
+            (InputAction::CameraBack, vec![Binding::Key(KeyCode::KeyS)]),
+            (InputAction::CameraLeft, vec![Binding::Key(KeyCode::KeyA)]),
+            (InputAction::CameraRight, vec![Binding::Key(KeyCode::KeyD)]),
+            (
+                InputAction::CameraRotate,
+                vec![Binding::Key(KeyCode::ControlLeft)],
+            ),
+            (
+                InputAction::ToggleMouseMovement,
+                vec![Binding::Key(KeyCode::KeyM)],
+            ),
+            (InputAction::Select, vec![Binding::Mouse(MouseButton::Left)]),
+            (
+                InputAction::Command,
+                vec![Binding::Mouse(MouseButton::Right)],
+            ),
+            (
+                InputAction::AddToSelection,
+                vec![Binding::Key(KeyCode::ShiftLeft)],
+            ),
+            (
+                InputAction::SubtractFromSelection,
+                vec![Binding::Key(KeyCode::AltLeft)],
+            ),
+            (
+                InputAction::SelectWholeType,
+                vec![
+                    Binding::Key(KeyCode::ControlLeft),
+                    Binding::Key(KeyCode::ControlRight),
+                ],
+            ),
+            (
+                InputAction::DropPortraitUnit,
+                vec![
+                    Binding::Key(KeyCode::ShiftLeft),
+                    Binding::Key(KeyCode::ShiftRight),
+                ],
+            ),
+            (
+                InputAction::SelectAllOfType,
+                vec![Binding::Key(KeyCode::KeyT)],
+            ),
+            (
+                InputAction::SelectIdleCollectors,
+                vec![Binding::Key(KeyCode::Period)],
+            ),
+            (
+                InputAction::SelectMilitary,
+                vec![Binding::Key(KeyCode::Comma)],
+            ),
+            (
+                InputAction::AssignControlGroup,
+                vec![
+                    Binding::Key(KeyCode::ControlLeft),
+                    Binding::Key(KeyCode::ControlRight),
+                ],
+            ),
+            (
+                InputAction::SaveCameraBookmark,
+                vec![
+                    Binding::Key(KeyCode::ControlLeft),
+                    Binding::Key(KeyCode::ControlRight),
+                ],
+            ),
+            (
+                InputAction::FollowSelected,
+                vec![Binding::Key(KeyCode::KeyF)],
+            ),
+            (InputAction::JumpToAlert, vec![Binding::Key(KeyCode::Space)]),
+            (
+                InputAction::OpenKeyBindings,
+                vec![Binding::Key(KeyCode::F10)],
+            ),
+            (InputAction::QuickSave, vec![Binding::Key(KeyCode::F11)]),
+            (InputAction::QuickLoad, vec![Binding::Key(KeyCode::F12)]),
+            (
+                InputAction::ToggleMapEditor,
+                vec![Binding::Key(KeyCode::F9)],
+            ),
+            (
+                InputAction::ToggleGalaxyView,
+                vec![Binding::Key(KeyCode::KeyG)],
+            ),
+            (
+                InputAction::SelfDestruct,
+                vec![Binding::Key(KeyCode::Delete)],
+            ),
+            (InputAction::Cancel, vec![Binding::Key(KeyCode::Escape)]),
+        ]);
+        let digits: [KeyCode; CONTROL_GROUPS] = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+        ];
+        for (group, digit) in digits.into_iter().enumerate() {
+            bindings.insert(InputAction::ControlGroup(group), vec![Binding::Key(digit)]);
+        }
+        let function_keys: [KeyCode; CAMERA_BOOKMARKS] = [
+            KeyCode::F1,
+            KeyCode::F2,
+            KeyCode::F3,
+            KeyCode::F4,
+            KeyCode::F5,
+            KeyCode::F6,
+            KeyCode::F7,
+            KeyCode::F8,
+        ];
+        for (bookmark, key) in function_keys.into_iter().enumerate() {
+            bindings.insert(
+                InputAction::CameraBookmark(bookmark),
+                vec![Binding::Key(key)],
+            );
+        }
+        for key in CONTEXT_MENU_KEYS {
+            bindings.insert(InputAction::ContextMenuKey(key), vec![Binding::Key(key)]);
+        }
+        KeyBindings { bindings }
+    }
+}
+impl KeyBindings {
+    pub fn load(path: &str) -> Result<KeyBindings, Box<dyn Error>> {
+        KeyBindings::parse(&fs::read_to_string(path)?)
+    }
+    fn parse(ron: &str) -> Result<KeyBindings, Box<dyn Error>> {
+        let mut key_bindings: KeyBindings = ron::from_str(ron)?;
+        // Actions added since the file was written keep their default bindings
+        for (action, bindings) in KeyBindings::default().bindings {
+            key_bindings.bindings.entry(action).or_insert(bindings);
+        }
+        Ok(key_bindings)
+    }
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(directory) = Path::new(path).parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(
+            path,
+            ron::ser::to_string_pretty(self, PrettyConfig::default())?,
+        )?;
+        Ok(())
+    }
+    /// Context menu keys without an entry of their own, from civilisation files using keys
+    /// outside `CONTEXT_MENU_KEYS`, stay bound to the key itself
+    pub fn get(&self, action: InputAction) -> Vec<Binding> {
+        match (self.bindings.get(&action), action) {
+            (Some(bindings), _) => bindings.clone(),
+            (None, InputAction::ContextMenuKey(key)) => vec![Binding::Key(key)],
+            (None, _) => Vec::new(),
+        }
+    }
+    /// Whether a binding of the action was just pressed, for systems that can't use
+    /// `InputActions` because the key binding screen silences it
+    pub fn just_pressed(
+        &self,
+        action: InputAction,
+        key_input: &ButtonInput<KeyCode>,
+        mouse_input: &ButtonInput<MouseButton>,
+    ) -> bool {
+        self.get(action).iter().any(|binding| match binding {
+            Binding::Key(key) => key_input.just_pressed(*key),
+            Binding::Mouse(button) => mouse_input.just_pressed(*button),
+        })
+    }
+    /// Binds the button to the action on top of its other bindings, or unbinds it if the
+    /// action already had it
+    pub fn toggle_binding(&mut self, action: InputAction, binding: Binding) {
+        let mut bindings: Vec<Binding> = self.get(action);
+        if let Some(index) = bindings.iter().position(|b| *b == binding) {
+            bindings.remove(index);
+        } else {
+            bindings.push(binding);
+        }
+        self.bindings.insert(action, bindings);
+    }
+    /// Buttons bound to two actions that could fire at the same time.
+    /// Only modifiers may share a button with each other.
+    pub fn conflicts(&self) -> Vec<(Binding, InputAction, InputAction)> {
+        let mut conflicts: Vec<(Binding, InputAction, InputAction)> = Vec::new();
+        for (i, (action, bindings)) in self.bindings.iter().enumerate() {
+            for (other, other_bindings) in self.bindings.iter().skip(i + 1) {
+                if action.is_modifier() && other.is_modifier() {
+                    continue;
+                }
+                for binding in bindings {
+                    if other_bindings.contains(binding) {
+                        conflicts.push((*binding, *action, *other));
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+}
+/// Whether the key binding screen is shown, and which action waits for a new binding
+#[derive(Resource, Default)]
+pub struct KeyBindingScreen {
+    pub open: bool,
+    listening: Option<InputAction>,
+}
+/// Read input by action instead of by button.
+/// Reports nothing while the key binding screen is open.
+#[derive(SystemParam)]
+pub struct InputActions<'w> {
+    key_bindings: Res<'w, KeyBindings>,
+    key_input: Res<'w, ButtonInput<KeyCode>>,
+    mouse_input: Res<'w, ButtonInput<MouseButton>>,
+    screen: Res<'w, KeyBindingScreen>,
+}
+impl InputActions<'_> {
+    fn any_binding(&self, action: InputAction, check: impl Fn(Binding) -> bool) -> bool {
+        !self.screen.open && self.key_bindings.get(action).iter().any(|b| check(*b))
+    }
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.any_binding(action, |binding| match binding {
+            Binding::Key(key) => self.key_input.pressed(key),
+            Binding::Mouse(button) => self.mouse_input.pressed(button),
+        })
+    }
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.any_binding(action, |binding| match binding {
+            Binding::Key(key) => self.key_input.just_pressed(key),
+            Binding::Mouse(button) => self.mouse_input.just_pressed(button),
+        })
+    }
+    pub fn just_released(&self, action: InputAction) -> bool {
+        self.any_binding(action, |binding| match binding {
+            Binding::Key(key) => self.key_input.just_released(key),
+            Binding::Mouse(button) => self.mouse_input.just_released(button),
+        })
+    }
+}
+
+pub struct KeyBindingsPlugin;
+impl Plugin for KeyBindingsPlugin {
+    fn build(&self, app: &mut App) {
+        let key_bindings: KeyBindings = match KeyBindings::load(KEY_BINDINGS_PATH) {
+            Ok(key_bindings) => key_bindings,
+            Err(e) => {
+                println!(
+                    "Could not load key bindings {}, using defaults: {}",
+                    KEY_BINDINGS_PATH, e
+                );
+                KeyBindings::default()
+            }
+        };
+        for (binding, action, other) in key_bindings.conflicts() {
+            println!(
+                "Key binding conflict: {} is bound to both {} and {}",
+                binding, action, other
+            );
+        }
+        app.insert_resource(key_bindings)
+            .init_resource::<KeyBindingScreen>()
+            .add_systems(
+                Update,
+                (
+                    toggle_key_binding_screen,
+                    capture_binding.after(toggle_key_binding_screen),
+                    key_binding_screen_buttons.after(capture_binding),
+                    refresh_key_binding_screen.after(key_binding_screen_buttons),
+                    close_key_binding_screen.after(key_binding_screen_buttons),
+                ),
+            );
+    }
+}
+#[derive(Component)]
+struct KeyBindingScreenRoot;
+#[derive(Component)]
+struct BindingRow(InputAction);
+#[derive(Component, Clone, Copy)]
+enum ScreenButton {
+    Save,
+    ResetDefaults,
+    Close,
+}
+
+fn screen_text(asset_server: &Res<AssetServer>, value: String) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font: asset_server.load("fonts/android-insomnia-font/AndroidInsomniaRegular.ttf"),
+            font_size: 14.0,
+            color: SCREEN_TEXT,
+        },
+    )
+}
+fn row_button(width: f32) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            width: Val::Px(width),
+            margin: UiRect::all(Val::Px(2.0)),
+            padding: UiRect::all(Val::Px(3.0)),
+            ..default()
+        },
+        background_color: ROW_BUTTON.into(),
+        ..default()
+    }
+}
+fn spawn_key_binding_screen(commands: &mut Commands, asset_server: &Res<AssetServer>) {
+    commands
+        .spawn((
+            KeyBindingScreenRoot,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(15.0),
+                    top: Val::Percent(10.0),
+                    width: Val::Percent(70.0),
+                    height: Val::Percent(75.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: SCREEN_BACKGROUND.into(),
+                z_index: ZIndex::Global(20),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(screen_text(
+                asset_server,
+                "Key bindings - click an action, then press a key or button to add it, or one it already has to remove it (Cancel stops)"
+                    .to_owned(),
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        flex_wrap: FlexWrap::Wrap,
+                        flex_grow: 1.0,
+                        margin: UiRect::vertical(Val::Px(8.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for action in InputAction::all() {
+                        parent
+                            .spawn((row_button(380.0), BindingRow(action)))
+                            .with_children(|parent| {
+                                parent.spawn(screen_text(asset_server, String::new()));
+                            });
+                    }
+                });
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (button, label) in [
+                        (ScreenButton::Save, "Save"),
+                        (ScreenButton::ResetDefaults, "Reset to defaults"),
+                        (ScreenButton::Close, "Close"),
+                    ] {
+                        parent
+                            .spawn((row_button(150.0), button))
+                            .with_children(|parent| {
+                                parent.spawn(screen_text(asset_server, label.to_owned()));
+                            });
+                    }
+                });
+        });
+}
+/// The screen can't use `InputActions` to open and close itself, as it silences them
+fn toggle_key_binding_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    key_bindings: Res<KeyBindings>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut screen: ResMut<KeyBindingScreen>,
+) {
+    if screen.listening.is_some() {
+        return;
+    }
+    let toggle: bool =
+        key_bindings.just_pressed(InputAction::OpenKeyBindings, &key_input, &mouse_input);
+    if !screen.open {
+        if toggle {
+            screen.open = true;
+            spawn_key_binding_screen(&mut commands, &asset_server);
+        }
+        return;
+    }
+    if toggle || key_bindings.just_pressed(InputAction::Cancel, &key_input, &mouse_input) {
+        screen.open = false;
+    }
+}
+fn close_key_binding_screen(
+    mut commands: Commands,
+    screen: Res<KeyBindingScreen>,
+    roots: Query<Entity, With<KeyBindingScreenRoot>>,
+) {
+    if screen.open {
+        return;
+    }
+    for root in roots.iter() {
+        commands.entity(root).despawn_recursive();
+    }
+}
+/// Adds the next key or mouse button pressed to the bindings of the action waiting for it,
+/// or removes it if the action already had it
+fn capture_binding(
+    mut key_bindings: ResMut<KeyBindings>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut screen: ResMut<KeyBindingScreen>,
+) {
+    let Some(action) = screen.listening else {
+        return;
+    };
+    if key_bindings.just_pressed(InputAction::Cancel, &key_input, &mouse_input) {
+        screen.listening = None;
+        return;
+    }
+    let binding: Option<Binding> = key_input
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse_input
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        });
+    if let Some(binding) = binding {
+        key_bindings.toggle_binding(action, binding);
+        screen.listening = None;
+    }
+}
+fn key_binding_screen_buttons(
+    mut key_bindings: ResMut<KeyBindings>,
+    mut screen: ResMut<KeyBindingScreen>,
+    mut rows: Query<
+        (&Interaction, &BindingRow, &mut BackgroundColor),
+        (Changed<Interaction>, Without<ScreenButton>),
+    >,
+    mut buttons: Query<(&Interaction, &ScreenButton, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, row, mut background_color) in rows.iter_mut() {
+        match interaction {
+            Interaction::Pressed => screen.listening = Some(row.0),
+            Interaction::Hovered => *background_color = HOVERED_ROW_BUTTON.into(),
+            Interaction::None => *background_color = ROW_BUTTON.into(),
+        }
+    }
+    for (interaction, button, mut background_color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => match button {
+                ScreenButton::Save => match key_bindings.save(KEY_BINDINGS_PATH) {
+                    Ok(()) => println!("Saved key bindings to {}", KEY_BINDINGS_PATH),
+                    Err(e) => println!("Could not save key bindings {}: {}", KEY_BINDINGS_PATH, e),
+                },
+                ScreenButton::ResetDefaults => *key_bindings = KeyBindings::default(),
+                ScreenButton::Close => screen.open = false,
+            },
+            Interaction::Hovered => *background_color = HOVERED_ROW_BUTTON.into(),
+            Interaction::None => *background_color = ROW_BUTTON.into(),
+        }
+    }
+}
+fn refresh_key_binding_screen(
+    key_bindings: Res<KeyBindings>,
+    screen: Res<KeyBindingScreen>,
+    rows: Query<(&BindingRow, &Children)>,
+    new_rows: Query<(), Added<BindingRow>>,
+    mut texts: Query<&mut Text>,
+) {
+    if !key_bindings.is_changed() && !screen.is_changed() && new_rows.is_empty() {
+        return;
+    }
+    let conflicts = key_bindings.conflicts();
+    for (row, children) in rows.iter() {
+        let action: InputAction = row.0;
+        let (value, color): (String, Color) = if screen.listening == Some(action) {
+            (format!("{}: press a key...", action), LISTENING_TEXT)
+        } else {
+            let bindings: Vec<String> = key_bindings
+                .get(action)
+                .iter()
+                .map(|binding| binding.to_string())
+                .collect();
+            let conflicting: bool = conflicts
+                .iter()
+                .any(|(_, a, b)| *a == action || *b == action);
+            (
+                format!("{}: {}", action, bindings.join(" / ")),
+                if conflicting {
+                    CONFLICT_TEXT
+                } else {
+                    SCREEN_TEXT
+                },
+            )
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = value.clone();
+                text.sections[0].style.color = color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_have_no_conflicts() {
+        assert_eq!(KeyBindings::default().conflicts(), Vec::new());
+    }
+
+    #[test]
+    fn actions_sharing_a_button_conflict_unless_both_are_modifiers() {
+        let mut key_bindings: KeyBindings = KeyBindings::default();
+        key_bindings.toggle_binding(InputAction::QuickSave, Binding::Key(KeyCode::KeyT));
+        key_bindings.toggle_binding(
+            InputAction::AddToSelection,
+            Binding::Key(KeyCode::ControlLeft),
+        );
+        assert_eq!(
+            key_bindings.conflicts(),
+            vec![(
+                Binding::Key(KeyCode::KeyT),
+                InputAction::SelectAllOfType,
+                InputAction::QuickSave
+            )]
+        );
+    }
+
+    #[test]
+    fn capturing_a_binding_adds_it_or_removes_it_again() {
+        let mut key_bindings: KeyBindings = KeyBindings::default();
+        key_bindings.toggle_binding(InputAction::Command, Binding::Key(KeyCode::KeyK));
+        assert_eq!(
+            key_bindings.get(InputAction::Command),
+            vec![
+                Binding::Mouse(MouseButton::Right),
+                Binding::Key(KeyCode::KeyK)
+            ]
+        );
+        key_bindings.toggle_binding(InputAction::Command, Binding::Mouse(MouseButton::Right));
+        assert_eq!(
+            key_bindings.get(InputAction::Command),
+            vec![Binding::Key(KeyCode::KeyK)]
+        );
+    }
+
+    #[test]
+    fn loading_keeps_saved_bindings_and_fills_in_missing_actions() {
+        let key_bindings: KeyBindings = KeyBindings::parse(
+            "(bindings: {CameraForward: [Key(ArrowUp)], ControlGroup(0): [Key(Numpad1)]})",
+        )
+        .unwrap();
+        assert_eq!(
+            key_bindings.get(InputAction::CameraForward),
+            vec![Binding::Key(KeyCode::ArrowUp)]
+        );
+        assert_eq!(
+            key_bindings.get(InputAction::ControlGroup(0)),
+            vec![Binding::Key(KeyCode::Numpad1)]
+        );
+        let defaults: KeyBindings = KeyBindings::default();
+        for action in InputAction::all() {
+            if action != InputAction::CameraForward && action != InputAction::ControlGroup(0) {
+                assert_eq!(key_bindings.get(action), defaults.get(action), "{}", action);
+            }
+        }
+    }
+
+    #[test]
+    fn context_menu_keys_outside_the_defaults_stay_bound_to_themselves() {
+        assert_eq!(
+            KeyBindings::default().get(InputAction::ContextMenuKey(KeyCode::KeyB)),
+            vec![Binding::Key(KeyCode::KeyB)]
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_rapier3d::prelude::{Collider, PhysicsSet, RigidBody};
+
+use crate::player_controller::CameraControllerSettings;
+
+/// Bodies further from the camera than this are drawn at this distance instead, scaled down so
+/// they keep their apparent size. Has to stay within the camera's far plane.
+pub const NEAR_FIELD: f64 = 800.0;
+/// Rapier works in `f32` around the origin, so bodies further out than this are kept out of it.
+/// Every star system and everything that flies lies well within.
+pub const PHYSICS_RANGE: f64 = 1_000_000.0;
+
+pub struct LargeWorld;
+impl Plugin for LargeWorld {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (
+                place_impostors.before(TransformSystem::TransformPropagate),
+                stow_far_physics.before(PhysicsSet::SyncBackend),
+            ),
+        );
+    }
+}
+/// Where a body is in the real, 1:1 layout of the system. Units and the movement grid stay close
+/// to the origin in plain `f32` coordinates, but planets and stars can be astronomically far away,
+/// beyond what a `Transform` can hold without jitter.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LargeWorldPosition {
+    pub position: DVec3,
+    pub scale: f32,
+}
+/// The model of a `LargeWorldPosition` body. It is a separate entity so it can be drawn wherever
+/// the camera needs it, while the body's own `Transform` and collider stay where the simulation
+/// put them.
+#[derive(Component)]
+pub struct Impostor {
+    pub body: Entity,
+}
+/// The physics of a body beyond `PHYSICS_RANGE`, taken off it until it comes back within range
+#[derive(Component)]
+pub struct StowedPhysics {
+    rigid_body: Option<RigidBody>,
+    collider: Option<Collider>,
+}
+impl LargeWorldPosition {
+    /// Translation and scale to draw the body with, as seen from the camera
+    pub fn rebased(&self, camera: DVec3) -> (Vec3, f32) {
+        // Relative to the camera in f64, so that nothing is lost before shrinking it
+        let relative: DVec3 = self.position - camera;
+        let distance: f64 = relative.length();
+        if distance <= NEAR_FIELD {
+            return (self.position.as_vec3(), self.scale);
+        }
+        let shrink: f64 = NEAR_FIELD / distance;
+        (
+            (camera + relative * shrink).as_vec3(),
+            (self.scale as f64 * shrink) as f32,
+        )
+    }
+}
+
+fn place_impostors(
+    mut commands: Commands,
+    camera: Query<&Transform, With<CameraControllerSettings>>,
+    bodies: Query<(&LargeWorldPosition, &Transform, &Visibility), Without<Impostor>>,
+    mut impostors: Query<
+        (Entity, &Impostor, &mut Transform, &mut Visibility),
+        (
+            Without<LargeWorldPosition>,
+            Without<CameraControllerSettings>,
+        ),
+    >,
+) {
+    let camera: Option<DVec3> = camera
+        .get_single()
+        .ok()
+        .map(|camera| camera.translation.as_dvec3());
+    for (entity, impostor, mut transform, mut visibility) in impostors.iter_mut() {
+        let Ok((large_world_position, body_transform, body_visibility)) = bodies.get(impostor.body)
+        else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        let (translation, scale) =
+            large_world_position.rebased(camera.unwrap_or(large_world_position.position));
+        transform.translation = translation;
+        transform.rotation = body_transform.rotation;
+        transform.scale = Vec3::splat(scale);
+        // Hidden along with the body, e.g. under the fog of war
+        if *visibility != *body_visibility {
+            *visibility = *body_visibility;
+        }
+    }
+}
+
+fn stow_far_physics(
+    mut commands: Commands,
+    bodies: Query<(
+        Entity,
+        &LargeWorldPosition,
+        Option<&RigidBody>,
+        Option<&Collider>,
+        Option<&StowedPhysics>,
+    )>,
+) {
+    for (entity, large_world_position, rigid_body, collider, stowed) in bodies.iter() {
+        let in_range: bool = large_world_position.position.length() <= PHYSICS_RANGE;
+        match stowed {
+            None if !in_range && (rigid_body.is_some() || collider.is_some()) => {
+                commands
+                    .entity(entity)
+                    .remove::<(RigidBody, Collider)>()
+                    .insert(StowedPhysics {
+                        rigid_body: rigid_body.cloned(),
+                        collider: collider.cloned(),
+                    });
+            }
+            Some(stowed) if in_range => {
+                let mut body = commands.entity(entity);
+                body.remove::<StowedPhysics>();
+                if let Some(rigid_body) = stowed.rigid_body {
+                    body.insert(rigid_body);
+                }
+                if let Some(collider) = &stowed.collider {
+                    body.insert(collider.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::scene::ScenePlugin;
+    use bevy_rapier3d::prelude::{NoUserData, RapierContext, RapierPhysicsPlugin};
+
+    use super::*;
+    use crate::resources::{ResourceSource, ResourceSourceId};
+
+    #[test]
+    fn near_bodies_keep_their_place() {
+        let body: LargeWorldPosition = LargeWorldPosition {
+            position: DVec3::new(-5.0, 2.0, 5.0),
+            scale: 1.0,
+        };
+        assert_eq!(
+            body.rebased(DVec3::new(0.0, 10.0, 0.0)),
+            (Vec3::new(-5.0, 2.0, 5.0), 1.0)
+        );
+    }
+
+    #[test]
+    fn far_bodies_keep_their_direction_and_apparent_size() {
+        let camera: DVec3 = DVec3::new(3.0, 10.0, -2.0);
+        let sun: LargeWorldPosition = LargeWorldPosition {
+            position: DVec3::new(150_000_000_000.0, 2.0, 5.0),
+            scale: 100_000.0,
+        };
+        let (translation, scale) = sun.rebased(camera);
+        let relative: DVec3 = translation.as_dvec3() - camera;
+        assert!((relative.length() - NEAR_FIELD).abs() < 1e-3);
+        assert!(
+            relative
+                .normalize()
+                .dot((sun.position - camera).normalize())
+                > 1.0 - 1e-9
+        );
+        let apparent_size: f64 = sun.scale as f64 / sun.position.distance(camera);
+        assert!((scale as f64 / relative.length() - apparent_size).abs() < 1e-9);
+    }
+
+    #[test]
+    fn far_cameras_leave_the_gameplay_position_alone() {
+        let mut app: App = App::new();
+        app.add_plugins(LargeWorld);
+        app.world_mut().spawn((
+            CameraControllerSettings::default(),
+            Transform::from_xyz(0.0, 100_000.0, 0.0),
+        ));
+        let position: Vec3 = Vec3::new(20.0, 2.0, -10.0);
+        let body: Entity = app
+            .world_mut()
+            .spawn((
+                LargeWorldPosition {
+                    position: position.as_dvec3(),
+                    scale: 1.0,
+                },
+                Transform::from_translation(position),
+                Visibility::Hidden,
+                ResourceSource {
+                    id: ResourceSourceId {
+                        system: 0,
+                        index: 0,
+                    },
+                },
+            ))
+            .id();
+        let impostor: Entity = app
+            .world_mut()
+            .spawn((
+                Impostor { body },
+                Transform::from_translation(position),
+                Visibility::Inherited,
+            ))
+            .id();
+        app.update();
+
+        let world: &World = app.world();
+        assert_eq!(
+            world.get::<Transform>(body),
+            Some(&Transform::from_translation(position))
+        );
+        let drawn: &Transform = world.get::<Transform>(impostor).unwrap();
+        assert!(drawn.translation.y > 100_000.0 - NEAR_FIELD as f32 - 1.0);
+        assert!(drawn.scale.x < 1.0);
+        assert_eq!(world.get::<Visibility>(impostor), Some(&Visibility::Hidden));
+
+        app.world_mut().entity_mut(body).despawn();
+        app.update();
+        assert!(app.world().get_entity(impostor).is_none());
+    }
+
+    #[test]
+    fn far_bodies_stay_out_of_physics() {
+        let mut app: App = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+            LargeWorld,
+        ))
+        .init_asset::<Mesh>();
+        let sun: DVec3 = DVec3::new(150_000_000_000.0, 0.0, 0.0);
+        let mut body = |position: DVec3| -> Entity {
+            app.world_mut()
+                .spawn((
+                    LargeWorldPosition {
+                        position,
+                        scale: 1.0,
+                    },
+                    SpatialBundle::from_transform(Transform::from_translation(position.as_vec3())),
+                    RigidBody::KinematicPositionBased,
+                    Collider::ball(1.0),
+                ))
+                .id()
+        };
+        let far: Entity = body(sun);
+        let near: Entity = body(DVec3::new(5.0, 0.0, 0.0));
+        app.update();
+
+        let positions = |app: &App| -> Vec<Vec3> {
+            app.world()
+                .resource::<RapierContext>()
+                .colliders
+                .iter()
+                .map(|(_, collider)| {
+                    let translation = collider.translation();
+                    Vec3::new(translation.x, translation.y, translation.z)
+                })
+                .collect()
+        };
+        assert_eq!(positions(&app), vec![Vec3::new(5.0, 0.0, 0.0)]);
+        assert!(app.world().get::<StowedPhysics>(near).is_none());
+
+        // Back in range, e.g. along its orbit
+        let position: DVec3 = DVec3::new(-20.0, 0.0, 0.0);
+        app.world_mut()
+            .entity_mut(far)
+            .insert(Transform::from_translation(position.as_vec3()))
+            .get_mut::<LargeWorldPosition>()
+            .unwrap()
+            .position = position;
+        app.update();
+        assert_eq!(positions(&app).len(), 2);
+        assert!(app.world().get::<StowedPhysics>(far).is_none());
+    }
+}
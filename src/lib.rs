@@ -0,0 +1,34 @@
+// Bevy systems take their queries and resources as arguments, however many they need
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+pub mod a_star;
+pub mod abilities;
+pub mod ai;
+pub mod ai_script;
+pub mod civilisation;
+pub mod combat;
+pub mod control_groups;
+pub mod environment;
+pub mod galaxy;
+pub mod hazards;
+pub mod key_bindings;
+pub mod large_world;
+pub mod map_editor;
+pub mod map_generation;
+pub mod minimap;
+pub mod movable;
+pub mod orbits;
+pub mod ownable;
+pub mod player_controller;
+pub mod production;
+pub mod research;
+pub mod resource_collection;
+pub mod resources;
+pub mod save;
+pub mod scenario;
+pub mod selection;
+pub mod simulation;
+pub mod spawner;
+pub mod ui;
+pub mod utils;
+pub mod visibility;
@@ -1,39 +1,186 @@
 // #![feature(let_chains)]
-mod a_star;
-mod civilisation;
-mod environment;
-mod movable;
-mod ownable;
-mod player_controller;
-mod resource_collection;
-mod resources;
-mod spawner;
-mod ui;
-mod utils;
 
-use crate::environment::Environment;
-use crate::movable::UnitMovement;
-use crate::player_controller::PlayerController;
-use crate::spawner::InstanceSpawner;
-use crate::ui::GameUI;
 use bevy::{
     prelude::*,
     utils::HashMap,
     window::{PresentMode, WindowMode, WindowPlugin, WindowResolution},
 };
 use bevy_rapier3d::prelude::*;
-use civilisation::CivilisationPlugin;
-use player_controller::{Civilisation, ContextMenuAction, LocalPlayer, PlayerInfo, TechLevel};
-use resource_collection::ResourceCollection;
-use resources::{ResourceStockpiles, ResourceType};
-use spawner::{InstanceSpawnRequest, UnitType};
+use orthros_be::abilities::Abilities;
+use orthros_be::ai::AiOpponent;
+use orthros_be::ai_script::{simulate_build_order, AiScript};
+use orthros_be::civilisation::{CivilisationPlugin, Civilisations, CIVILISATIONS_PATH};
+use orthros_be::combat::Combat;
+use orthros_be::control_groups::ControlGroups;
+use orthros_be::environment::Environment;
+use orthros_be::galaxy::GalaxyPlugin;
+use orthros_be::hazards::Hazards;
+use orthros_be::key_bindings::KeyBindingsPlugin;
+use orthros_be::large_world::LargeWorld;
+use orthros_be::map_editor::MapEditorPlugin;
+use orthros_be::map_generation::{generate_map, MapParameters};
+use orthros_be::minimap::{Minimap, MinimapRenderer, MinimapSettings};
+use orthros_be::movable::UnitMovement;
+use orthros_be::orbits::Orbits;
+use orthros_be::player_controller::Civilisation;
+use orthros_be::player_controller::PlayerController;
+use orthros_be::production::Production;
+use orthros_be::research::{Research, TechTree, TECH_TREE_PATH};
+use orthros_be::resource_collection::ResourceCollection;
+use orthros_be::resources::{ResourceStockpiles, ResourceType};
+use orthros_be::save::{LoadRequest, SaveLoad};
+use orthros_be::scenario::{spawn_players, PlayerOverrides, Scenario, DEFAULT_SCENARIO_PATH};
+use orthros_be::selection::Selection;
+use orthros_be::simulation::{Simulation, DEFAULT_TICK_RATE};
+use orthros_be::spawner::{InstanceSpawnRequest, UnitType};
+use orthros_be::spawner::{InstanceSpawner, UnitModels};
+use orthros_be::ui::GameUI;
+use orthros_be::visibility::FogOfWar;
 
 // #[derive(Component)]
 // struct ContextMenuActions {
 //     actions: Vec<ContextMenuAction>,
 // }
+#[derive(Resource)]
+struct LaunchOptions {
+    /// One build order per AI player, in scenario order. The last one is repeated for any AI
+    /// players beyond the list.
+    ai_scripts: Vec<String>,
+    build_order_report: Option<(String, u32)>,
+    /// Overrides the civilisation the scenario gives the local player
+    civilisation: Option<Civilisation>,
+    /// Overrides the civilisation the scenario gives the AI players
+    ai_civilisation: Option<Civilisation>,
+    scenario: String,
+    minimap: MinimapRenderer,
+    /// Save to continue instead of starting a new match
+    load: Option<String>,
+    /// Generate the map instead of loading the scenario
+    map_generation: Option<MapParameters>,
+    /// Simulation ticks per second
+    tick_rate: f64,
+}
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        LaunchOptions {
+            ai_scripts: Vec::new(),
+            build_order_report: None,
+            civilisation: None,
+            ai_civilisation: None,
+            scenario: DEFAULT_SCENARIO_PATH.into(),
+            minimap: MinimapRenderer::Camera,
+            load: None,
+            map_generation: None,
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+}
+fn parse_launch_options() -> LaunchOptions {
+    let mut launch_options: LaunchOptions = LaunchOptions::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ai-script" => launch_options.ai_scripts.extend(args.next()),
+            "--load" => launch_options.load = args.next(),
+            "--scenario" => {
+                if let Some(path) = args.next() {
+                    launch_options.scenario = path;
+                }
+            }
+            "--civilisation" => launch_options.civilisation = args.next().map(Civilisation),
+            "--ai-civilisation" => launch_options.ai_civilisation = args.next().map(Civilisation),
+            "--minimap" => match args.next().as_deref() {
+                Some("camera") => launch_options.minimap = MinimapRenderer::Camera,
+                Some("icons") => launch_options.minimap = MinimapRenderer::Icons,
+                other => println!("Unknown minimap {:?}, expected camera or icons", other),
+            },
+            "--seed" | "--players" | "--map-size" | "--resource-richness" | "--belt-density" => {
+                let parameters: &mut MapParameters = launch_options
+                    .map_generation
+                    .get_or_insert_with(MapParameters::default);
+                let Some(value) = args.next() else {
+                    println!("Missing value for {}", arg);
+                    continue;
+                };
+                if !parameters.set(&arg, &value) {
+                    println!("Invalid value {} for {}", value, arg);
+                }
+            }
+            "--tick-rate" => match args.next().map(|rate| rate.parse::<f64>()) {
+                Some(Ok(rate)) if rate > 0.0 && rate.is_finite() => launch_options.tick_rate = rate,
+                other => println!("Invalid tick rate {:?}", other),
+            },
+            // Nothing else runs with a report, so a bad one ends it right away
+            "--build-order-report" => {
+                let (Some(path), Some(minutes)) = (args.next(), args.next()) else {
+                    println!("Expected a build order and its minutes after {}", arg);
+                    std::process::exit(1);
+                };
+                match minutes.parse::<u32>() {
+                    Ok(minutes) => launch_options.build_order_report = Some((path, minutes)),
+                    Err(e) => {
+                        println!("Invalid minutes {} for {}: {}", minutes, arg, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => println!("Unknown argument {}", arg),
+        }
+    }
+    launch_options
+}
 fn main() {
+    let launch_options: LaunchOptions = parse_launch_options();
+    if let Some((path, minutes)) = &launch_options.build_order_report {
+        match (
+            AiScript::load(path),
+            TechTree::load(TECH_TREE_PATH),
+            Civilisations::load(CIVILISATIONS_PATH),
+        ) {
+            (Ok(script), Ok(tech_tree), Ok(civilisations)) => println!(
+                "{}",
+                simulate_build_order(
+                    script,
+                    &civilisations.player_info(
+                        &tech_tree,
+                        &launch_options
+                            .civilisation
+                            .clone()
+                            .unwrap_or(Civilisation("Greek".into()))
+                    ),
+                    &[UnitType::Spacestation],
+                    ResourceStockpiles(HashMap::from([(ResourceType::Plotanium, 100)])),
+                    &civilisations.unit_specifications(),
+                    &civilisations.civilisation_boni(),
+                    &tech_tree,
+                    *minutes,
+                )
+            ),
+            (Err(e), _, _) => println!("Could not load build order {}: {}", path, e),
+            (_, Err(e), _) => println!("Could not load tech tree {}: {}", TECH_TREE_PATH, e),
+            (_, _, Err(e)) => {
+                println!("Could not load civilisations {}: {}", CIVILISATIONS_PATH, e)
+            }
+        }
+        return;
+    }
+    let scenario: Scenario = match &launch_options.map_generation {
+        Some(parameters) => {
+            println!("Generating map {:?}", parameters);
+            generate_map(parameters)
+        }
+        None => match Scenario::load(&launch_options.scenario) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                println!("Could not load scenario {}: {}", launch_options.scenario, e);
+                return;
+            }
+        },
+    };
     App::new()
+        // Before the plugins, which set up the map from it
+        .insert_resource(scenario)
+        .insert_resource(Time::<Fixed>::from_hz(launch_options.tick_rate))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 present_mode: PresentMode::Immediate,
@@ -50,56 +197,66 @@ fn main() {
             Environment,
             UnitMovement,
             InstanceSpawner,
+            UnitModels,
             GameUI,
             ResourceCollection,
             RapierPhysicsPlugin::<NoUserData>::default(),
             CivilisationPlugin,
+            FogOfWar,
+            AiOpponent,
+            Production,
+            Research,
+            Abilities,
+        ))
+        .add_plugins((
+            KeyBindingsPlugin,
+            Minimap,
+            Selection,
+            ControlGroups,
+            SaveLoad,
+            MapEditorPlugin,
+            LargeWorld,
+            GalaxyPlugin,
+            Orbits,
+            Hazards,
+            Combat,
+            Simulation,
             RapierDebugRenderPlugin::default(),
         ))
         .add_event::<InstanceSpawnRequest>()
+        .insert_resource(MinimapSettings {
+            renderer: launch_options.minimap,
+        })
+        .insert_resource(launch_options)
         .add_systems(Startup, setup)
         .run();
 }
 
-fn setup(mut commands: Commands, mut spawn_events: EventWriter<InstanceSpawnRequest>) {
-    let mut player_info: PlayerInfo = PlayerInfo {
-        civilisation: Civilisation::Greek,
-        tech_level: TechLevel::L0,
-        context_menu_actions: HashMap::new(),
+fn setup(
+    mut commands: Commands,
+    mut spawn_events: EventWriter<InstanceSpawnRequest>,
+    mut load_requests: EventWriter<LoadRequest>,
+    launch_options: Res<LaunchOptions>,
+    scenario: Res<Scenario>,
+    civilisations: Res<Civilisations>,
+    tech_tree: Res<TechTree>,
+) {
+    if let Some(path) = &launch_options.load {
+        load_requests.send(LoadRequest { path: path.clone() });
+        return;
+    }
+    let overrides: PlayerOverrides = PlayerOverrides {
+        civilisation: launch_options.civilisation.clone(),
+        ai_civilisation: launch_options.ai_civilisation.clone(),
+        ai_scripts: AiScript::load_all(&launch_options.ai_scripts),
+        ai_for_all: None,
     };
-
-    player_info.context_menu_actions.insert(
-        UnitType::Spacestation,
-        vec![
-            ContextMenuAction::Build(UnitType::Cruiser),
-            ContextMenuAction::Build(UnitType::MiningStation),
-        ],
+    spawn_players(
+        &mut commands,
+        &mut spawn_events,
+        &scenario,
+        &overrides,
+        &civilisations,
+        &tech_tree,
     );
-    commands.spawn((
-        LocalPlayer,
-        player_info,
-        ResourceStockpiles(HashMap::from([(ResourceType::Plotanium, 0)])),
-    ));
-    for x in 0..2 {
-        for y in 0..2 {
-            spawn_events.send(InstanceSpawnRequest {
-                location: Vec3 {
-                    x: x as f32 * 2.0,
-                    y: 2.0,
-                    z: y as f32 * 2.0,
-                },
-                unit_type: UnitType::Cruiser,
-                civilisation: Civilisation::Greek,
-            });
-        }
-    }
-    spawn_events.send(InstanceSpawnRequest {
-        location: Vec3 {
-            x: -3.0,
-            y: 2.0,
-            z: -3.0,
-        },
-        unit_type: UnitType::Spacestation,
-        civilisation: Civilisation::Greek,
-    });
 }
@@ -0,0 +1,940 @@
+use std::fmt;
+
+use bevy::ecs::system::SystemParam;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_rapier3d::prelude::*;
+
+use crate::ai::AiPlayer;
+use crate::environment::{
+    spawn_prop, spawn_resource_source, CostZone, GridArea, MovementGrid, OBSTACLE,
+};
+use crate::galaxy::{shift_areas, shift_cost_zones, Galaxy, InSystem};
+use crate::hazards::{hazard_cost_zones, HazardZones};
+use crate::key_bindings::{InputAction, InputActions};
+use crate::large_world::LargeWorldPosition;
+use crate::ownable::{Owner, PlayerColour};
+use crate::player_controller::{
+    ray_from_mouse_position, CameraControllerSettings, LocalPlayer, PlayerInfo, StartPosition,
+};
+use crate::resources::{
+    ResourceLevel, ResourceSource, ResourceSourceId, ResourceStockpiles, ResourceType,
+};
+use crate::scenario::{
+    Prop, Scenario, ScenarioPlayer, ScenarioProp, ScenarioResourceSource, ScenarioSystem,
+    ScenarioUnit,
+};
+use crate::spawner::{EntityWrapper, InstanceSpawnRequest, UnitInformation, UnitType};
+use crate::ui::RayBlock;
+
+pub const EDITED_SCENARIO_PATH: &str = "./assets/scenarios/edited.ron";
+/// Height units and props are placed at, like in the scenario files
+const PLACEMENT_HEIGHT: f32 = 2.0;
+const UNIT_TYPES: [UnitType; 3] = [
+    UnitType::Cruiser,
+    UnitType::Spacestation,
+    UnitType::MiningStation,
+];
+const RESOURCE_TYPES: [ResourceType; 1] = [ResourceType::Plotanium];
+const RESOURCE_AMOUNTS: [i32; 5] = [1_000, 5_000, 10_000, 42_000, 100_000];
+const BRUSH_RADII: [f32; 5] = [0.5, 1.0, 2.0, 5.0, 10.0];
+const ZONE_COSTS: [u8; 4] = [5, 10, 25, 50];
+
+const PANEL_BACKGROUND: Color = Color::srgba(12.0 / 256.0, 11.0 / 256.0, 13.0 / 256.0, 0.9);
+const PANEL_TEXT: Color = Color::srgb(
+    0x81 as f32 / 256.0,
+    0xC1 as f32 / 256.0,
+    0x14 as f32 / 256.0,
+);
+const PANEL_BUTTON: Color = Color::srgb(0.1, 0.1, 0.12);
+const HOVERED_PANEL_BUTTON: Color = Color::srgb(64.0 / 256.0, 99.0 / 256.0, 64.0 / 256.0);
+const ACTIVE_TOOL: Color = Color::srgb(0.25, 0.4, 0.25);
+const OBSTACLE_OUTLINE: Color = Color::srgb(0.9, 0.2, 0.1);
+const COST_ZONE_OUTLINE: Color = Color::srgb(0.9, 0.7, 0.1);
+const START_POSITION_MARKER: Color = Color::srgb(0.2, 0.6, 0.95);
+
+pub struct MapEditorPlugin;
+impl Plugin for MapEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapEditor>()
+            .add_systems(Startup, load_grid_areas)
+            .add_systems(
+                Update,
+                (
+                    toggle_map_editor,
+                    map_editor_buttons.after(toggle_map_editor),
+                    refresh_map_editor_panel.after(map_editor_buttons),
+                    map_editor_input
+                        .after(map_editor_buttons)
+                        .run_if(not(map_editor_closed)),
+                    draw_grid_areas.run_if(not(map_editor_closed)),
+                ),
+            );
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditorTool {
+    PlaceUnit,
+    PlaceProp,
+    PlaceAsteroid,
+    Delete,
+    PaintObstacle,
+    PaintCostZone,
+    /// Removes obstacles and cost zones
+    Erase,
+    StartPosition,
+}
+impl EditorTool {
+    const ALL: [EditorTool; 8] = [
+        EditorTool::PlaceUnit,
+        EditorTool::PlaceProp,
+        EditorTool::PlaceAsteroid,
+        EditorTool::Delete,
+        EditorTool::PaintObstacle,
+        EditorTool::PaintCostZone,
+        EditorTool::Erase,
+        EditorTool::StartPosition,
+    ];
+    fn paints(&self) -> bool {
+        matches!(
+            self,
+            EditorTool::PaintObstacle | EditorTool::PaintCostZone | EditorTool::Erase
+        )
+    }
+}
+impl fmt::Display for EditorTool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditorTool::PlaceUnit => write!(f, "Place unit"),
+            EditorTool::PlaceProp => write!(f, "Place prop"),
+            EditorTool::PlaceAsteroid => write!(f, "Place asteroid"),
+            EditorTool::Delete => write!(f, "Delete"),
+            EditorTool::PaintObstacle => write!(f, "Paint obstacle"),
+            EditorTool::PaintCostZone => write!(f, "Paint cost zone"),
+            EditorTool::Erase => write!(f, "Erase obstacles and zones"),
+            EditorTool::StartPosition => write!(f, "Set start position"),
+        }
+    }
+}
+/// The map editor pauses the match and lets the local player build the map around it.
+/// Options are indices into the lists of choices above.
+#[derive(Resource)]
+pub struct MapEditor {
+    pub open: bool,
+    tool: EditorTool,
+    player: usize,
+    unit_type: usize,
+    prop: usize,
+    resource_type: usize,
+    resource_amount: usize,
+    brush_radius: usize,
+    zone_cost: usize,
+    obstacles: Vec<GridArea>,
+    cost_zones: Vec<CostZone>,
+    /// Hazards aren't edited, but still have to end up on the movement grid
+    hazard_zones: Vec<CostZone>,
+}
+impl Default for MapEditor {
+    fn default() -> Self {
+        MapEditor {
+            open: false,
+            tool: EditorTool::PlaceUnit,
+            player: 0,
+            unit_type: 0,
+            prop: 0,
+            resource_type: 0,
+            resource_amount: 2,
+            brush_radius: 2,
+            zone_cost: 1,
+            obstacles: Vec::new(),
+            cost_zones: Vec::new(),
+            hazard_zones: Vec::new(),
+        }
+    }
+}
+impl MapEditor {
+    fn rebuild_grid(&self, movement_grid: &mut MovementGrid) {
+        movement_grid.rebuild(
+            &self.obstacles,
+            &[self.cost_zones.clone(), self.hazard_zones.clone()].concat(),
+        );
+    }
+}
+#[derive(Component)]
+struct MapEditorPanel;
+#[derive(Component, Clone, Copy, PartialEq)]
+enum EditorButton {
+    Tool(EditorTool),
+    Player,
+    UnitType,
+    Prop,
+    ResourceType,
+    ResourceAmount,
+    BrushRadius,
+    ZoneCost,
+    Export,
+}
+
+pub fn map_editor_closed(editor: Option<Res<MapEditor>>) -> bool {
+    editor.is_none_or(|editor| !editor.open)
+}
+
+/// Areas of all systems, in world coordinates
+fn load_grid_areas(
+    mut editor: ResMut<MapEditor>,
+    scenario: Res<Scenario>,
+    hazards: Res<HazardZones>,
+) {
+    editor.hazard_zones = hazard_cost_zones(&hazards.0);
+    editor.obstacles = scenario.obstacles.clone();
+    editor.cost_zones = scenario.cost_zones.clone();
+    for (index, system) in scenario.systems.iter().enumerate() {
+        let origin: Vec2 = Galaxy::system_origin(index + 1);
+        let obstacles: Vec<GridArea> = shift_areas(&system.obstacles, origin);
+        let cost_zones: Vec<CostZone> = shift_cost_zones(&system.cost_zones, origin);
+        editor.obstacles.extend(obstacles);
+        editor.cost_zones.extend(cost_zones);
+    }
+}
+
+/// Players in a stable order, the same the export writes them in. Setup spawns them one after
+/// another, so this is also the order the scenario lists them in.
+fn sorted_players<'a>(
+    players: impl Iterator<Item = (Entity, &'a PlayerInfo)>,
+) -> Vec<(Entity, &'a PlayerInfo)> {
+    let mut players: Vec<(Entity, &PlayerInfo)> = players.collect();
+    players.sort_by_key(|(entity, _)| *entity);
+    players
+}
+
+fn toggle_map_editor(
+    mut commands: Commands,
+    actions: InputActions,
+    asset_server: Res<AssetServer>,
+    mut editor: ResMut<MapEditor>,
+    mut time: ResMut<Time<Virtual>>,
+    panels: Query<Entity, With<MapEditorPanel>>,
+) {
+    if !actions.just_pressed(InputAction::ToggleMapEditor) {
+        return;
+    }
+    editor.open = !editor.open;
+    if editor.open {
+        // Nothing moves, mines or builds while the map is being edited
+        time.pause();
+        spawn_map_editor_panel(&mut commands, &asset_server);
+    } else {
+        time.unpause();
+        for panel in panels.iter() {
+            commands.entity(panel).despawn_recursive();
+        }
+    }
+}
+
+fn panel_text(asset_server: &Res<AssetServer>) -> TextBundle {
+    TextBundle::from_section(
+        "",
+        TextStyle {
+            font: asset_server.load("fonts/android-insomnia-font/AndroidInsomniaRegular.ttf"),
+            font_size: 14.0,
+            color: PANEL_TEXT,
+        },
+    )
+}
+fn spawn_map_editor_panel(commands: &mut Commands, asset_server: &Res<AssetServer>) {
+    let buttons = EditorTool::ALL.into_iter().map(EditorButton::Tool).chain([
+        EditorButton::Player,
+        EditorButton::UnitType,
+        EditorButton::Prop,
+        EditorButton::ResourceType,
+        EditorButton::ResourceAmount,
+        EditorButton::BrushRadius,
+        EditorButton::ZoneCost,
+        EditorButton::Export,
+    ]);
+    commands
+        .spawn((
+            MapEditorPanel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Percent(10.0),
+                    width: Val::Px(260.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: PANEL_BACKGROUND.into(),
+                z_index: ZIndex::Global(15),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            let mut title: TextBundle = panel_text(asset_server);
+            title.text.sections[0].value = "Map editor (match paused)".into();
+            parent.spawn(title);
+            for button in buttons {
+                parent
+                    .spawn((
+                        button,
+                        ButtonBundle {
+                            style: Style {
+                                margin: UiRect::all(Val::Px(2.0)),
+                                padding: UiRect::all(Val::Px(3.0)),
+                                ..default()
+                            },
+                            background_color: PANEL_BUTTON.into(),
+                            ..default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(panel_text(asset_server));
+                    });
+            }
+        });
+}
+
+fn next(index: usize, len: usize) -> usize {
+    (index + 1) % len.max(1)
+}
+
+fn map_editor_buttons(
+    mut editor: ResMut<MapEditor>,
+    mut buttons: Query<(&Interaction, &EditorButton, &mut BackgroundColor), Changed<Interaction>>,
+    scenario: Res<Scenario>,
+    galaxy: Res<Galaxy>,
+    world: ExportableWorld,
+) {
+    for (interaction, button, mut background_color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Pressed => match button {
+                EditorButton::Tool(tool) => editor.tool = *tool,
+                EditorButton::Player => {
+                    editor.player = next(editor.player, world.players.iter().count())
+                }
+                EditorButton::UnitType => {
+                    editor.unit_type = next(editor.unit_type, UNIT_TYPES.len())
+                }
+                EditorButton::Prop => editor.prop = next(editor.prop, scenario.props.len()),
+                EditorButton::ResourceType => {
+                    editor.resource_type = next(editor.resource_type, RESOURCE_TYPES.len())
+                }
+                EditorButton::ResourceAmount => {
+                    editor.resource_amount = next(editor.resource_amount, RESOURCE_AMOUNTS.len())
+                }
+                EditorButton::BrushRadius => {
+                    editor.brush_radius = next(editor.brush_radius, BRUSH_RADII.len())
+                }
+                EditorButton::ZoneCost => {
+                    editor.zone_cost = next(editor.zone_cost, ZONE_COSTS.len())
+                }
+                EditorButton::Export => {
+                    let exported: Scenario = world.export(&editor, &scenario, &galaxy);
+                    match exported.save(EDITED_SCENARIO_PATH) {
+                        Ok(()) => println!("Exported scenario to {}", EDITED_SCENARIO_PATH),
+                        Err(e) => {
+                            println!("Could not export scenario {}: {}", EDITED_SCENARIO_PATH, e)
+                        }
+                    }
+                }
+            },
+            Interaction::Hovered => *background_color = HOVERED_PANEL_BUTTON.into(),
+            Interaction::None => *background_color = PANEL_BUTTON.into(),
+        }
+    }
+}
+
+fn refresh_map_editor_panel(
+    editor: Res<MapEditor>,
+    scenario: Res<Scenario>,
+    players: Query<(Entity, &PlayerInfo)>,
+    new_buttons: Query<(), Added<EditorButton>>,
+    mut buttons: Query<(&EditorButton, &Children, &Interaction, &mut BackgroundColor)>,
+    mut texts: Query<&mut Text>,
+) {
+    if !editor.is_changed() && new_buttons.is_empty() {
+        return;
+    }
+    let players = sorted_players(players.iter());
+    for (button, children, interaction, mut background_color) in buttons.iter_mut() {
+        let label: String = match button {
+            EditorButton::Tool(tool) => tool.to_string(),
+            EditorButton::Player => match players.get(editor.player) {
+                Some((_, player_info)) => {
+                    format!(
+                        "Player {} ({})",
+                        editor.player + 1,
+                        player_info.civilisation
+                    )
+                }
+                None => "No players".into(),
+            },
+            EditorButton::UnitType => format!("Unit: {}", UNIT_TYPES[editor.unit_type]),
+            EditorButton::Prop => match scenario.props.get(editor.prop) {
+                Some(prop) => format!("Prop: {}", prop.name),
+                None => "No props in the scenario".into(),
+            },
+            EditorButton::ResourceType => {
+                format!("Resource: {:?}", RESOURCE_TYPES[editor.resource_type])
+            }
+            EditorButton::ResourceAmount => {
+                format!("Amount: {}", RESOURCE_AMOUNTS[editor.resource_amount])
+            }
+            EditorButton::BrushRadius => {
+                format!("Brush radius: {}", BRUSH_RADII[editor.brush_radius])
+            }
+            EditorButton::ZoneCost => format!("Zone cost: {}", ZONE_COSTS[editor.zone_cost]),
+            EditorButton::Export => format!("Export to {}", EDITED_SCENARIO_PATH),
+        };
+        if *interaction == Interaction::None {
+            *background_color = match button {
+                EditorButton::Tool(tool) if *tool == editor.tool => ACTIVE_TOOL.into(),
+                _ => PANEL_BUTTON.into(),
+            };
+        }
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
+
+fn map_editor_input(
+    mut commands: Commands,
+    actions: InputActions,
+    mut editor: ResMut<MapEditor>,
+    mut movement_grid: ResMut<MovementGrid>,
+    camera: Query<(&Camera, &GlobalTransform), With<CameraControllerSettings>>,
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    rapier_context: Res<RapierContext>,
+    rayblock: Query<(), With<RayBlock>>,
+    asset_server: Res<AssetServer>,
+    scenario: Res<Scenario>,
+    galaxy: Res<Galaxy>,
+    players: Query<(Entity, &PlayerInfo)>,
+    deletable: Query<(), Or<(With<UnitInformation>, With<ScenarioProp>)>>,
+    descriptions: Query<(Entity, &EntityWrapper)>,
+    resource_sources: Query<&ResourceSource>,
+    mut spawn_events: EventWriter<InstanceSpawnRequest>,
+) {
+    let tool: EditorTool = editor.tool;
+    // Painting goes on while the button is held, everything else happens once per click
+    let clicked: bool = if tool.paints() {
+        actions.pressed(InputAction::Select)
+    } else {
+        actions.just_pressed(InputAction::Select)
+    };
+    if !clicked || !rayblock.is_empty() {
+        return;
+    }
+    let (Ok(primary), Ok((camera, camera_transform))) =
+        (primary_query.get_single(), camera.get_single())
+    else {
+        return;
+    };
+    let (ray_pos, ray_dir) = ray_from_mouse_position(primary, camera, camera_transform);
+    if tool == EditorTool::Delete {
+        if let Some((hit_entity, _)) = rapier_context.cast_ray(
+            ray_pos,
+            ray_dir,
+            f32::MAX,
+            true,
+            QueryFilter::only_kinematic(),
+        ) {
+            if deletable.get(hit_entity).is_ok() {
+                commands.entity(hit_entity).despawn_recursive();
+                for (description, wrapper) in descriptions.iter() {
+                    if wrapper.entity == hit_entity {
+                        commands.entity(description).despawn();
+                    }
+                }
+            }
+        }
+        return;
+    }
+    // The map's ground is a sensor, like the one move orders are aimed at
+    let Some((_, ray_intersection)) = rapier_context.cast_ray_and_get_normal(
+        ray_pos,
+        ray_dir,
+        f32::MAX,
+        true,
+        QueryFilter::exclude_solids(QueryFilter::new()),
+    ) else {
+        return;
+    };
+    let position: Vec3 = Vec3::new(
+        ray_intersection.point.x,
+        PLACEMENT_HEIGHT,
+        ray_intersection.point.z,
+    );
+    let area: GridArea = GridArea {
+        centre: position.xz(),
+        radius: BRUSH_RADII[editor.brush_radius],
+    };
+    let players = sorted_players(players.iter());
+    match tool {
+        EditorTool::PlaceUnit => {
+            let Some((owner, player_info)) = players.get(editor.player) else {
+                return;
+            };
+            spawn_events.send(InstanceSpawnRequest {
+                location: position,
+                unit_type: UNIT_TYPES[editor.unit_type].clone(),
+                civilisation: player_info.civilisation.clone(),
+                owner: *owner,
+                entity: None,
+            });
+        }
+        EditorTool::PlaceProp => {
+            if let Some(prop) = scenario.props.get(editor.prop) {
+                let entity: Entity = spawn_prop(
+                    &mut commands,
+                    &asset_server,
+                    &Prop {
+                        position: position.as_dvec3(),
+                        ..prop.clone()
+                    },
+                );
+                commands.entity(entity).insert(InSystem(galaxy.active));
+            }
+        }
+        EditorTool::PlaceAsteroid => {
+            let entity: Entity = spawn_resource_source(
+                &mut commands,
+                &asset_server,
+                &ScenarioResourceSource {
+                    prop: Prop {
+                        name: "Asteroid".into(),
+                        info: "Asteroid. Rich in resources".into(),
+                        model: "3d_models/environment/asteroid_01.glb".into(),
+                        scene: "Scene0".into(),
+                        position: position.as_dvec3(),
+                        rotation: 0.0,
+                        scale: 1.0,
+                        orbit: None,
+                    },
+                    resource_type: RESOURCE_TYPES[editor.resource_type],
+                    resource_amount: RESOURCE_AMOUNTS[editor.resource_amount],
+                    radius: 1.0,
+                },
+                // Placed after everything the system already has
+                ResourceSourceId {
+                    system: galaxy.active,
+                    index: resource_sources
+                        .iter()
+                        .filter(|source| source.id.system == galaxy.active)
+                        .map(|source| source.id.index + 1)
+                        .max()
+                        .unwrap_or(0),
+                },
+            );
+            commands.entity(entity).insert(InSystem(galaxy.active));
+        }
+        EditorTool::StartPosition => {
+            if let Some((player, _)) = players.get(editor.player) {
+                commands.entity(*player).insert(StartPosition(position));
+            }
+        }
+        EditorTool::PaintObstacle => {
+            // Skip strokes that barely differ from the last one while dragging
+            if editor
+                .obstacles
+                .last()
+                .is_some_and(|last| last.centre.distance(area.centre) < area.radius / 2.0)
+            {
+                return;
+            }
+            editor.obstacles.push(area);
+            movement_grid.paint(&area, OBSTACLE);
+        }
+        EditorTool::PaintCostZone => {
+            if editor
+                .cost_zones
+                .last()
+                .is_some_and(|last| last.area.centre.distance(area.centre) < area.radius / 2.0)
+            {
+                return;
+            }
+            let zone: CostZone = CostZone {
+                area,
+                cost: ZONE_COSTS[editor.zone_cost],
+            };
+            editor.cost_zones.push(zone);
+            // Zones never cover obstacles, so rebuild rather than paint over them
+            editor.rebuild_grid(&mut movement_grid);
+        }
+        EditorTool::Erase => {
+            let erased = |other: &GridArea| other.centre.distance(area.centre) <= area.radius;
+            let before: usize = editor.obstacles.len() + editor.cost_zones.len();
+            editor.obstacles.retain(|obstacle| !erased(obstacle));
+            editor.cost_zones.retain(|zone| !erased(&zone.area));
+            if editor.obstacles.len() + editor.cost_zones.len() != before {
+                editor.rebuild_grid(&mut movement_grid);
+            }
+        }
+        EditorTool::Delete => {}
+    }
+}
+
+fn draw_grid_areas(
+    mut gizmos: Gizmos,
+    editor: Res<MapEditor>,
+    start_positions: Query<&StartPosition>,
+) {
+    let circle = |gizmos: &mut Gizmos, area: &GridArea, colour: Color| {
+        gizmos.circle(
+            area.centre.extend(PLACEMENT_HEIGHT).xzy(),
+            Dir3::Y,
+            area.radius,
+            colour,
+        );
+    };
+    for obstacle in &editor.obstacles {
+        circle(&mut gizmos, obstacle, OBSTACLE_OUTLINE);
+    }
+    for zone in &editor.cost_zones {
+        circle(&mut gizmos, &zone.area, COST_ZONE_OUTLINE);
+    }
+    for start_position in start_positions.iter() {
+        gizmos.sphere(start_position.0, Quat::IDENTITY, 1.0, START_POSITION_MARKER);
+    }
+}
+
+type PlayerDetails = (
+    Has<LocalPlayer>,
+    Option<&'static AiPlayer>,
+    Option<&'static PlayerColour>,
+    &'static ResourceStockpiles,
+    Option<&'static StartPosition>,
+);
+type ResourceSourceData = (
+    &'static ScenarioProp,
+    &'static LargeWorldPosition,
+    Option<&'static InSystem>,
+    &'static ResourceLevel,
+    &'static Collider,
+);
+/// Everything that ends up in an exported scenario
+#[derive(SystemParam)]
+struct ExportableWorld<'w, 's> {
+    players: Query<'w, 's, (Entity, &'static PlayerInfo)>,
+    player_details: Query<'w, 's, PlayerDetails>,
+    units: Query<'w, 's, (&'static UnitInformation, &'static Owner, &'static Transform)>,
+    props: Query<
+        'w,
+        's,
+        (
+            &'static ScenarioProp,
+            &'static LargeWorldPosition,
+            Option<&'static InSystem>,
+        ),
+        Without<ResourceSource>,
+    >,
+    resource_sources: Query<'w, 's, ResourceSourceData, With<ResourceSource>>,
+}
+impl ExportableWorld<'_, '_> {
+    /// The world as it currently stands in the scenario format
+    fn export(&self, editor: &MapEditor, scenario: &Scenario, galaxy: &Galaxy) -> Scenario {
+        let players: Vec<ScenarioPlayer> = sorted_players(self.players.iter())
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, (player, player_info))| {
+                let (local, ai_player, colour, stockpiles, start_position) =
+                    self.player_details.get(player).ok()?;
+                let colour: Srgba = colour.map_or(Color::WHITE, |colour| colour.0).to_srgba();
+                Some(ScenarioPlayer {
+                    civilisation: player_info.civilisation.clone(),
+                    local,
+                    ai: ai_player.map(|ai_player| ai_player.difficulty),
+                    colour: [colour.red, colour.green, colour.blue],
+                    start_position: start_position.map(|start_position| start_position.0),
+                    // What the players have gathered so far isn't part of the map
+                    starting_resources: scenario.players.get(index).map_or_else(
+                        || stockpiles.0.clone(),
+                        |scenario_player| scenario_player.starting_resources.clone(),
+                    ),
+                    units: self
+                        .units
+                        .iter()
+                        .filter(|(_, owner, _)| owner.player == player)
+                        .map(|(unit_information, _, transform)| ScenarioUnit {
+                            unit_type: unit_information.unit_type.clone(),
+                            position: transform.translation,
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+        // The first system is the scenario's own, its contents go to the top level below
+        let mut systems: Vec<ScenarioSystem> = std::iter::once(ScenarioSystem {
+            name: scenario.name.clone(),
+            galaxy_position: scenario.galaxy_position,
+            grid: scenario.grid.clone(),
+            resource_sources: Vec::new(),
+            props: Vec::new(),
+            obstacles: Vec::new(),
+            cost_zones: Vec::new(),
+            hazards: Vec::new(),
+        })
+        .chain(scenario.systems.iter().map(|system| ScenarioSystem {
+            resource_sources: Vec::new(),
+            props: Vec::new(),
+            obstacles: Vec::new(),
+            cost_zones: Vec::new(),
+            ..system.clone()
+        }))
+        .collect();
+        let last_system: usize = systems.len() - 1;
+        let system_of = |in_system: Option<&InSystem>| {
+            in_system.map_or(0, |in_system| in_system.0.min(last_system))
+        };
+        let local_prop = |prop: &ScenarioProp, position: DVec3, system: usize| {
+            Prop {
+                position,
+                ..prop.0.clone()
+            }
+            .shifted(-Galaxy::system_origin(system).as_dvec2().extend(0.0).xzy())
+        };
+        for (scenario_prop, large_world_position, in_system, resource_level, collider) in
+            self.resource_sources.iter()
+        {
+            let system: usize = system_of(in_system);
+            systems[system]
+                .resource_sources
+                .push(ScenarioResourceSource {
+                    prop: local_prop(scenario_prop, large_world_position.position, system),
+                    resource_type: resource_level.resource_type,
+                    resource_amount: resource_level.resource_amount,
+                    radius: collider.as_ball().map_or(1.0, |ball| ball.radius()),
+                });
+        }
+        for (scenario_prop, large_world_position, in_system) in self.props.iter() {
+            let system: usize = system_of(in_system);
+            systems[system].props.push(local_prop(
+                scenario_prop,
+                large_world_position.position,
+                system,
+            ));
+        }
+        for obstacle in &editor.obstacles {
+            let system: usize = galaxy.system_at(obstacle.centre).unwrap_or(0);
+            systems[system].obstacles.push(GridArea {
+                centre: obstacle.centre - Galaxy::system_origin(system),
+                ..*obstacle
+            });
+        }
+        for zone in &editor.cost_zones {
+            let system: usize = galaxy.system_at(zone.area.centre).unwrap_or(0);
+            systems[system].cost_zones.push(CostZone {
+                area: GridArea {
+                    centre: zone.area.centre - Galaxy::system_origin(system),
+                    ..zone.area
+                },
+                ..*zone
+            });
+        }
+        let home: ScenarioSystem = systems.remove(0);
+        Scenario {
+            name: scenario.name.clone(),
+            galaxy_position: scenario.galaxy_position,
+            grid: scenario.grid.clone(),
+            lighting: scenario.lighting.clone(),
+            players,
+            resource_sources: home.resource_sources,
+            props: home.props,
+            obstacles: home.obstacles,
+            cost_zones: home.cost_zones,
+            hazards: scenario.hazards.clone(),
+            systems,
+            jump_gates: scenario.jump_gates.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::GridSettings;
+    use crate::player_controller::{Civilisation, TechLevel};
+    use crate::scenario::DEFAULT_SCENARIO_PATH;
+    use bevy::ecs::system::SystemState;
+    use bevy::utils::{HashMap, HashSet};
+
+    fn grid() -> MovementGrid {
+        MovementGrid::new(GridSettings {
+            cell_size: 1.0,
+            grid_width: 10,
+            grid_height: 10,
+            xy_offset: Vec2::splat(5.0),
+            density: 0.2,
+        })
+    }
+    fn cost_at(grid: &MovementGrid, position: Vec2) -> u8 {
+        grid.cost(grid.settings.world_to_cell(position).unwrap())
+    }
+    fn painted_cells(grid: &MovementGrid) -> usize {
+        grid.grid
+            .iter()
+            .flatten()
+            .filter(|&&cost| cost != 0)
+            .count()
+    }
+
+    #[test]
+    fn painting_covers_the_cells_within_the_area() {
+        let mut grid: MovementGrid = grid();
+        grid.rebuild(&[], &[]);
+        // Centred on a cell, so only it and its four neighbours are within reach
+        grid.paint(
+            &GridArea {
+                centre: Vec2::splat(0.5),
+                radius: 1.0,
+            },
+            7,
+        );
+        assert_eq!(painted_cells(&grid), 5);
+        assert_eq!(cost_at(&grid, Vec2::new(1.5, 0.5)), 7);
+        assert_eq!(cost_at(&grid, Vec2::new(1.5, 1.5)), 0);
+        // Off the grid, nothing is touched
+        grid.paint(
+            &GridArea {
+                centre: Vec2::splat(-100.0),
+                radius: 2.0,
+            },
+            OBSTACLE,
+        );
+        assert_eq!(painted_cells(&grid), 5);
+    }
+
+    #[test]
+    fn rebuilding_puts_obstacles_over_zones_and_hazards() {
+        let mut editor: MapEditor = MapEditor {
+            obstacles: vec![GridArea {
+                centre: Vec2::splat(0.5),
+                radius: 0.5,
+            }],
+            cost_zones: vec![CostZone {
+                area: GridArea {
+                    centre: Vec2::splat(0.5),
+                    radius: 2.0,
+                },
+                cost: 10,
+            }],
+            hazard_zones: vec![CostZone {
+                area: GridArea {
+                    centre: Vec2::splat(-3.5),
+                    radius: 0.5,
+                },
+                cost: 50,
+            }],
+            ..default()
+        };
+        let mut grid: MovementGrid = grid();
+        editor.rebuild_grid(&mut grid);
+        assert_eq!(grid.grid.len(), 10);
+        assert_eq!(cost_at(&grid, Vec2::splat(0.5)), OBSTACLE);
+        assert_eq!(cost_at(&grid, Vec2::new(2.5, 0.5)), 10);
+        assert_eq!(cost_at(&grid, Vec2::splat(-3.5)), 50);
+        assert_eq!(cost_at(&grid, Vec2::splat(4.5)), 0);
+
+        // Erasing the obstacle leaves the zone underneath
+        editor.obstacles.clear();
+        editor.rebuild_grid(&mut grid);
+        assert_eq!(cost_at(&grid, Vec2::splat(0.5)), 10);
+    }
+
+    fn player_info(civilisation: &Civilisation) -> PlayerInfo {
+        PlayerInfo {
+            civilisation: civilisation.clone(),
+            tech_level: TechLevel::L0,
+            context_menu_actions: HashMap::new(),
+            researched: HashSet::new(),
+            researching: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn exported_scenarios_load_again() {
+        let scenario: Scenario = Scenario::load(DEFAULT_SCENARIO_PATH).unwrap();
+        let mut world: World = World::new();
+        for scenario_player in &scenario.players {
+            // Gathered during the match, which the export should leave out
+            let mut stockpiles: HashMap<ResourceType, i32> =
+                scenario_player.starting_resources.clone();
+            stockpiles.insert(ResourceType::Plotanium, 12_345);
+            let mut player = world.spawn((
+                player_info(&scenario_player.civilisation),
+                ResourceStockpiles(stockpiles),
+            ));
+            if scenario_player.local {
+                player.insert(LocalPlayer);
+            }
+            if let Some(difficulty) = scenario_player.ai {
+                player.insert(AiPlayer::new(difficulty));
+            }
+            if let Some(position) = scenario_player.start_position {
+                player.insert(StartPosition(position));
+            }
+        }
+        for (index, resource_source) in scenario.resource_sources.iter().enumerate() {
+            world.spawn((
+                ScenarioProp(resource_source.prop.clone()),
+                LargeWorldPosition {
+                    position: resource_source.prop.position,
+                    scale: resource_source.prop.scale,
+                },
+                ResourceLevel {
+                    resource_type: resource_source.resource_type,
+                    resource_amount: resource_source.resource_amount,
+                },
+                Collider::ball(resource_source.radius),
+                ResourceSource {
+                    id: ResourceSourceId { system: 0, index },
+                },
+            ));
+        }
+        let editor: MapEditor = MapEditor {
+            obstacles: scenario.obstacles.clone(),
+            cost_zones: scenario.cost_zones.clone(),
+            ..default()
+        };
+        let galaxy: Galaxy = Galaxy::from_scenario(&scenario);
+        let mut state: SystemState<ExportableWorld> = SystemState::new(&mut world);
+        let exported: Scenario = state.get(&world).export(&editor, &scenario, &galaxy);
+
+        let path: String = std::env::temp_dir()
+            .join("map_editor_export.ron")
+            .to_string_lossy()
+            .into_owned();
+        exported.save(&path).unwrap();
+        let loaded: Scenario = Scenario::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.players.len(), scenario.players.len());
+        for (loaded, original) in loaded.players.iter().zip(&scenario.players) {
+            assert_eq!(loaded.local, original.local);
+            assert_eq!(loaded.ai, original.ai);
+            assert_eq!(loaded.start_position, original.start_position);
+            assert_eq!(loaded.starting_resources, original.starting_resources);
+        }
+        assert_eq!(
+            loaded.resource_sources.len(),
+            scenario.resource_sources.len()
+        );
+        for (loaded, original) in loaded
+            .resource_sources
+            .iter()
+            .zip(&scenario.resource_sources)
+        {
+            assert!(loaded.prop.position.distance(original.prop.position) < 1e-3);
+            assert_eq!(loaded.resource_amount, original.resource_amount);
+            assert_eq!(loaded.radius, original.radius);
+        }
+        assert_eq!(loaded.obstacles, scenario.obstacles);
+        assert_eq!(loaded.cost_zones, scenario.cost_zones);
+        assert_eq!(loaded.systems.len(), scenario.systems.len());
+    }
+}
@@ -0,0 +1,425 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::Difficulty;
+use crate::environment::{GridArea, GridSettings};
+use crate::player_controller::Civilisation;
+use crate::resources::ResourceType;
+use crate::scenario::{
+    Lighting, Prop, Scenario, ScenarioPlayer, ScenarioResourceSource, ScenarioUnit,
+};
+use crate::spawner::UnitType;
+
+/// Height units and props are placed at, like in the scenario files
+const PLACEMENT_HEIGHT: f32 = 2.0;
+const CELL_SIZE: f32 = 0.2;
+const ASTEROID_MODEL: &str = "3d_models/environment/asteroid_01.glb";
+const PLAYER_COLOURS: [[f32; 3]; 8] = [
+    [0.2, 0.8, 0.2],
+    [0.85, 0.15, 0.1],
+    [0.2, 0.4, 0.9],
+    [0.9, 0.8, 0.1],
+    [0.6, 0.2, 0.8],
+    [0.1, 0.8, 0.8],
+    [0.9, 0.5, 0.1],
+    [0.8, 0.8, 0.8],
+];
+// Distances from the centre of the map, as a fraction of its size
+const START_DISTANCE: f32 = 0.38;
+const HOME_CLUSTER_DISTANCE: f32 = 0.34;
+const BELT_DISTANCE: f32 = 0.28;
+const BELT_WIDTH: f32 = 0.04;
+const CONTESTED_CLUSTER_DISTANCE: f32 = 0.2;
+/// Spread of the asteroids within a cluster, as a fraction of the map size
+const CLUSTER_SPREAD: f32 = 0.015;
+const HOME_CLUSTER_ASTEROIDS: usize = 3;
+const CONTESTED_CLUSTER_ASTEROIDS: usize = 2;
+const HOME_ASTEROID_AMOUNT: f32 = 15_000.0;
+const CONTESTED_ASTEROID_AMOUNT: f32 = 25_000.0;
+/// Belt rocks per world unit of the belt at a density of 1
+const BELT_ROCKS_PER_UNIT: f32 = 0.5;
+const BELT_ROCK_RADII: (f32, f32) = (0.5, 2.0);
+/// Half the width of the gap left in the belt in front of every start position
+const BELT_LANE: f32 = 0.12;
+/// Smaller maps don't leave room for the belt lanes, larger ones make the movement grid huge
+const MAP_SIZE_RANGE: (f32, f32) = (100.0, 600.0);
+const MAX_RESOURCE_RICHNESS: f32 = 100.0;
+
+/// What a generated skirmish map should look like. The same parameters always give the same map.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapParameters {
+    pub seed: u64,
+    pub players: usize,
+    /// Side length of the square map in world units
+    pub map_size: f32,
+    /// Multiplier on the resources in every asteroid
+    pub resource_richness: f32,
+    /// 0 leaves the asteroid belt empty, 1 makes it a wall with a lane for every player
+    pub asteroid_belt_density: f64,
+}
+impl Default for MapParameters {
+    fn default() -> Self {
+        MapParameters {
+            seed: 0,
+            players: 2,
+            map_size: 200.0,
+            resource_richness: 1.0,
+            asteroid_belt_density: 0.2,
+        }
+    }
+}
+
+impl MapParameters {
+    /// Sets the parameter of a command line option like `--map-size` from its value. False if
+    /// the value doesn't parse or lies outside what a map can be generated with.
+    pub fn set(&mut self, option: &str, value: &str) -> bool {
+        match option {
+            "--seed" => value.parse().map(|seed| self.seed = seed).is_ok(),
+            "--players" => match value.parse() {
+                Ok(players) if (2..=PLAYER_COLOURS.len()).contains(&players) => {
+                    self.players = players;
+                    true
+                }
+                _ => false,
+            },
+            "--map-size" => match value.parse() {
+                Ok(size) if (MAP_SIZE_RANGE.0..=MAP_SIZE_RANGE.1).contains(&size) => {
+                    self.map_size = size;
+                    true
+                }
+                _ => false,
+            },
+            "--resource-richness" => match value.parse() {
+                Ok(richness) if (0.0..=MAX_RESOURCE_RICHNESS).contains(&richness) => {
+                    self.resource_richness = richness;
+                    true
+                }
+                _ => false,
+            },
+            "--belt-density" => match value.parse() {
+                Ok(density) if (0.0..=1.0).contains(&density) => {
+                    self.asteroid_belt_density = density;
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+    /// The same parameters brought within the ranges a map can be generated with
+    pub fn clamped(&self) -> MapParameters {
+        let default: MapParameters = MapParameters::default();
+        let within = |value: f32, min: f32, max: f32, default: f32| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(min, max)
+            }
+        };
+        MapParameters {
+            seed: self.seed,
+            players: self.players.clamp(2, PLAYER_COLOURS.len()),
+            map_size: within(
+                self.map_size,
+                MAP_SIZE_RANGE.0,
+                MAP_SIZE_RANGE.1,
+                default.map_size,
+            ),
+            resource_richness: within(
+                self.resource_richness,
+                0.0,
+                MAX_RESOURCE_RICHNESS,
+                default.resource_richness,
+            ),
+            asteroid_belt_density: if self.asteroid_belt_density.is_nan() {
+                default.asteroid_belt_density
+            } else {
+                self.asteroid_belt_density.clamp(0.0, 1.0)
+            },
+        }
+    }
+}
+
+/// SplitMix64. Kept in the crate so that a seed gives the same map on every build.
+struct SeededRng(u64);
+impl SeededRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Uniform in [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+fn on_map(offset: Vec2) -> Vec3 {
+    Vec3::new(offset.x, PLACEMENT_HEIGHT, offset.y)
+}
+fn asteroid(name: &str, position: Vec2, rotation: f32, scale: f32) -> Prop {
+    Prop {
+        name: name.into(),
+        info: "Asteroid. Rich in Plotanium".into(),
+        model: ASTEROID_MODEL.into(),
+        scene: "Scene0".into(),
+        position: on_map(position).as_dvec3(),
+        rotation,
+        scale,
+        orbit: None,
+    }
+}
+/// Offsets of the asteroids in a cluster, shared by all players so that no one is favoured
+fn cluster_offsets(rng: &mut SeededRng, asteroids: usize, spread: f32) -> Vec<Vec2> {
+    (0..asteroids)
+        .map(|_| Vec2::from_angle(rng.range(0.0, TAU)) * rng.range(0.3, 1.0) * spread)
+        .collect()
+}
+
+/// Lays out start positions, asteroid clusters and an asteroid belt with rotational symmetry,
+/// so every player starts out with the same map around them
+pub fn generate_map(parameters: &MapParameters) -> Scenario {
+    let parameters: &MapParameters = &parameters.clamped();
+    let mut rng: SeededRng = SeededRng(parameters.seed);
+    let players: usize = parameters.players;
+    let size: f32 = parameters.map_size;
+    let cells: u32 = (size / CELL_SIZE).ceil() as u32;
+    let step: f32 = TAU / players as f32;
+    let first_angle: f32 = rng.range(0.0, TAU);
+    let home_cluster: Vec<Vec2> =
+        cluster_offsets(&mut rng, HOME_CLUSTER_ASTEROIDS, CLUSTER_SPREAD * size);
+    let contested_cluster: Vec<Vec2> =
+        cluster_offsets(&mut rng, CONTESTED_CLUSTER_ASTEROIDS, CLUSTER_SPREAD * size);
+    let home_amount: i32 = (HOME_ASTEROID_AMOUNT * parameters.resource_richness) as i32;
+    let contested_amount: i32 = (CONTESTED_ASTEROID_AMOUNT * parameters.resource_richness) as i32;
+
+    // One sector of the belt, as (angle within the sector, distance, radius), repeated for everyone
+    let belt_length: f32 = step * BELT_DISTANCE * size;
+    let belt_rocks: usize =
+        (belt_length * BELT_ROCKS_PER_UNIT * parameters.asteroid_belt_density as f32) as usize;
+    let belt_sector: Vec<(f32, f32, f32)> = (0..belt_rocks)
+        .map(|_| {
+            (
+                rng.range(BELT_LANE, step - BELT_LANE),
+                rng.range(
+                    BELT_DISTANCE - BELT_WIDTH / 2.0,
+                    BELT_DISTANCE + BELT_WIDTH / 2.0,
+                ) * size,
+                rng.range(BELT_ROCK_RADII.0, BELT_ROCK_RADII.1),
+            )
+        })
+        .collect();
+
+    let mut scenario_players: Vec<ScenarioPlayer> = Vec::new();
+    let mut resource_sources: Vec<ScenarioResourceSource> = Vec::new();
+    let mut props: Vec<Prop> = Vec::new();
+    let mut obstacles: Vec<GridArea> = Vec::new();
+    for (player, colour) in PLAYER_COLOURS.into_iter().enumerate().take(players) {
+        let angle: f32 = first_angle + step * player as f32;
+        let direction: Vec2 = Vec2::from_angle(angle);
+        let start: Vec2 = direction * START_DISTANCE * size;
+        // Escorts sit between the station and the centre of the map
+        let escort: Vec2 = -direction * 3.0;
+        let side: Vec2 = direction.perp() * 1.5;
+        scenario_players.push(ScenarioPlayer {
+            civilisation: Civilisation("Greek".into()),
+            local: player == 0,
+            ai: (player != 0).then_some(Difficulty::Normal),
+            colour,
+            start_position: Some(on_map(start)),
+            starting_resources: HashMap::from([(ResourceType::Plotanium, 100)]),
+            units: vec![
+                ScenarioUnit {
+                    unit_type: UnitType::Spacestation,
+                    position: on_map(start),
+                },
+                ScenarioUnit {
+                    unit_type: UnitType::Cruiser,
+                    position: on_map(start + escort + side),
+                },
+                ScenarioUnit {
+                    unit_type: UnitType::Cruiser,
+                    position: on_map(start + escort - side),
+                },
+            ],
+        });
+        let home: Vec2 = direction * HOME_CLUSTER_DISTANCE * size;
+        for offset in &home_cluster {
+            resource_sources.push(ScenarioResourceSource {
+                prop: asteroid("Asteroid", home + direction.rotate(*offset), 0.0, 1.0),
+                resource_type: ResourceType::Plotanium,
+                resource_amount: home_amount,
+                radius: 1.0,
+            });
+        }
+        // Halfway to the next player, so neighbours are the same distance away from it
+        let contested_direction: Vec2 = Vec2::from_angle(angle + step / 2.0);
+        let contested: Vec2 = contested_direction * CONTESTED_CLUSTER_DISTANCE * size;
+        for offset in &contested_cluster {
+            resource_sources.push(ScenarioResourceSource {
+                prop: asteroid("Asteroid", contested + direction.rotate(*offset), 0.0, 1.0),
+                resource_type: ResourceType::Plotanium,
+                resource_amount: contested_amount,
+                radius: 1.0,
+            });
+        }
+        for (rock_angle, distance, radius) in &belt_sector {
+            let position: Vec2 = Vec2::from_angle(angle + rock_angle) * *distance;
+            props.push(asteroid(
+                "Belt asteroid",
+                position,
+                (angle + rock_angle).to_degrees(),
+                *radius,
+            ));
+            obstacles.push(GridArea {
+                centre: position,
+                radius: *radius,
+            });
+        }
+    }
+    Scenario {
+        name: format!("Generated map {}", parameters.seed),
+        grid: GridSettings {
+            cell_size: CELL_SIZE,
+            grid_width: cells,
+            grid_height: cells,
+            xy_offset: Vec2::splat(cells as f32 / 2.0),
+            density: parameters.asteroid_belt_density,
+        },
+        lighting: Lighting {
+            illuminance: 3200.0,
+            position: Vec3::new(0.0, 20.0, 0.0),
+            pitch: -45.0,
+            ambient_brightness: None,
+        },
+        players: scenario_players,
+        resource_sources,
+        props,
+        obstacles,
+        cost_zones: Vec::new(),
+        hazards: Vec::new(),
+        galaxy_position: Vec2::ZERO,
+        systems: Vec::new(),
+        jump_gates: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::MovementGrid;
+
+    fn parameters(seed: u64) -> MapParameters {
+        MapParameters {
+            seed,
+            players: 4,
+            asteroid_belt_density: 0.8,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn the_same_seed_generates_the_same_map() {
+        let first: String = ron::to_string(&generate_map(&parameters(42))).unwrap();
+        let second: String = ron::to_string(&generate_map(&parameters(42))).unwrap();
+        assert_eq!(first, second);
+        let other: String = ron::to_string(&generate_map(&parameters(43))).unwrap();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn players_get_the_same_resources() {
+        let scenario: Scenario = generate_map(&parameters(7));
+        assert_eq!(scenario.players.len(), 4);
+        assert_eq!(
+            scenario
+                .players
+                .iter()
+                .filter(|player| player.local)
+                .count(),
+            1
+        );
+        for player in &scenario.players {
+            let start: Vec2 = player.start_position.unwrap().xz();
+            let mut nearby: Vec<i32> = scenario
+                .resource_sources
+                .iter()
+                .filter(|source| source.prop.position.as_vec3().xz().distance(start) < 20.0)
+                .map(|source| source.resource_amount)
+                .collect();
+            nearby.sort();
+            assert_eq!(nearby, vec![15_000; HOME_CLUSTER_ASTEROIDS]);
+        }
+    }
+
+    #[test]
+    fn start_positions_and_resources_are_not_blocked() {
+        let scenario: Scenario = generate_map(&parameters(3));
+        let mut movement_grid: MovementGrid = MovementGrid::new(scenario.grid.clone());
+        movement_grid.rebuild(&scenario.obstacles, &scenario.cost_zones);
+        assert!(!scenario.obstacles.is_empty());
+        let positions = scenario
+            .players
+            .iter()
+            .map(|player| player.start_position.unwrap())
+            .chain(
+                scenario
+                    .resource_sources
+                    .iter()
+                    .map(|source| source.prop.position.as_vec3()),
+            );
+        for position in positions {
+            let cell: UVec2 = movement_grid
+                .settings
+                .world_to_cell(position.xz())
+                .expect("placed on the map");
+            assert!(!movement_grid.is_blocked(cell));
+        }
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected_or_clamped() {
+        let mut options: MapParameters = MapParameters::default();
+        for (option, value) in [
+            ("--map-size", "0"),
+            ("--map-size", "-5"),
+            ("--map-size", "NaN"),
+            ("--map-size", "100000"),
+            ("--players", "1"),
+            ("--players", "9"),
+            ("--resource-richness", "-1"),
+            ("--belt-density", "2"),
+            ("--seed", "x"),
+            ("--unknown", "1"),
+        ] {
+            assert!(!options.set(option, value), "{} {}", option, value);
+        }
+        assert!(options.set("--map-size", "150"));
+        assert!(options.set("--players", "3"));
+        assert_eq!(options.map_size, 150.0);
+        assert_eq!(options.players, 3);
+
+        for map_size in [0.0, -10.0, f32::NAN, f32::INFINITY] {
+            let scenario: Scenario = generate_map(&MapParameters {
+                map_size,
+                resource_richness: f32::NAN,
+                ..parameters(1)
+            });
+            let cells: u32 = scenario.grid.grid_width;
+            assert!(cells >= (MAP_SIZE_RANGE.0 / CELL_SIZE) as u32);
+            assert!(cells <= (MAP_SIZE_RANGE.1 / CELL_SIZE).ceil() as u32);
+            assert!(scenario
+                .resource_sources
+                .iter()
+                .all(|source| source.resource_amount > 0));
+        }
+    }
+}
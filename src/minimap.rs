@@ -0,0 +1,621 @@
+use crate::environment::{GridSettings, MovementGrid};
+use crate::key_bindings::{InputAction, InputActions};
+use crate::movable::{Movable, MoveCommand};
+use crate::ownable::{Owner, PlayerColour, Selected};
+use crate::player_controller::{
+    centre_camera_on, CameraControllerSettings, LocalPlayer, RenderLayerMap,
+};
+use crate::resources::ResourceSource;
+use crate::spawner::{UnitInformation, UnitType};
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy::render::camera::{ClearColorConfig, RenderTarget, ScalingMode};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::view::RenderLayers;
+use bevy::ui::RelativeCursorPosition;
+
+const MINIMAP_SIZE: u32 = 256;
+const MINIMAP_CAMERA_HEIGHT: f32 = 200.0;
+const MINIMAP_BACKGROUND: Color = Color::srgb(0.02, 0.02, 0.04);
+const VIEW_OUTLINE: Color = Color::WHITE;
+/// Marker colour of units whose owner has no `PlayerColour`
+pub const NEUTRAL_MARKER: Color = Color::srgb(0.6, 0.6, 0.6);
+const RESOURCE_MARKER: Color = Color::srgb(0.95, 0.8, 0.2);
+/// The icon minimap is redrawn this often rather than every frame
+const ICON_MINIMAP_REFRESH: f32 = 0.1;
+
+pub struct Minimap;
+impl Plugin for Minimap {
+    fn build(&self, app: &mut App) {
+        app.init_gizmo_group::<MinimapGizmos>()
+            .init_resource::<MinimapSettings>()
+            .add_systems(Startup, configure_minimap_gizmos)
+            .add_systems(
+                Update,
+                (
+                    minimap_input,
+                    follow_active_system,
+                    draw_view_outline.run_if(not(resource_exists::<IconMinimap>)),
+                    draw_icon_minimap.run_if(resource_exists::<IconMinimap>),
+                ),
+            );
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MinimapRenderer {
+    /// A second 3D camera looking down on the map
+    #[default]
+    Camera,
+    /// Icons drawn into a small image on the CPU, without an extra render pass
+    Icons,
+}
+#[derive(Resource, Default)]
+pub struct MinimapSettings {
+    pub renderer: MinimapRenderer,
+}
+/// The image the icon minimap draws into
+#[derive(Resource)]
+struct IconMinimap {
+    image: Handle<Image>,
+}
+/// Gizmos only the minimap camera renders
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct MinimapGizmos;
+#[derive(Component)]
+pub struct MinimapCamera;
+/// The UI image showing the minimap, which takes the clicks
+#[derive(Component)]
+pub struct MinimapImage;
+/// A unit's blip on the minimap
+#[derive(Component)]
+pub struct MinimapMarker;
+
+pub fn initialise_mini_map(
+    commands: &mut Commands,
+    images: ResMut<Assets<Image>>,
+    grid_settings: &GridSettings,
+    settings: &MinimapSettings,
+) -> Entity {
+    let image_handle: Handle<Image> = match settings.renderer {
+        MinimapRenderer::Camera => initialise_camera_minimap(commands, images, grid_settings),
+        MinimapRenderer::Icons => initialise_icon_minimap(commands, images),
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(65.0),
+                height: Val::Percent(80.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                ImageBundle {
+                    image: UiImage::from(image_handle),
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..Default::default()
+                    },
+                    ..default()
+                },
+                MinimapImage,
+                Interaction::None,
+                RelativeCursorPosition::default(),
+            ));
+        })
+        .id()
+}
+fn initialise_icon_minimap(
+    commands: &mut Commands,
+    mut images: ResMut<Assets<Image>>,
+) -> Handle<Image> {
+    let image: Image = Image::new_fill(
+        Extent3d {
+            width: MINIMAP_SIZE,
+            height: MINIMAP_SIZE,
+            ..default()
+        },
+        TextureDimension::D2,
+        &MINIMAP_BACKGROUND.to_srgba().to_u8_array(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    let image_handle: Handle<Image> = images.add(image);
+    commands.insert_resource(IconMinimap {
+        image: image_handle.clone(),
+    });
+    image_handle
+}
+fn initialise_camera_minimap(
+    commands: &mut Commands,
+    mut images: ResMut<Assets<Image>>,
+    grid_settings: &GridSettings,
+) -> Handle<Image> {
+    let size = Extent3d {
+        width: MINIMAP_SIZE,
+        height: MINIMAP_SIZE,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    image.resize(size);
+    let image_handle = images.add(image);
+
+    let bounds: Rect = grid_settings.world_bounds();
+    commands.spawn((
+        Camera3dBundle {
+            camera_3d: Camera3d { ..default() },
+            camera: Camera {
+                clear_color: ClearColorConfig::Custom(MINIMAP_BACKGROUND),
+                // render before the "main pass" camera
+                order: -1,
+                target: RenderTarget::Image(image_handle.clone()),
+                ..default()
+            },
+            projection: minimap_projection(bounds),
+            transform: minimap_camera_transform(bounds),
+            ..default()
+        },
+        MinimapCamera,
+        RenderLayers::from_layers(&[
+            RenderLayerMap::General as usize,
+            RenderLayerMap::Minimap as usize,
+        ]),
+    ));
+    image_handle
+}
+
+/// Orthographic, so that the whole map fills the minimap whatever its size
+fn minimap_projection(bounds: Rect) -> Projection {
+    OrthographicProjection {
+        scaling_mode: ScalingMode::Fixed {
+            width: bounds.width(),
+            height: bounds.height(),
+        },
+        far: 2.0 * MINIMAP_CAMERA_HEIGHT,
+        ..default()
+    }
+    .into()
+}
+fn minimap_camera_transform(bounds: Rect) -> Transform {
+    Transform::from_translation(bounds.center().extend(MINIMAP_CAMERA_HEIGHT).xzy())
+        .looking_to(Vec3::NEG_Y, Vec3::Z)
+}
+/// The movement grid is swapped for that of the system on screen, so the minimap shows it too
+fn follow_active_system(
+    movement_grid: Res<MovementGrid>,
+    mut cameras: Query<(&mut Transform, &mut Projection), With<MinimapCamera>>,
+) {
+    if !movement_grid.is_changed() {
+        return;
+    }
+    let bounds: Rect = movement_grid.settings.world_bounds();
+    for (mut transform, mut projection) in cameras.iter_mut() {
+        let target: Transform = minimap_camera_transform(bounds);
+        if *transform != target {
+            *transform = target;
+            *projection = minimap_projection(bounds);
+        }
+    }
+}
+
+fn configure_minimap_gizmos(mut config_store: ResMut<GizmoConfigStore>) {
+    let (config, _) = config_store.config_mut::<MinimapGizmos>();
+    config.render_layers = RenderLayers::layer(RenderLayerMap::Minimap as usize);
+    config.line_width = 2.0;
+}
+
+/// Pixel of a minimap of the given size showing the world position (x, z), or None if it lies
+/// off the map. Laid out like the minimap camera sees the map: +z up and +x to the left.
+pub fn world_to_minimap(position: Vec2, bounds: Rect, size: UVec2) -> Option<UVec2> {
+    if !bounds.contains(position) {
+        return None;
+    }
+    let normalized: Vec2 = (bounds.max - position) / bounds.size();
+    Some(
+        (normalized * size.as_vec2())
+            .as_uvec2()
+            .min(size - UVec2::ONE),
+    )
+}
+/// World position (x, z) under a point of the minimap, given relative to its size with the
+/// origin in the top left corner
+pub fn minimap_to_world(normalized: Vec2, bounds: Rect) -> Vec2 {
+    bounds.max - normalized * bounds.size()
+}
+/// Where a ray through the viewport position hits the ground plane
+fn ground_point(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    viewport_position: Vec2,
+) -> Option<Vec3> {
+    let ray: Ray3d = camera.viewport_to_world(camera_transform, viewport_position)?;
+    let distance: f32 = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+    Some(ray.get_point(distance))
+}
+/// The ground area the camera sees, or None if part of the view is above the horizon
+pub fn view_footprint(camera: &Camera, camera_transform: &GlobalTransform) -> Option<[Vec3; 4]> {
+    let size: Vec2 = camera.logical_viewport_size()?;
+    let corners: [Vec2; 4] = [
+        Vec2::ZERO,
+        Vec2::new(size.x, 0.0),
+        size,
+        Vec2::new(0.0, size.y),
+    ];
+    let mut footprint: [Vec3; 4] = [Vec3::ZERO; 4];
+    for (point, corner) in footprint.iter_mut().zip(corners) {
+        *point = ground_point(camera, camera_transform, corner)?;
+    }
+    Some(footprint)
+}
+
+/// Left click or drag moves the main camera, right click sends the selected units there
+fn minimap_input(
+    mut commands: Commands,
+    actions: InputActions,
+    mut dragging: Local<bool>,
+    minimap: Query<&RelativeCursorPosition, With<MinimapImage>>,
+    movement_grid: Res<MovementGrid>,
+    mut main_camera: Query<&mut Transform, With<CameraControllerSettings>>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+    selected_units: Query<(Entity, &Owner), (With<Selected>, With<Movable>)>,
+) {
+    let Ok(cursor) = minimap.get_single() else {
+        return;
+    };
+    if actions.just_pressed(InputAction::Select) && cursor.mouse_over() {
+        *dragging = true;
+    }
+    if !actions.pressed(InputAction::Select) {
+        *dragging = false;
+    }
+    let Some(normalized) = cursor.normalized else {
+        return;
+    };
+    // Dragging past the edge keeps the camera at the edge of the map
+    let target: Vec3 = minimap_to_world(
+        normalized.clamp(Vec2::ZERO, Vec2::ONE),
+        movement_grid.settings.world_bounds(),
+    )
+    .extend(0.0)
+    .xzy();
+    if *dragging {
+        if let Ok(mut main_camera_transform) = main_camera.get_single_mut() {
+            centre_camera_on(&mut main_camera_transform, target);
+        }
+    }
+    if actions.just_pressed(InputAction::Command) && cursor.mouse_over() {
+        let Ok(local_player) = local_player.get_single() else {
+            return;
+        };
+        for (entity, owner) in selected_units.iter() {
+            if owner.player == local_player {
+                commands.entity(entity).remove::<MoveCommand>();
+                commands.entity(entity).insert(MoveCommand {
+                    target: target.xz(),
+                });
+            }
+        }
+    }
+}
+
+fn draw_view_outline(
+    mut gizmos: Gizmos<MinimapGizmos>,
+    main_camera: Query<(&Camera, &GlobalTransform), With<CameraControllerSettings>>,
+) {
+    let Ok((camera, camera_transform)) = main_camera.get_single() else {
+        return;
+    };
+    let Some(footprint) = view_footprint(camera, camera_transform) else {
+        return;
+    };
+    gizmos.linestrip(
+        footprint
+            .iter()
+            .chain(footprint.first())
+            .map(|corner| *corner + Vec3::Y),
+        VIEW_OUTLINE,
+    );
+}
+
+fn fill_square(data: &mut [u8], size: UVec2, centre: UVec2, half_width: u32, colour: [u8; 4]) {
+    let min: UVec2 = centre.saturating_sub(UVec2::splat(half_width));
+    let max: UVec2 = (centre + UVec2::splat(half_width)).min(size - UVec2::ONE);
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let index: usize = ((y * size.x + x) * 4) as usize;
+            data[index..index + 4].copy_from_slice(&colour);
+        }
+    }
+}
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const BOTTOM: u8 = 4;
+const TOP: u8 = 8;
+/// Cohen–Sutherland region of the point relative to the rectangle
+fn outcode(point: DVec2, min: DVec2, max: DVec2) -> u8 {
+    let mut code: u8 = 0;
+    if point.x < min.x {
+        code |= LEFT;
+    } else if point.x > max.x {
+        code |= RIGHT;
+    }
+    if point.y < min.y {
+        code |= BOTTOM;
+    } else if point.y > max.y {
+        code |= TOP;
+    }
+    code
+}
+/// Cohen–Sutherland clipping of the segment to the rectangle, `None` if it misses the rectangle
+fn clip_line(from: Vec2, to: Vec2, rect: Rect) -> Option<(Vec2, Vec2)> {
+    if !from.is_finite() || !to.is_finite() {
+        return None;
+    }
+    let (min, max): (DVec2, DVec2) = (rect.min.as_dvec2(), rect.max.as_dvec2());
+    let mut ends: [DVec2; 2] = [from.as_dvec2(), to.as_dvec2()];
+    let mut codes: [u8; 2] = ends.map(|end| outcode(end, min, max));
+    // Each end is moved onto at most two edges
+    for _ in 0..4 {
+        if codes[0] | codes[1] == 0 {
+            break;
+        }
+        if codes[0] & codes[1] != 0 {
+            return None;
+        }
+        let outside: usize = if codes[0] != 0 { 0 } else { 1 };
+        let [start, end] = ends;
+        let delta: DVec2 = end - start;
+        let code: u8 = codes[outside];
+        ends[outside] = if code & TOP != 0 {
+            DVec2::new(start.x + delta.x * (max.y - start.y) / delta.y, max.y)
+        } else if code & BOTTOM != 0 {
+            DVec2::new(start.x + delta.x * (min.y - start.y) / delta.y, min.y)
+        } else if code & RIGHT != 0 {
+            DVec2::new(max.x, start.y + delta.y * (max.x - start.x) / delta.x)
+        } else {
+            DVec2::new(min.x, start.y + delta.y * (min.x - start.x) / delta.x)
+        };
+        codes[outside] = outcode(ends[outside], min, max);
+    }
+    if codes[0] & codes[1] != 0 {
+        return None;
+    }
+    // Rounding can leave an end a hair outside
+    let [start, end] = ends.map(|end| end.clamp(min, max).as_vec2());
+    Some((start, end))
+}
+/// Bresenham line, clipped to the image first so far away ends cost nothing
+fn draw_line(data: &mut [u8], size: UVec2, from: Vec2, to: Vec2, colour: [u8; 4]) {
+    let Some((from, to)) = clip_line(
+        from,
+        to,
+        Rect::from_corners(Vec2::ZERO, size.as_vec2() - Vec2::ONE),
+    ) else {
+        return;
+    };
+    let (from, to): (IVec2, IVec2) = (from.floor().as_ivec2(), to.floor().as_ivec2());
+    let delta: IVec2 = IVec2::new((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let step: IVec2 = IVec2::new(
+        if from.x < to.x { 1 } else { -1 },
+        if from.y < to.y { 1 } else { -1 },
+    );
+    let mut error: i32 = delta.x + delta.y;
+    let mut point: IVec2 = from;
+    loop {
+        if point.cmpge(IVec2::ZERO).all() && point.cmplt(size.as_ivec2()).all() {
+            let index: usize = ((point.y as u32 * size.x + point.x as u32) * 4) as usize;
+            data[index..index + 4].copy_from_slice(&colour);
+        }
+        if point == to {
+            break;
+        }
+        let doubled: i32 = 2 * error;
+        if doubled >= delta.y {
+            error += delta.y;
+            point.x += step.x;
+        }
+        if doubled <= delta.x {
+            error += delta.x;
+            point.y += step.y;
+        }
+    }
+}
+/// Position on the minimap without clamping, so lines leaving the map keep their direction
+fn world_to_minimap_unclamped(position: Vec2, bounds: Rect, size: UVec2) -> Vec2 {
+    (bounds.max - position) / bounds.size() * size.as_vec2()
+}
+
+fn draw_icon_minimap(
+    time: Res<Time>,
+    mut since_refresh: Local<f32>,
+    icon_minimap: Res<IconMinimap>,
+    mut images: ResMut<Assets<Image>>,
+    movement_grid: Res<MovementGrid>,
+    units: Query<(&GlobalTransform, &Owner, &UnitInformation)>,
+    resources: Query<&GlobalTransform, With<ResourceSource>>,
+    player_colours: Query<&PlayerColour>,
+    main_camera: Query<(&Camera, &GlobalTransform), With<CameraControllerSettings>>,
+) {
+    *since_refresh += time.delta_seconds();
+    if *since_refresh < ICON_MINIMAP_REFRESH {
+        return;
+    }
+    *since_refresh = 0.0;
+    let Some(image) = images.get_mut(&icon_minimap.image) else {
+        return;
+    };
+    let size: UVec2 = image.size();
+    let bounds: Rect = movement_grid.settings.world_bounds();
+    let background: [u8; 4] = MINIMAP_BACKGROUND.to_srgba().to_u8_array();
+    for pixel in image.data.chunks_exact_mut(4) {
+        pixel.copy_from_slice(&background);
+    }
+    let resource_colour: [u8; 4] = RESOURCE_MARKER.to_srgba().to_u8_array();
+    for transform in resources.iter() {
+        if let Some(pixel) = world_to_minimap(transform.translation().xz(), bounds, size) {
+            fill_square(&mut image.data, size, pixel, 0, resource_colour);
+        }
+    }
+    for (transform, owner, unit_information) in units.iter() {
+        let Some(pixel) = world_to_minimap(transform.translation().xz(), bounds, size) else {
+            continue;
+        };
+        let colour: Color = player_colours
+            .get(owner.player)
+            .map_or(NEUTRAL_MARKER, |colour| colour.0);
+        let half_width: u32 = match unit_information.unit_type {
+            UnitType::Spacestation => 2,
+            UnitType::Cruiser | UnitType::MiningStation => 1,
+        };
+        fill_square(
+            &mut image.data,
+            size,
+            pixel,
+            half_width,
+            colour.to_srgba().to_u8_array(),
+        );
+    }
+    let Ok((camera, camera_transform)) = main_camera.get_single() else {
+        return;
+    };
+    if let Some(footprint) = view_footprint(camera, camera_transform) {
+        let outline: [u8; 4] = VIEW_OUTLINE.to_srgba().to_u8_array();
+        for (i, corner) in footprint.iter().enumerate() {
+            let next: Vec3 = footprint[(i + 1) % footprint.len()];
+            draw_line(
+                &mut image.data,
+                size,
+                world_to_minimap_unclamped(corner.xz(), bounds, size),
+                world_to_minimap_unclamped(next.xz(), bounds, size),
+                outline,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds() -> Rect {
+        Rect::new(-100.0, -100.0, 100.0, 100.0)
+    }
+
+    #[test]
+    fn map_corners_land_in_minimap_corners() {
+        let size: UVec2 = UVec2::splat(256);
+        // +x is drawn to the left and +z at the top, like the minimap camera sees it
+        assert_eq!(
+            world_to_minimap(Vec2::new(99.9, 99.9), bounds(), size),
+            Some(UVec2::new(0, 0))
+        );
+        assert_eq!(
+            world_to_minimap(Vec2::new(-100.0, -100.0), bounds(), size),
+            Some(UVec2::new(255, 255))
+        );
+        assert_eq!(
+            world_to_minimap(Vec2::ZERO, bounds(), size),
+            Some(UVec2::new(128, 128))
+        );
+    }
+
+    #[test]
+    fn positions_off_the_map_are_not_drawn() {
+        assert_eq!(
+            world_to_minimap(Vec2::new(150.0, 0.0), bounds(), UVec2::splat(256)),
+            None
+        );
+    }
+
+    #[test]
+    fn clicking_the_minimap_finds_the_drawn_position() {
+        let size: UVec2 = UVec2::splat(200);
+        let position: Vec2 = Vec2::new(37.0, -52.0);
+        let pixel: UVec2 = world_to_minimap(position, bounds(), size).unwrap();
+        let clicked: Vec2 = minimap_to_world(
+            (pixel.as_vec2() + Vec2::splat(0.5)) / size.as_vec2(),
+            bounds(),
+        );
+        assert!(clicked.distance(position) <= 1.0);
+    }
+
+    #[test]
+    fn lines_leaving_the_image_are_clipped() {
+        let size: UVec2 = UVec2::splat(8);
+        let mut data: Vec<u8> = vec![0; 8 * 8 * 4];
+        draw_line(
+            &mut data,
+            size,
+            Vec2::new(-4.0, 2.0),
+            Vec2::new(12.0, 2.0),
+            [255; 4],
+        );
+        let row: &[u8] = &data[2 * 8 * 4..3 * 8 * 4];
+        assert!(row.iter().all(|channel| *channel == 255));
+        assert_eq!(
+            data.iter().filter(|channel| **channel == 255).count(),
+            8 * 4
+        );
+    }
+
+    #[test]
+    fn lines_with_far_away_ends_are_clipped_before_drawing() {
+        let size: UVec2 = UVec2::splat(8);
+        let mut data: Vec<u8> = vec![0; 8 * 8 * 4];
+        draw_line(
+            &mut data,
+            size,
+            Vec2::new(-3.0e9, 5.5),
+            Vec2::new(f32::MAX, 5.5),
+            [255; 4],
+        );
+        let row: &[u8] = &data[5 * 8 * 4..6 * 8 * 4];
+        assert!(row.iter().all(|channel| *channel == 255));
+        assert_eq!(
+            data.iter().filter(|channel| **channel == 255).count(),
+            8 * 4
+        );
+    }
+
+    #[test]
+    fn lines_passing_the_image_are_not_drawn() {
+        let size: UVec2 = UVec2::splat(8);
+        let mut data: Vec<u8> = vec![0; 8 * 8 * 4];
+        draw_line(
+            &mut data,
+            size,
+            Vec2::new(-1.0e9, -2.0e9),
+            Vec2::new(2.0e9, 1.0e9),
+            [255; 4],
+        );
+        assert!(data.iter().all(|channel| *channel == 0));
+    }
+
+    #[test]
+    fn diagonal_lines_are_clipped_onto_the_edges() {
+        let rect: Rect = Rect::new(0.0, 0.0, 7.0, 7.0);
+        let (from, to) = clip_line(Vec2::new(-10.0, -10.0), Vec2::new(20.0, 20.0), rect).unwrap();
+        assert_eq!((from, to), (Vec2::ZERO, Vec2::splat(7.0)));
+    }
+}
@@ -1,23 +1,20 @@
 use crate::a_star::{a_star, calculate_a_star};
 use crate::environment::MovementGrid;
+use crate::hazards::InHazard;
 use bevy::ecs::component::Component;
 use bevy::math::Vec3;
 use bevy::prelude::*;
 use bevy::transform::components::Transform;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
-use std::time::Duration;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 pub struct UnitMovement;
 
 impl Plugin for UnitMovement {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (a_star, calculate_a_star))
-            .add_systems(Update, move_units)
-            .insert_resource(MovementTimer(Timer::new(
-                Duration::from_millis(1500),
-                TimerMode::Repeating,
-            )));
+        app.add_systems(FixedUpdate, (a_star, calculate_a_star))
+            .add_systems(FixedUpdate, move_units);
     }
 }
 pub const DISTANCE_FACTOR: f32 = 1.0;
@@ -26,12 +23,12 @@ pub struct NodeCoords {
     pub xy: UVec2,
     pub h: Option<Heading>,
 }
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct PathNode {
     pub xy: Vec2,
     pub h: Heading,
 }
-#[derive(Eq, PartialEq, Hash, Clone, Copy, EnumIter, Debug, Default)]
+#[derive(Eq, PartialEq, Hash, Clone, Copy, EnumIter, Debug, Default, Serialize, Deserialize)]
 pub enum Heading {
     #[default]
     N,
@@ -57,8 +54,6 @@ pub struct MoveCommand {
 #[derive(Component)]
 pub struct Movable {}
 
-#[derive(Resource)]
-struct MovementTimer(Timer);
 #[derive(Component)]
 pub struct MovementPath {
     pub path: Vec<PathNode>,
@@ -104,8 +99,8 @@ pub fn calculate_heading(from: &UVec2, to: &UVec2) -> Heading {
 pub fn check_path_width(current: UVec2, target: UVec2, gridmap: &MovementGrid) -> bool {
     if current.x != target.x
         && current.y != target.y
-        && gridmap.grid[current.x as usize][target.y as usize] != 0
-        && gridmap.grid[target.x as usize][current.y as usize] != 0
+        && gridmap.is_blocked(UVec2::new(current.x, target.y))
+        && gridmap.is_blocked(UVec2::new(target.x, current.y))
     {
         return false;
     }
@@ -125,7 +120,7 @@ pub fn get_neighbours(current: UVec2, gridmap: &MovementGrid) -> Vec<NodeCoords>
                 && (adjacent_cell.x as usize) < gridmap.grid.len()
                 && adjacent_cell.y >= 0
                 && (adjacent_cell.y as usize) < gridmap.grid[0].len()
-                && gridmap.grid[adjacent_cell.x as usize][adjacent_cell.y as usize] == 0
+                && !gridmap.is_blocked(adjacent_cell.as_uvec2())
                 && adjacent_cell.as_uvec2() != current
                 && check_path_width(current, adjacent_cell.as_uvec2(), gridmap)
             {
@@ -202,13 +197,13 @@ fn move_towards(
     target_reached
 }
 fn move_units(
-    mut movables: Query<(Entity, &mut Transform, &mut MovementPath)>,
+    mut movables: Query<(Entity, &mut Transform, &mut MovementPath, Option<&InHazard>)>,
     time: Res<Time>,
     mut commands: Commands,
 ) {
-    let speed: f64 = 1.0;
     let rotation_speed: f64 = 1.0;
-    for (entity, mut transform, mut movementpath) in movables.iter_mut() {
+    for (entity, mut transform, mut movementpath, in_hazard) in movables.iter_mut() {
+        let speed: f64 = in_hazard.map_or(1.0, |in_hazard| in_hazard.speed as f64);
         let node: &PathNode = match movementpath.path.last() {
             Some(n) => n,
             None => {
@@ -0,0 +1,287 @@
+use std::f64::consts::{PI, TAU};
+use std::time::Duration;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::environment::{GridArea, MovementGrid};
+use crate::galaxy::Galaxy;
+use crate::large_world::LargeWorldPosition;
+use crate::resource_collection::{Collector, CollectorState};
+use crate::simulation::MatchTime;
+
+/// Newton steps when solving Kepler's equation. A fixed count keeps the result the same on
+/// every run, and converges for any eccentricity the scenarios use.
+const KEPLER_ITERATIONS: usize = 16;
+
+pub struct Orbits;
+impl Plugin for Orbits {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ObstacleTimer(Timer::new(
+            Duration::from_millis(500),
+            TimerMode::Repeating,
+        )))
+        .add_systems(
+            FixedUpdate,
+            (
+                move_orbiting_bodies,
+                carry_docked_collectors.after(move_orbiting_bodies),
+                update_orbital_obstacles,
+            ),
+        );
+    }
+}
+/// A Keplerian orbit in the horizontal plane. With no eccentricity it is a circle.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Orbit {
+    /// What the body circles around, in the same coordinates as its position
+    pub centre: DVec3,
+    pub semi_major_axis: f64,
+    #[serde(default)]
+    pub eccentricity: f64,
+    /// Seconds for one revolution
+    pub period: f64,
+    /// Degrees along the orbit at the start of the match, counted from the periapsis
+    #[serde(default)]
+    pub phase: f64,
+    /// Degrees the periapsis is turned around the centre, counted from the x axis
+    #[serde(default)]
+    pub periapsis: f64,
+    /// Radius the body blocks on the movement grid, if it gets in the way of ships at all
+    #[serde(default)]
+    pub obstacle_radius: Option<f32>,
+}
+impl Orbit {
+    /// Where the body is after the given seconds of match time. Only depends on the time, so
+    /// orbits never drift apart between runs or after pausing.
+    pub fn position_at(&self, seconds: f64) -> DVec3 {
+        let eccentricity: f64 = self.eccentricity.clamp(0.0, 0.99);
+        let mean_anomaly: f64 = (self.phase.to_radians()
+            + TAU * seconds / self.period.max(f64::EPSILON))
+        .rem_euclid(TAU);
+        // Starting from the mean anomaly overshoots on very elongated orbits
+        let mut eccentric_anomaly: f64 = if eccentricity > 0.8 { PI } else { mean_anomaly };
+        for _ in 0..KEPLER_ITERATIONS {
+            eccentric_anomaly -=
+                (eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly)
+                    / (1.0 - eccentricity * eccentric_anomaly.cos());
+        }
+        let semi_minor_axis: f64 = self.semi_major_axis * (1.0 - eccentricity.powi(2)).sqrt();
+        let x: f64 = self.semi_major_axis * (eccentric_anomaly.cos() - eccentricity);
+        let z: f64 = semi_minor_axis * eccentric_anomaly.sin();
+        let (sin, cos) = self.periapsis.to_radians().sin_cos();
+        self.centre + DVec3::new(x * cos - z * sin, 0.0, x * sin + z * cos)
+    }
+}
+#[derive(Component)]
+pub struct Orbiting(pub Orbit);
+#[derive(Resource)]
+struct ObstacleTimer(Timer);
+
+/// Puts bodies where their orbit has them at the current match time. The simulation works with
+/// the `Transform`, the body is drawn from its `LargeWorldPosition`.
+fn move_orbiting_bodies(
+    match_time: Res<MatchTime>,
+    mut bodies: Query<(&Orbiting, &mut LargeWorldPosition, &mut Transform)>,
+) {
+    for (orbiting, mut large_world_position, mut transform) in bodies.iter_mut() {
+        let position: DVec3 = orbiting.0.position_at(match_time.elapsed);
+        large_world_position.position = position;
+        transform.translation = position.as_vec3();
+    }
+}
+
+/// Collectors mining an orbiting asteroid move along with it, by as much as the asteroid moved
+/// this tick
+fn carry_docked_collectors(
+    time: Res<Time>,
+    match_time: Res<MatchTime>,
+    mut collectors: Query<(&Collector, &mut Transform)>,
+    bodies: Query<&Orbiting>,
+) {
+    let now: f64 = match_time.elapsed;
+    let before: f64 = now - time.delta_seconds_f64();
+    for (collector, mut transform) in collectors.iter_mut() {
+        if collector.collecting != CollectorState::Collecting {
+            continue;
+        }
+        let Ok(orbiting) = bodies.get(collector.resource_entity.entity) else {
+            continue;
+        };
+        let moved: DVec3 = orbiting.0.position_at(now) - orbiting.0.position_at(before);
+        transform.translation += moved.as_vec3();
+    }
+}
+
+fn update_orbital_obstacles(
+    time: Res<Time>,
+    match_time: Res<MatchTime>,
+    mut timer: ResMut<ObstacleTimer>,
+    mut galaxy: ResMut<Galaxy>,
+    mut movement_grid: ResMut<MovementGrid>,
+    bodies: Query<&Orbiting>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let now: f64 = match_time.elapsed;
+    let obstacles: Vec<GridArea> = bodies
+        .iter()
+        .filter_map(|orbiting| {
+            Some(GridArea {
+                centre: orbiting.0.position_at(now).as_vec3().xz(),
+                radius: orbiting.0.obstacle_radius?,
+            })
+        })
+        .collect();
+    // Areas outside of a grid leave it untouched
+    galaxy.for_each_grid(&mut movement_grid, |_, grid| {
+        grid.move_obstacles(obstacles.clone())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::GridSettings;
+    use crate::resources::ResourceType;
+    use crate::simulation::Simulation;
+    use crate::spawner::EntityWrapper;
+    use bevy::time::TimeUpdateStrategy;
+
+    fn orbit(eccentricity: f64) -> Orbit {
+        Orbit {
+            centre: DVec3::new(10.0, 2.0, -5.0),
+            semi_major_axis: 20.0,
+            eccentricity,
+            period: 60.0,
+            phase: 0.0,
+            periapsis: 90.0,
+            obstacle_radius: Some(1.0),
+        }
+    }
+
+    #[test]
+    fn circular_orbits_keep_their_radius_and_repeat() {
+        let orbit: Orbit = orbit(0.0);
+        for second in 0..120 {
+            let position: DVec3 = orbit.position_at(second as f64 * 0.7);
+            assert!((position.distance(orbit.centre) - 20.0).abs() < 1e-9);
+            assert_eq!(position.y, orbit.centre.y);
+        }
+        assert!(orbit.position_at(13.0).distance(orbit.position_at(73.0)) < 1e-9);
+        // A quarter of the way around
+        assert!(
+            orbit
+                .position_at(15.0)
+                .distance(DVec3::new(-10.0, 2.0, -5.0))
+                < 1e-9
+        );
+    }
+
+    #[test]
+    fn elliptic_orbits_are_closest_at_periapsis() {
+        let orbit: Orbit = orbit(0.5);
+        let periapsis: DVec3 = orbit.position_at(0.0);
+        let apoapsis: DVec3 = orbit.position_at(30.0);
+        assert!((periapsis.distance(orbit.centre) - 10.0).abs() < 1e-9);
+        assert!((apoapsis.distance(orbit.centre) - 30.0).abs() < 1e-9);
+        // Turned by the periapsis angle onto the z axis
+        assert!(periapsis.distance(orbit.centre + DVec3::new(0.0, 0.0, 10.0)) < 1e-9);
+        assert_eq!(orbit.position_at(1234.5), orbit.position_at(1234.5));
+    }
+
+    #[test]
+    fn moving_obstacles_leave_static_ones_in_place() {
+        let mut grid: MovementGrid = MovementGrid::new(GridSettings {
+            cell_size: 1.0,
+            grid_width: 20,
+            grid_height: 20,
+            xy_offset: Vec2::splat(10.0),
+            density: 0.0,
+        });
+        let rock: GridArea = GridArea {
+            centre: Vec2::ZERO,
+            radius: 2.0,
+        };
+        grid.rebuild(&[rock], &[]);
+        let planet = |x: f32| GridArea {
+            centre: Vec2::new(x, 0.0),
+            radius: 2.0,
+        };
+        grid.move_obstacles(vec![planet(2.0)]);
+        let settings: GridSettings = grid.settings.clone();
+        let cell = |x: f32| settings.world_to_cell(Vec2::new(x, 0.5)).unwrap();
+        assert!(grid.is_blocked(cell(3.5)));
+        grid.move_obstacles(vec![planet(7.0)]);
+        assert!(!grid.is_blocked(cell(3.5)));
+        assert!(grid.is_blocked(cell(0.5)));
+        assert!(grid.is_blocked(cell(7.5)));
+    }
+
+    #[test]
+    fn bodies_and_docked_collectors_follow_the_match_time() {
+        let mut movement_grid: MovementGrid = MovementGrid::new(GridSettings {
+            cell_size: 1.0,
+            grid_width: 100,
+            grid_height: 100,
+            xy_offset: Vec2::splat(50.0),
+            density: 0.0,
+        });
+        movement_grid.rebuild(&[], &[]);
+        let mut app: App = App::new();
+        app.add_plugins((MinimalPlugins, Orbits, Simulation))
+            .insert_resource(Time::<Fixed>::from_hz(50.0))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                20,
+            )))
+            .insert_resource(movement_grid)
+            .insert_resource(Galaxy::default())
+            // As if loaded from a save
+            .insert_resource(MatchTime { elapsed: 100.0 });
+        let orbit: Orbit = orbit(0.0);
+        let spawned_at: DVec3 = orbit.position_at(0.0);
+        let asteroid: Entity = app
+            .world_mut()
+            .spawn((
+                Orbiting(orbit),
+                LargeWorldPosition {
+                    position: spawned_at,
+                    scale: 1.0,
+                },
+                Transform::from_translation(spawned_at.as_vec3()),
+            ))
+            .id();
+        let player: Entity = app.world_mut().spawn_empty().id();
+        let docked: Vec3 = Vec3::new(1.5, 0.0, 0.0);
+        let collector: Entity = app
+            .world_mut()
+            .spawn((
+                Collector {
+                    resource: ResourceType::Plotanium,
+                    resource_entity: EntityWrapper { entity: asteroid },
+                    player: EntityWrapper { entity: player },
+                    collecting: CollectorState::Collecting,
+                },
+                Transform::from_translation(orbit.position_at(100.0).as_vec3() + docked),
+            ))
+            .id();
+        // The first update only starts the clock, then a second of ticks
+        for _ in 0..51 {
+            app.update();
+        }
+        let world: &World = app.world();
+        let elapsed: f64 = world.resource::<MatchTime>().elapsed;
+        assert!((elapsed - 101.0).abs() < 1e-9);
+        let position: DVec3 = orbit.position_at(elapsed);
+        assert_eq!(
+            world.get::<LargeWorldPosition>(asteroid).unwrap().position,
+            position
+        );
+        let asteroid_at: Vec3 = world.get::<Transform>(asteroid).unwrap().translation;
+        assert!(asteroid_at.distance(position.as_vec3()) < 1e-4);
+        let collector_at: Vec3 = world.get::<Transform>(collector).unwrap().translation;
+        assert!(collector_at.distance(asteroid_at + docked) < 1e-3);
+    }
+}
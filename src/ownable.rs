@@ -1,4 +1,6 @@
 use bevy::ecs::component::Component;
+use bevy::ecs::entity::Entity;
+use bevy::prelude::Color;
 
 #[derive(Component)]
 pub struct Selectable;
@@ -8,3 +10,11 @@ pub struct SelectionCircle;
 #[derive(Component)]
 
 pub struct Selected {}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub player: Entity,
+}
+/// Colour a player's units are marked with, e.g. on the minimap
+#[derive(Component, Clone, Copy)]
+pub struct PlayerColour(pub Color);
@@ -1,3 +1,6 @@
+use crate::environment::MovementGrid;
+use crate::key_bindings::{InputAction, InputActions};
+use crate::map_editor::map_editor_closed;
 use crate::movable::{Movable, MoveCommand};
 use crate::ownable::{Selectable, Selected, SelectionCircle};
 use crate::spawner::UnitType;
@@ -11,29 +14,52 @@ use bevy::input::mouse::MouseWheel;
 use bevy::math::Quat;
 use bevy::render::render_resource::{TextureViewDescriptor, TextureViewDimension};
 use bevy::render::view::RenderLayers;
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
 use bevy::window::PrimaryWindow;
 use bevy::{core_pipeline::bloom::BloomSettings, prelude::*};
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub enum TechLevel {
     L0,
+    L1,
+    L2,
+    L3,
 }
-#[derive(Eq, Hash, PartialEq, Clone, Copy)]
-pub enum Civilisation {
-    Greek,
-    // ROMAN,
-    // JAPANESE,
+impl fmt::Display for TechLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TechLevel::L0 => write!(f, "I"),
+            TechLevel::L1 => write!(f, "II"),
+            TechLevel::L2 => write!(f, "III"),
+            TechLevel::L3 => write!(f, "IV"),
+        }
+    }
 }
-#[derive(Component, Clone)]
+/// Name of a civilisation defined in the civilisation files
+#[derive(Eq, Hash, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Civilisation(pub String);
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Ability {
+    /// Drop all movement and collection orders
+    Stop,
+}
+#[derive(Component, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum ContextMenuAction {
     Build(UnitType),
+    Research(String),
+    Ability(Ability),
+    SelfDestruct,
 }
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct PlayerInfo {
     pub civilisation: Civilisation,
     pub tech_level: TechLevel,
     pub context_menu_actions: HashMap<UnitType, Vec<ContextMenuAction>>,
+    pub researched: HashSet<String>,
+    pub researching: HashSet<String>,
 }
 #[derive(Component)]
 pub struct LocalPlayer;
@@ -43,9 +69,29 @@ pub enum RenderLayerMap {
     Main = 1,
     Minimap = 2,
 }
+/// A `MouseScrollUnit::Pixel` scroll of this many pixels zooms as far as one wheel notch
+const PIXELS_PER_SCROLL_LINE: f32 = 40.0;
+pub const CAMERA_BOOKMARKS: usize = 8;
 pub struct PlayerController;
 #[derive(Event)]
 pub struct DeselectEvent;
+/// Something a player should have a look at, like a freshly built unit
+#[derive(Event)]
+pub struct Alert {
+    pub player: Entity,
+    pub position: Vec3,
+    pub message: String,
+}
+#[derive(Resource, Default)]
+pub struct LatestAlert {
+    pub position: Option<Vec3>,
+}
+/// Where a player's camera starts out
+#[derive(Component, Clone, Copy)]
+pub struct StartPosition(pub Vec3);
+/// Which of the match's players this is, counted from 1 in scenario order
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PlayerNumber(pub usize);
 impl Plugin for PlayerController {
     fn build(&self, app: &mut App) {
         app.add_plugins(CameraController)
@@ -54,7 +100,7 @@ impl Plugin for PlayerController {
             .add_systems(
                 Update,
                 (
-                    process_mouse,
+                    process_mouse.run_if(map_editor_closed),
                     mouse_controller.after(process_mouse),
                     asset_loaded,
                 ),
@@ -69,8 +115,19 @@ struct Cubemap {
 struct CameraController;
 impl Plugin for CameraController {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, camera_setup)
-            .add_systems(Update, camera_controller);
+        app.add_event::<Alert>()
+            .init_resource::<LatestAlert>()
+            .add_systems(Startup, camera_setup)
+            .add_systems(
+                Update,
+                (
+                    camera_controller,
+                    record_alerts,
+                    camera_shortcuts.after(camera_controller),
+                    camera_follow.after(camera_shortcuts),
+                    centre_on_start_position.after(camera_controller),
+                ),
+            );
     }
 }
 
@@ -79,24 +136,26 @@ pub struct CameraControllerSettings {
     pub enabled: bool,
     pub initialized: bool,
     pub sensitivity: f32,
-    pub key_forward: KeyCode,
-    pub key_back: KeyCode,
-    pub key_left: KeyCode,
-    pub key_right: KeyCode,
-    pub rotate_key: KeyCode,
     pub rotation_speed: f32,
-    pub key_run: KeyCode,
-    pub mouse_key_enable_mouse: MouseButton,
-    pub mouse_unit_move_button: MouseButton,
-    pub keyboard_key_enable_mouse: KeyCode,
     pub pan_speed: f32,
     pub friction: f32,
     pub pitch: f32,
     pub yaw: f32,
     pub velocity: Vec3,
+    /// Height at which panning moves at `pan_speed`, higher up it speeds up proportionally
+    pub pan_reference_height: f32,
+    /// Distance from the window border in logical pixels within which the cursor pans
+    pub edge_scroll_margin: f32,
     pub zoom_min: f32,
     pub zoom_max: f32,
-    pub zoom_speed: f32,
+    /// Fraction of the height a single scroll step zooms by
+    pub zoom_step: f32,
+    /// How quickly the height follows `zoom_target`, higher is snappier
+    pub zoom_smoothing: f32,
+    pub zoom_target: f32,
+    pub bookmarks: [Option<Transform>; CAMERA_BOOKMARKS],
+    /// Unit the camera stays centred on until the player pans away
+    pub follow: Option<Entity>,
 }
 
 impl Default for CameraControllerSettings {
@@ -105,36 +164,36 @@ impl Default for CameraControllerSettings {
             enabled: true,
             initialized: false,
             sensitivity: 0.5,
-            key_forward: KeyCode::KeyW,
-            key_back: KeyCode::KeyS,
-            key_left: KeyCode::KeyA,
-            key_right: KeyCode::KeyD,
-            rotate_key: KeyCode::ControlLeft,
             rotation_speed: 0.005,
-            key_run: KeyCode::ControlLeft,
-            mouse_key_enable_mouse: MouseButton::Left,
-            mouse_unit_move_button: MouseButton::Right,
-            keyboard_key_enable_mouse: KeyCode::KeyM,
             friction: 0.5,
             pitch: 0.0,
             yaw: 0.0,
             velocity: Vec3::ZERO,
             pan_speed: 4.0,
-            zoom_speed: 500.0,
+            pan_reference_height: 10.0,
+            edge_scroll_margin: 8.0,
+            zoom_step: 0.15,
+            zoom_smoothing: 10.0,
+            zoom_target: 10.0,
             zoom_min: 5.0,
             zoom_max: 100000.0,
+            bookmarks: [None; CAMERA_BOOKMARKS],
+            follow: None,
         }
     }
 }
 
 pub fn camera_controller(
     time: Res<Time>,
-    key_input: Res<ButtonInput<KeyCode>>,
+    actions: InputActions,
     mut mouse_wheel: EventReader<MouseWheel>,
     mut move_toggled: Local<bool>,
     cameras: Query<(&Camera, &GlobalTransform)>,
     mut query: Query<(&mut Transform, &mut CameraControllerSettings), With<Camera>>,
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    movement_grid: Res<MovementGrid>,
     rapier_context: Res<RapierContext>,
+    rayblock: Query<(), With<RayBlock>>,
 ) {
     let dt = time.delta_seconds();
 
@@ -143,6 +202,7 @@ pub fn camera_controller(
             let (yaw, pitch, _roll) = transform.rotation.to_euler(EulerRot::YXZ);
             options.yaw = yaw;
             options.pitch = pitch;
+            options.zoom_target = transform.translation.y;
             options.initialized = true;
         }
         if !options.enabled {
@@ -155,57 +215,59 @@ pub fn camera_controller(
         let mut pitch: f32 = 0.0;
         // let mut roll: f32 = 0.0;
 
-        if key_input.pressed(options.rotate_key) {
-            if key_input.pressed(options.key_forward) {
+        let rotating: bool = actions.pressed(InputAction::CameraRotate);
+        if rotating {
+            if actions.pressed(InputAction::CameraForward) {
                 pitch -= 1.0
             }
-            if key_input.pressed(options.key_back) {
+            if actions.pressed(InputAction::CameraBack) {
                 pitch += 1.0
             }
-            if key_input.pressed(options.key_right) {
+            if actions.pressed(InputAction::CameraRight) {
                 yaw += 1.0;
             }
-            if key_input.pressed(options.key_left) {
+            if actions.pressed(InputAction::CameraLeft) {
                 yaw -= 1.0;
             }
         } else {
-            if key_input.pressed(options.key_forward) {
+            if actions.pressed(InputAction::CameraForward) {
                 axis_input.z += 1.0;
             }
-            if key_input.pressed(options.key_back) {
+            if actions.pressed(InputAction::CameraBack) {
                 axis_input.z -= 1.0;
             }
-            if key_input.pressed(options.key_right) {
+            if actions.pressed(InputAction::CameraRight) {
                 axis_input.x += 1.0;
             }
-            if key_input.pressed(options.key_left) {
+            if actions.pressed(InputAction::CameraLeft) {
                 axis_input.x -= 1.0;
             }
+            // The cursor resting on the UI along the window's edges shouldn't pan
+            if let (Ok(window), true) = (primary_query.get_single(), rayblock.is_empty()) {
+                axis_input += edge_scroll_input(window, options.edge_scroll_margin);
+            }
         }
 
-        if key_input.just_pressed(options.keyboard_key_enable_mouse) {
+        if actions.just_pressed(InputAction::ToggleMouseMovement) {
             *move_toggled = !*move_toggled;
         }
         for evt in mouse_wheel.read() {
-            match evt.unit {
-                MouseScrollUnit::Line => {
-                    if (transform.translation.y > options.zoom_min || evt.y < 0.0)
-                        && (transform.translation.y < options.zoom_max || evt.y > 0.0)
-                    {
-                        axis_input.y = -evt.y;
-                    }
-                }
-                MouseScrollUnit::Pixel => {}
-            }
+            // Trackpads report pixels, mouse wheels report lines
+            let lines: f32 = match evt.unit {
+                MouseScrollUnit::Line => evt.y,
+                MouseScrollUnit::Pixel => evt.y / PIXELS_PER_SCROLL_LINE,
+            };
+            // Every step zooms by the same fraction of the height, whatever the height
+            options.zoom_target = (options.zoom_target * (1.0 - options.zoom_step).powf(lines))
+                .clamp(options.zoom_min, options.zoom_max);
         }
         // Apply movement update
         if axis_input != Vec3::ZERO {
-            options.velocity = axis_input.normalize()
-                * Vec3 {
-                    x: options.pan_speed,
-                    y: options.zoom_speed,
-                    z: options.pan_speed,
-                };
+            options.follow = None;
+            // Panning covers the same share of the view at any height
+            let pan_speed: f32 = options.pan_speed
+                * (transform.translation.y / options.pan_reference_height).max(1.0);
+            options.velocity = axis_input.normalize() * pan_speed;
         } else {
             let friction = options.friction.clamp(0.0, 1.0);
             options.velocity *= 1.0 - friction;
@@ -215,10 +277,14 @@ pub fn camera_controller(
         }
         let right = transform.right();
 
-        transform.translation += options.velocity.x * dt * *right
-            + options.velocity.y * dt * Vec3::Y
-            + options.velocity.z * dt * Vec3::Z;
-        if key_input.pressed(options.rotate_key) {
+        transform.translation +=
+            options.velocity.x * dt * *right + options.velocity.z * dt * Vec3::Z;
+        let zoom_blend: f32 = 1.0 - (-options.zoom_smoothing * dt).exp();
+        transform.translation.y = transform
+            .translation
+            .y
+            .lerp(options.zoom_target, zoom_blend);
+        if rotating {
             for (camera, camera_transform) in cameras.iter() {
                 // First, compute a ray from the mouse position.
                 let (ray_pos, ray_dir) = ray_from_camera_center(camera, camera_transform);
@@ -241,9 +307,139 @@ pub fn camera_controller(
                     }
                 }
             }
+            // Orbiting changes the height, which zooming shouldn't undo
+            options.zoom_target = transform.translation.y;
         }
+        clamp_camera_to_bounds(&mut transform, movement_grid.settings.world_bounds());
     }
 }
+/// Bookmarks, follow mode and jumping to alerts
+fn camera_shortcuts(
+    actions: InputActions,
+    latest_alert: Res<LatestAlert>,
+    selected: Query<Entity, With<Selected>>,
+    mut query: Query<(&mut Transform, &mut CameraControllerSettings), With<Camera>>,
+) {
+    let Ok((mut transform, mut options)) = query.get_single_mut() else {
+        return;
+    };
+    for bookmark in 0..CAMERA_BOOKMARKS {
+        if !actions.just_pressed(InputAction::CameraBookmark(bookmark)) {
+            continue;
+        }
+        if actions.pressed(InputAction::SaveCameraBookmark) {
+            options.bookmarks[bookmark] = Some(*transform);
+        } else if let Some(bookmarked) = options.bookmarks[bookmark] {
+            *transform = bookmarked;
+            options.zoom_target = bookmarked.translation.y;
+            options.follow = None;
+        }
+    }
+    if actions.just_pressed(InputAction::FollowSelected) {
+        options.follow = match options.follow {
+            Some(_) => None,
+            None => selected.iter().next(),
+        };
+    }
+    if actions.just_pressed(InputAction::JumpToAlert) {
+        if let Some(position) = latest_alert.position {
+            options.follow = None;
+            centre_camera_on(&mut transform, position);
+        }
+    }
+}
+/// Keeps the followed unit in the centre of the view. Runs after the orbit rotation, which
+/// then turns around the unit.
+pub fn camera_follow(
+    mut query: Query<(&mut Transform, &mut CameraControllerSettings), With<Camera>>,
+    units: Query<&GlobalTransform>,
+) {
+    let Ok((mut transform, mut options)) = query.get_single_mut() else {
+        return;
+    };
+    let Some(unit) = options.follow else {
+        return;
+    };
+    match units.get(unit) {
+        Ok(unit_transform) => centre_camera_on(&mut transform, unit_transform.translation()),
+        Err(_) => options.follow = None,
+    }
+}
+fn record_alerts(
+    mut alerts: EventReader<Alert>,
+    mut latest_alert: ResMut<LatestAlert>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+) {
+    let Ok(local_player) = local_player.get_single() else {
+        return;
+    };
+    for alert in alerts.read() {
+        if alert.player == local_player {
+            latest_alert.position = Some(alert.position);
+        }
+    }
+}
+/// Pan direction from the cursor resting at the window's edges
+fn edge_scroll_input(window: &Window, margin: f32) -> Vec3 {
+    let mut axis_input: Vec3 = Vec3::ZERO;
+    if !window.focused || margin <= 0.0 {
+        return axis_input;
+    }
+    let Some(cursor) = window.cursor_position() else {
+        return axis_input;
+    };
+    if cursor.x < margin {
+        axis_input.x -= 1.0;
+    } else if cursor.x > window.width() - margin {
+        axis_input.x += 1.0;
+    }
+    if cursor.y < margin {
+        axis_input.z += 1.0;
+    } else if cursor.y > window.height() - margin {
+        axis_input.z -= 1.0;
+    }
+    axis_input
+}
+/// The point on the horizontal plane at `height` the camera looks at. A camera looking along
+/// the plane or away from it focuses on the point straight below or above it instead.
+pub fn camera_focus(camera_transform: &Transform, height: f32) -> Vec3 {
+    let forward: Vec3 = *camera_transform.forward();
+    let distance: f32 = (height - camera_transform.translation.y) / forward.y;
+    if forward.y.abs() < 1e-3 || distance < 0.0 {
+        return Vec3::new(
+            camera_transform.translation.x,
+            height,
+            camera_transform.translation.z,
+        );
+    }
+    camera_transform.translation + forward * distance
+}
+/// Keeps the point the camera looks at on the map
+fn clamp_camera_to_bounds(camera_transform: &mut Transform, bounds: Rect) {
+    let focus: Vec3 = camera_focus(camera_transform, 0.0);
+    let clamped: Vec2 = focus.xz().clamp(bounds.min, bounds.max);
+    camera_transform.translation.x += clamped.x - focus.x;
+    camera_transform.translation.z += clamped.y - focus.z;
+}
+
+/// Moves the camera sideways so that the point it looks at is the target, keeping its height
+/// and orientation
+pub fn centre_camera_on(camera_transform: &mut Transform, target: Vec3) {
+    let looked_at: Vec3 = camera_focus(camera_transform, target.y);
+    camera_transform.translation += target - looked_at;
+}
+
+fn centre_on_start_position(
+    mut camera: Query<&mut Transform, With<CameraControllerSettings>>,
+    start_positions: Query<&StartPosition, (With<LocalPlayer>, Added<StartPosition>)>,
+) {
+    let (Ok(mut transform), Ok(start_position)) =
+        (camera.get_single_mut(), start_positions.get_single())
+    else {
+        return;
+    };
+    centre_camera_on(&mut transform, start_position.0);
+}
 
 fn camera_setup(
     mut commands: Commands,
@@ -313,7 +509,7 @@ fn asset_loaded(
     }
 }
 
-fn mouse_controller(
+pub fn mouse_controller(
     mut selectable: Query<(Entity, &mut Selectable, &Children)>,
     mut selection_circle: Query<&mut Visibility, With<SelectionCircle>>,
     mut selected_entities: Query<(Entity, &Selected)>,
@@ -321,7 +517,7 @@ fn mouse_controller(
     mut commands: Commands,
     mut ray_hit_event: EventReader<RayHit>,
     deselect_event: EventReader<DeselectEvent>,
-    key_input: Res<ButtonInput<KeyCode>>,
+    actions: InputActions,
 ) {
     if !deselect_event.is_empty() {
         println!("Deselection");
@@ -344,7 +540,7 @@ fn mouse_controller(
                     }
                 }
             }
-            if !key_input.pressed(KeyCode::ControlLeft) {
+            if !actions.pressed(InputAction::AddToSelection) {
                 for (sel_entity, _, children) in selectable.iter() {
                     let mut deselect: bool = true;
 
@@ -378,7 +574,7 @@ fn mouse_controller(
     }
 }
 
-fn ray_from_mouse_position(
+pub fn ray_from_mouse_position(
     window: &Window,
     camera: &Camera,
     camera_transform: &GlobalTransform,
@@ -414,6 +610,7 @@ fn handle_select(
     mouse_unit_move_button: bool,
     mouse_key_enable_mouse: bool,
     deselect_event: &mut EventWriter<DeselectEvent>,
+    keep_selection: bool,
 ) {
     let (ray_pos, ray_dir) = ray_from_mouse_position(primary, camera, camera_transform);
     // println!("{:?}", mouse_unit_move_button);
@@ -435,7 +632,7 @@ fn handle_select(
             mouse_key_enable_mouse,
             ray_intersection,
         });
-    } else {
+    } else if !keep_selection {
         deselect_event.send(DeselectEvent);
     }
 }
@@ -470,8 +667,8 @@ fn handle_unit_move_cmd(
 fn process_mouse(
     mut ray_hit_event: EventWriter<RayHit>,
     mut deselect_event: EventWriter<DeselectEvent>,
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
-    camera_options: Query<(&CameraControllerSettings, &Camera, &GlobalTransform)>,
+    actions: InputActions,
+    camera_options: Query<(&Camera, &GlobalTransform), With<CameraControllerSettings>>,
     primary_query: Query<&Window, With<PrimaryWindow>>,
     rapier_context: Res<RapierContext>,
     rayblock: Query<Entity, With<RayBlock>>,
@@ -484,11 +681,9 @@ fn process_mouse(
     if !rayblock.is_empty() {
         mouse_over_ui = true;
     }
-    if let Ok((options, camera, camera_transform)) = camera_options.get_single() {
-        let mouse_key_enable_mouse =
-            mouse_button_input.just_pressed(options.mouse_key_enable_mouse);
-        let mouse_unit_move_button =
-            mouse_button_input.just_pressed(options.mouse_unit_move_button);
+    if let Ok((camera, camera_transform)) = camera_options.get_single() {
+        let mouse_key_enable_mouse = actions.just_pressed(InputAction::Select);
+        let mouse_unit_move_button = actions.just_pressed(InputAction::Command);
         if mouse_over_ui {
             return;
         }
@@ -502,6 +697,9 @@ fn process_mouse(
                 mouse_unit_move_button,
                 mouse_key_enable_mouse,
                 &mut deselect_event,
+                // Clicking into space starts a box selection that shouldn't lose the selection
+                actions.pressed(InputAction::AddToSelection)
+                    || actions.pressed(InputAction::SubtractFromSelection),
             )
         }
         if mouse_unit_move_button {
@@ -517,3 +715,78 @@ fn process_mouse(
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::window::WindowResolution;
+
+    fn window_with_cursor(cursor: Vec2) -> Window {
+        let mut window: Window = Window {
+            resolution: WindowResolution::new(800.0, 600.0),
+            focused: true,
+            ..default()
+        };
+        window.set_cursor_position(Some(cursor));
+        window
+    }
+
+    #[test]
+    fn camera_looking_down_focuses_where_its_view_meets_the_plane() {
+        let camera: Transform =
+            Transform::from_xyz(3.0, 10.0, 10.0).looking_at(Vec3::new(3.0, 0.0, 0.0), Vec3::Y);
+        assert!(camera_focus(&camera, 0.0).distance(Vec3::new(3.0, 0.0, 0.0)) < 1e-3);
+    }
+
+    #[test]
+    fn camera_looking_up_focuses_below_itself() {
+        let camera: Transform =
+            Transform::from_xyz(3.0, 10.0, 10.0).looking_at(Vec3::new(3.0, 20.0, 0.0), Vec3::Y);
+        assert_eq!(camera_focus(&camera, 0.0), Vec3::new(3.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn centring_keeps_height_and_orientation() {
+        let mut camera: Transform =
+            Transform::from_xyz(0.0, 10.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y);
+        let rotation: Quat = camera.rotation;
+        centre_camera_on(&mut camera, Vec3::new(50.0, 0.0, -20.0));
+        assert!(camera_focus(&camera, 0.0).distance(Vec3::new(50.0, 0.0, -20.0)) < 1e-3);
+        assert_eq!(camera.translation.y, 10.0);
+        assert_eq!(camera.rotation, rotation);
+    }
+
+    #[test]
+    fn camera_is_kept_looking_at_the_map() {
+        let mut camera: Transform =
+            Transform::from_xyz(0.0, 10.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y);
+        centre_camera_on(&mut camera, Vec3::new(500.0, 0.0, 0.0));
+        clamp_camera_to_bounds(
+            &mut camera,
+            Rect::from_corners(Vec2::splat(-100.0), Vec2::splat(100.0)),
+        );
+        assert!(camera_focus(&camera, 0.0).distance(Vec3::new(100.0, 0.0, 0.0)) < 1e-3);
+    }
+
+    #[test]
+    fn cursor_at_the_edges_pans() {
+        assert_eq!(
+            edge_scroll_input(&window_with_cursor(Vec2::new(2.0, 2.0)), 8.0),
+            Vec3::new(-1.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            edge_scroll_input(&window_with_cursor(Vec2::new(799.0, 599.0)), 8.0),
+            Vec3::new(1.0, 0.0, -1.0)
+        );
+        assert_eq!(
+            edge_scroll_input(&window_with_cursor(Vec2::new(400.0, 300.0)), 8.0),
+            Vec3::ZERO
+        );
+    }
+
+    #[test]
+    fn unfocused_window_does_not_pan() {
+        let mut window: Window = window_with_cursor(Vec2::new(2.0, 300.0));
+        window.focused = false;
+        assert_eq!(edge_scroll_input(&window, 8.0), Vec3::ZERO);
+    }
+}
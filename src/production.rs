@@ -0,0 +1,291 @@
+use crate::ownable::Owner;
+use crate::player_controller::{Alert, Civilisation, ContextMenuAction, PlayerInfo};
+use crate::research::{ResearchCompleted, TechTree};
+use crate::resources::{ResourceStockpiles, ResourceType};
+use crate::spawner::{InstanceSpawnRequest, UnitInformation, UnitSpecifications};
+use bevy::{prelude::*, utils::hashbrown::HashMap};
+use std::collections::VecDeque;
+
+pub struct Production;
+impl Plugin for Production {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ProductionRequest>()
+            .add_event::<ProductionStarted>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    enqueue_production,
+                    advance_production.after(enqueue_production),
+                ),
+            );
+    }
+}
+#[derive(Clone, Debug)]
+pub struct ProductionItem {
+    pub action: ContextMenuAction,
+    pub player: Entity,
+    pub remaining: f32,
+    pub total: f32,
+}
+/// Everything a producer has been ordered to build or research, worked off front to back
+#[derive(Component, Default)]
+pub struct ProductionQueue {
+    pub items: VecDeque<ProductionItem>,
+    pub produced: usize,
+}
+/// Ask a producer to perform an action for a player. Costs are paid when the item is queued.
+#[derive(Event)]
+pub struct ProductionRequest {
+    pub producer: Entity,
+    pub player: Entity,
+    pub action: ContextMenuAction,
+}
+/// A production request has been paid for and queued
+#[derive(Event, Clone, PartialEq, Debug)]
+pub struct ProductionStarted {
+    pub producer: Entity,
+    pub player: Entity,
+    pub action: ContextMenuAction,
+}
+
+impl ProductionQueue {
+    /// Advances the item at the front of the queue, returning it once it is finished
+    pub fn tick(&mut self, delta: f32) -> Option<ProductionItem> {
+        let item: &mut ProductionItem = self.items.front_mut()?;
+        item.remaining -= delta;
+        if item.remaining > 0.0 {
+            return None;
+        }
+        self.items.pop_front()
+    }
+}
+
+/// Cost and duration of an action, if it has to go through a production queue
+pub fn production_cost<'a>(
+    action: &ContextMenuAction,
+    civilisation: &Civilisation,
+    unit_specifications: &'a UnitSpecifications,
+    tech_tree: &'a TechTree,
+) -> Option<(&'a HashMap<ResourceType, f32>, f32)> {
+    match action {
+        ContextMenuAction::Build(unit_type) => unit_specifications
+            .unit_specifications
+            .get(&(civilisation.clone(), unit_type.clone()))
+            .map(|unit_specification| {
+                (&unit_specification.unit_cost, unit_specification.build_time)
+            }),
+        ContextMenuAction::Research(tech) => tech_tree
+            .techs
+            .get(tech)
+            .map(|tech| (&tech.cost, tech.research_time)),
+        ContextMenuAction::Ability(_) | ContextMenuAction::SelfDestruct => None,
+    }
+}
+
+pub fn spawn_location(producer: Vec3, index: usize) -> Vec3 {
+    Vec3 {
+        x: producer.x + 3.0 + (index % 4) as f32 * 2.0,
+        y: 2.0,
+        z: producer.z + 3.0 + (index / 4 % 4) as f32 * 2.0,
+    }
+}
+
+fn enqueue_production(
+    mut production_requests: EventReader<ProductionRequest>,
+    mut production_started: EventWriter<ProductionStarted>,
+    mut producers: Query<(&UnitInformation, &Owner, &mut ProductionQueue)>,
+    mut players: Query<(&mut PlayerInfo, &mut ResourceStockpiles)>,
+    unit_specifications: Res<UnitSpecifications>,
+    tech_tree: Res<TechTree>,
+) {
+    for request in production_requests.read() {
+        let Ok((unit_information, owner, mut queue)) = producers.get_mut(request.producer) else {
+            continue;
+        };
+        if owner.player != request.player {
+            continue;
+        }
+        let Ok((mut player_info, mut stockpiles)) = players.get_mut(request.player) else {
+            continue;
+        };
+        if let Some(reason) =
+            tech_tree.lock_reason(&request.action, &unit_information.unit_type, &player_info)
+        {
+            println!("Can't perform {:?}: {}", request.action, reason);
+            continue;
+        }
+        let Some((cost, duration)) = production_cost(
+            &request.action,
+            &player_info.civilisation,
+            &unit_specifications,
+            &tech_tree,
+        ) else {
+            continue;
+        };
+        if !stockpiles.try_pay(cost) {
+            println!("Not enough resources for {:?}", request.action);
+            continue;
+        }
+        if let ContextMenuAction::Research(tech) = &request.action {
+            player_info.researching.insert(tech.clone());
+        }
+        queue.items.push_back(ProductionItem {
+            action: request.action.clone(),
+            player: request.player,
+            remaining: duration,
+            total: duration,
+        });
+        production_started.send(ProductionStarted {
+            producer: request.producer,
+            player: request.player,
+            action: request.action.clone(),
+        });
+    }
+}
+
+pub fn advance_production(
+    time: Res<Time>,
+    mut producers: Query<(&Transform, &mut ProductionQueue)>,
+    player_infos: Query<&PlayerInfo>,
+    mut spawn_events: EventWriter<InstanceSpawnRequest>,
+    mut research_completed: EventWriter<ResearchCompleted>,
+    mut alerts: EventWriter<Alert>,
+) {
+    for (transform, mut queue) in producers.iter_mut() {
+        let Some(item) = queue.tick(time.delta_seconds()) else {
+            continue;
+        };
+        match item.action {
+            ContextMenuAction::Build(unit_type) => {
+                let Ok(player_info) = player_infos.get(item.player) else {
+                    continue;
+                };
+                let location: Vec3 = spawn_location(transform.translation, queue.produced);
+                alerts.send(Alert {
+                    player: item.player,
+                    position: location,
+                    message: format!("{} ready", unit_type),
+                });
+                spawn_events.send(InstanceSpawnRequest {
+                    location,
+                    unit_type,
+                    civilisation: player_info.civilisation.clone(),
+                    owner: item.player,
+                    entity: None,
+                });
+                queue.produced += 1;
+            }
+            ContextMenuAction::Research(tech) => {
+                alerts.send(Alert {
+                    player: item.player,
+                    position: transform.translation,
+                    message: format!("Research complete: {}", tech),
+                });
+                research_completed.send(ResearchCompleted {
+                    player: item.player,
+                    tech,
+                });
+            }
+            ContextMenuAction::Ability(_) | ContextMenuAction::SelfDestruct => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::utils::HashSet;
+    use bevy_rapier3d::rapier::prelude::ShapeType;
+
+    use super::*;
+    use crate::player_controller::TechLevel;
+    use crate::spawner::{UnitSpecification, UnitStats, UnitType};
+    use crate::utils::ShapeTypeSerializable;
+
+    fn civilisation() -> Civilisation {
+        Civilisation("Test".into())
+    }
+    fn player(world: &mut World) -> Entity {
+        world
+            .spawn((
+                PlayerInfo {
+                    civilisation: civilisation(),
+                    tech_level: TechLevel::L0,
+                    context_menu_actions: HashMap::from([(
+                        UnitType::Spacestation,
+                        vec![ContextMenuAction::Build(UnitType::Cruiser)],
+                    )]),
+                    researched: HashSet::new(),
+                    researching: HashSet::new(),
+                },
+                ResourceStockpiles(HashMap::from([(ResourceType::Plotanium, 100)])),
+            ))
+            .id()
+    }
+    fn plotanium(world: &World, player: Entity) -> i32 {
+        world.get::<ResourceStockpiles>(player).unwrap().0[&ResourceType::Plotanium]
+    }
+
+    #[test]
+    fn only_the_owner_can_use_a_producer() {
+        let mut world: World = World::new();
+        world.insert_resource(UnitSpecifications {
+            unit_specifications: [(
+                (civilisation(), UnitType::Cruiser),
+                UnitSpecification {
+                    file_path: String::new(),
+                    scene: String::new(),
+                    icon_path: String::new(),
+                    unit_name: String::new(),
+                    movable: true,
+                    shape: ShapeTypeSerializable(ShapeType::Ball),
+                    dimensions: Vec3::ONE,
+                    prescaling: 1.0,
+                    base_stats: UnitStats(Vec::new()),
+                    unit_info: String::new(),
+                    unit_cost: HashMap::from([(ResourceType::Plotanium, 30.0)]),
+                    build_time: 10.0,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        });
+        world.insert_resource(TechTree {
+            techs: Default::default(),
+        });
+        world.init_resource::<Events<ProductionRequest>>();
+        world.init_resource::<Events<ProductionStarted>>();
+        let owner: Entity = player(&mut world);
+        let intruder: Entity = player(&mut world);
+        let station: Entity = world
+            .spawn((
+                UnitInformation {
+                    unit_name: String::new(),
+                    unit_type: UnitType::Spacestation,
+                    civilisation: civilisation(),
+                    thumbnail: String::new(),
+                    stats: UnitStats(Vec::new()),
+                    unit_info: String::new(),
+                    unit_cost: HashMap::new(),
+                },
+                Owner { player: owner },
+                ProductionQueue::default(),
+            ))
+            .id();
+
+        for player in [intruder, owner] {
+            world.send_event(ProductionRequest {
+                producer: station,
+                player,
+                action: ContextMenuAction::Build(UnitType::Cruiser),
+            });
+        }
+        world.run_system_once(enqueue_production);
+
+        let queue: &ProductionQueue = world.get::<ProductionQueue>(station).unwrap();
+        assert_eq!(queue.items.len(), 1);
+        assert_eq!(queue.items[0].player, owner);
+        assert_eq!(plotanium(&world, intruder), 100);
+        assert_eq!(plotanium(&world, owner), 70);
+    }
+}
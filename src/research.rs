@@ -0,0 +1,312 @@
+use crate::ownable::Owner;
+use crate::player_controller::{ContextMenuAction, PlayerInfo, TechLevel};
+use crate::production::ProductionQueue;
+use crate::resources::ResourceType;
+use crate::spawner::{UnitInformation, UnitStat, UnitStats, UnitType};
+use bevy::{prelude::*, utils::hashbrown::HashMap};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fs};
+
+pub const TECH_TREE_PATH: &str = "./assets/techs/techs.ron";
+
+pub struct Research;
+impl Plugin for Research {
+    fn build(&self, app: &mut App) {
+        // Loaded right away, so that startup systems can tell which actions are still locked
+        let tech_tree: TechTree = match TechTree::load(TECH_TREE_PATH) {
+            Ok(tech_tree) => tech_tree,
+            Err(e) => {
+                println!("Could not load tech tree {}: {}", TECH_TREE_PATH, e);
+                TechTree {
+                    techs: HashMap::new(),
+                }
+            }
+        };
+        app.add_event::<ResearchCompleted>()
+            .insert_resource(tech_tree)
+            .add_systems(
+                FixedUpdate,
+                (
+                    apply_research,
+                    release_abandoned_research.after(apply_research),
+                ),
+            );
+    }
+}
+/// Added on top of the matching stat of a unit
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum StatModifier {
+    MaxMiningDist(f32),
+    BaseMiningRate(f32),
+    BonusMiningRate((ResourceType, f32)),
+    VisionRange(f32),
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TechEffect {
+    AdvanceTechLevel(TechLevel),
+    Unlock {
+        unit_type: UnitType,
+        action: ContextMenuAction,
+    },
+    ModifyStat {
+        unit_type: UnitType,
+        modifier: StatModifier,
+    },
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TechDefinition {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub icon_path: String,
+    pub cost: HashMap<ResourceType, f32>,
+    pub research_time: f32,
+    pub prerequisites: Vec<String>,
+    pub required_tech_level: TechLevel,
+    pub effects: Vec<TechEffect>,
+}
+#[derive(Resource)]
+pub struct TechTree {
+    pub techs: HashMap<String, TechDefinition>,
+}
+#[derive(Event)]
+pub struct ResearchCompleted {
+    pub player: Entity,
+    pub tech: String,
+}
+
+impl StatModifier {
+    pub fn apply(&self, stats: &mut UnitStats) {
+        for stat in stats.iter_mut() {
+            match (stat, self) {
+                (UnitStat::MaxMiningDist(v), StatModifier::MaxMiningDist(m))
+                | (UnitStat::BaseMiningRate(v), StatModifier::BaseMiningRate(m))
+                | (UnitStat::VisionRange(v), StatModifier::VisionRange(m)) => {
+                    *v += m;
+                    return;
+                }
+                (UnitStat::BonusMiningRate((t, v)), StatModifier::BonusMiningRate((mt, m)))
+                    if t == mt =>
+                {
+                    *v += m;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        stats.push(match *self {
+            StatModifier::MaxMiningDist(m) => UnitStat::MaxMiningDist(m),
+            StatModifier::BaseMiningRate(m) => UnitStat::BaseMiningRate(m),
+            StatModifier::BonusMiningRate(m) => UnitStat::BonusMiningRate(m),
+            StatModifier::VisionRange(m) => UnitStat::VisionRange(m),
+        });
+    }
+}
+
+impl TechTree {
+    pub fn load(path: &str) -> Result<TechTree, Box<dyn Error>> {
+        let techs: Vec<TechDefinition> = ron::from_str(&fs::read_to_string(path)?)?;
+        Ok(TechTree {
+            techs: techs
+                .into_iter()
+                .map(|tech| (tech.id.clone(), tech))
+                .collect(),
+        })
+    }
+    pub fn name<'a>(&'a self, tech: &'a str) -> &'a str {
+        self.techs.get(tech).map_or(tech, |tech| &tech.name)
+    }
+    /// All modifiers the player's research applies to the unit type
+    pub fn stat_modifiers<'a>(
+        &'a self,
+        player_info: &'a PlayerInfo,
+        unit_type: &'a UnitType,
+    ) -> impl Iterator<Item = &'a StatModifier> + 'a {
+        player_info
+            .researched
+            .iter()
+            .filter_map(|tech| self.techs.get(tech))
+            .flat_map(|tech| tech.effects.iter())
+            .filter_map(move |effect| match effect {
+                TechEffect::ModifyStat {
+                    unit_type: modified,
+                    modifier,
+                } if modified == unit_type => Some(modifier),
+                _ => None,
+            })
+    }
+    /// Names of the techs that unlock the action for the unit type
+    pub fn unlocked_by(&self, action: &ContextMenuAction, producer: &UnitType) -> Vec<&str> {
+        self.techs
+            .values()
+            .filter(|tech| {
+                tech.effects.iter().any(|effect| {
+                    matches!(effect, TechEffect::Unlock { unit_type, action: unlocked }
+                        if unit_type == producer && unlocked == action)
+                })
+            })
+            .map(|tech| tech.name.as_str())
+            .collect()
+    }
+    /// Actions a producer of the given type could get through research but doesn't have yet
+    pub fn locked_actions(
+        &self,
+        player_info: &PlayerInfo,
+        producer: &UnitType,
+    ) -> Vec<ContextMenuAction> {
+        let mut locked: Vec<ContextMenuAction> = Vec::new();
+        for tech in self.techs.values() {
+            for effect in &tech.effects {
+                if let TechEffect::Unlock { unit_type, action } = effect {
+                    if unit_type == producer
+                        && !player_info
+                            .context_menu_actions
+                            .get(producer)
+                            .is_some_and(|actions| actions.contains(action))
+                        && !locked.contains(action)
+                    {
+                        locked.push(action.clone());
+                    }
+                }
+            }
+        }
+        locked
+    }
+    /// Why the producer can't perform the action right now, if it can't
+    pub fn lock_reason(
+        &self,
+        action: &ContextMenuAction,
+        producer: &UnitType,
+        player_info: &PlayerInfo,
+    ) -> Option<String> {
+        if !player_info
+            .context_menu_actions
+            .get(producer)
+            .is_some_and(|actions| actions.contains(action))
+        {
+            let unlocked_by: Vec<&str> = self.unlocked_by(action, producer);
+            if unlocked_by.is_empty() {
+                return Some("Not available".into());
+            }
+            return Some(format!("Requires {}", unlocked_by.join(" or ")));
+        }
+        if let ContextMenuAction::Research(tech_id) = action {
+            let Some(tech) = self.techs.get(tech_id) else {
+                return Some(format!("Unknown technology {}", tech_id));
+            };
+            if player_info.researched.contains(tech_id) {
+                return Some("Already researched".into());
+            }
+            if player_info.researching.contains(tech_id) {
+                return Some("Research in progress".into());
+            }
+            if tech.required_tech_level > player_info.tech_level {
+                return Some(format!("Requires tech level {}", tech.required_tech_level));
+            }
+            let missing: Vec<&str> = tech
+                .prerequisites
+                .iter()
+                .filter(|prerequisite| !player_info.researched.contains(*prerequisite))
+                .map(|prerequisite| self.name(prerequisite))
+                .collect();
+            if !missing.is_empty() {
+                return Some(format!("Requires {}", missing.join(", ")));
+            }
+        }
+        None
+    }
+    /// Applies the research to the player. Stat changes of existing units are left to the caller.
+    pub fn complete_research(&self, tech_id: &str, player_info: &mut PlayerInfo) {
+        player_info.researching.remove(tech_id);
+        player_info.researched.insert(tech_id.to_owned());
+        let Some(tech) = self.techs.get(tech_id) else {
+            return;
+        };
+        for effect in &tech.effects {
+            match effect {
+                TechEffect::AdvanceTechLevel(tech_level) => {
+                    player_info.tech_level = player_info.tech_level.max(*tech_level);
+                }
+                TechEffect::Unlock { unit_type, action } => {
+                    let actions = player_info
+                        .context_menu_actions
+                        .entry(unit_type.clone())
+                        .or_default();
+                    if !actions.contains(action) {
+                        actions.push(action.clone());
+                    }
+                }
+                TechEffect::ModifyStat { .. } => {}
+            }
+        }
+    }
+}
+
+fn apply_research(
+    mut research_completed: EventReader<ResearchCompleted>,
+    tech_tree: Res<TechTree>,
+    mut player_infos: Query<&mut PlayerInfo>,
+    mut units: Query<(&mut UnitInformation, &Owner)>,
+) {
+    for completed in research_completed.read() {
+        let Ok(mut player_info) = player_infos.get_mut(completed.player) else {
+            continue;
+        };
+        tech_tree.complete_research(&completed.tech, &mut player_info);
+        println!("Researched {}", tech_tree.name(&completed.tech));
+        let Some(tech) = tech_tree.techs.get(&completed.tech) else {
+            continue;
+        };
+        for (mut unit_information, owner) in units.iter_mut() {
+            if owner.player != completed.player {
+                continue;
+            }
+            for effect in &tech.effects {
+                if let TechEffect::ModifyStat {
+                    unit_type,
+                    modifier,
+                } = effect
+                {
+                    if *unit_type == unit_information.unit_type {
+                        modifier.apply(&mut unit_information.stats);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Research whose queue entry is gone without being completed, because its producer was
+/// destroyed or the entry was cancelled, can be started again
+fn release_abandoned_research(
+    mut research_completed: EventReader<ResearchCompleted>,
+    queues: Query<&ProductionQueue>,
+    mut player_infos: Query<(Entity, &mut PlayerInfo)>,
+) {
+    // Completed this tick, but possibly not applied until the next one
+    let completed: Vec<(Entity, String)> = research_completed
+        .read()
+        .map(|completed| (completed.player, completed.tech.clone()))
+        .collect();
+    for (player, mut player_info) in player_infos.iter_mut() {
+        let abandoned: Vec<String> = player_info
+            .researching
+            .iter()
+            .filter(|tech| {
+                let action: ContextMenuAction = ContextMenuAction::Research((*tech).clone());
+                !completed.contains(&(player, (*tech).clone()))
+                    && !queues.iter().any(|queue| {
+                        queue
+                            .items
+                            .iter()
+                            .any(|item| item.player == player && item.action == action)
+                    })
+            })
+            .cloned()
+            .collect();
+        for tech in abandoned {
+            println!("Research of {} was abandoned", tech);
+            player_info.researching.remove(&tech);
+        }
+    }
+}
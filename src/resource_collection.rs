@@ -1,71 +1,101 @@
 use crate::{
-    civilisation::CivilisationBoniMap,
-    ownable::Selected,
+    civilisation::{CivilisationBoni, CivilisationBoniMap},
+    ownable::{Owner, Selected},
     player_controller::{LocalPlayer, PlayerInfo, RayHit},
-    resources::{ResourceLevel, ResourceStockpiles, ResourceType},
-    spawner::{EntityWrapper, UnitInformation, UnitStat, UnitType},
+    resources::{IncomeModifier, ResourceLevel, ResourceStockpiles, ResourceType},
+    spawner::{EntityWrapper, UnitInformation, UnitStat, UnitStats, UnitType},
 };
 
 use bevy::{prelude::*, time::Stopwatch};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const COLLECTION_INTERVAL: Duration = Duration::from_secs(1);
 
 //#[derive(Resource)]
 //struct CollectionTick {
 //    time: Stopwatch,
 //}
-#[derive(Eq, PartialEq, Copy, Clone)]
-enum CollectorState {
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum CollectorState {
     Collecting,
     Approaching,
     Cancelled,
 }
 #[derive(Component)]
-struct Collector {
-    resource: ResourceType,
-    resource_entity: EntityWrapper,
-    player: EntityWrapper,
-    collecting: CollectorState,
+pub struct Collector {
+    pub resource: ResourceType,
+    pub resource_entity: EntityWrapper,
+    pub player: EntityWrapper,
+    pub collecting: CollectorState,
+}
+/// Order a collector to mine the given resource source on behalf of a player
+#[derive(Event)]
+pub struct CollectionCommand {
+    pub collector: Entity,
+    pub resource_entity: Entity,
+    pub player: Entity,
 }
 
 pub struct ResourceCollection;
 impl Plugin for ResourceCollection {
     fn build(&self, app: &mut App) {
         app.add_event::<RayHit>()
-            .add_systems(Update, (process_collection_command, collect));
+            .add_event::<CollectionCommand>()
+            .add_systems(Update, issue_collection_command)
+            .add_systems(FixedUpdate, (process_collection_command, collect));
     }
 }
 
-fn process_collection_command(
-    mut commands: Commands,
-    selected_entities: Query<(Entity, &UnitInformation), With<Selected>>,
+fn issue_collection_command(
+    selected_entities: Query<(Entity, &UnitInformation, &Owner), With<Selected>>,
     mut ray_hit_event: EventReader<RayHit>,
     resource_sources: Query<&ResourceLevel>,
     main_player: Query<Entity, With<LocalPlayer>>,
+    mut collection_commands: EventWriter<CollectionCommand>,
 ) {
     let main_player_entity: Entity = main_player.get_single().unwrap();
     for hit in ray_hit_event.read() {
-        if let Ok(resource_level) = resource_sources.get(hit.hit_entity) {
-            for (entity, unit_information) in selected_entities.iter() {
-                match unit_information.unit_type {
-                    UnitType::MiningStation => {
-                        commands.entity(entity).insert(Collector {
-                            resource: resource_level.resource_type, //TODO make adaptive
-                            resource_entity: EntityWrapper {
-                                entity: hit.hit_entity,
-                            },
-
-                            player: EntityWrapper {
-                                entity: main_player_entity,
-                            },
-                            collecting: CollectorState::Approaching,
-                        });
-                    }
-                    _ => {}
+        if resource_sources.get(hit.hit_entity).is_ok() {
+            for (entity, unit_information, owner) in selected_entities.iter() {
+                if unit_information.unit_type == UnitType::MiningStation
+                    && owner.player == main_player_entity
+                {
+                    collection_commands.send(CollectionCommand {
+                        collector: entity,
+                        resource_entity: hit.hit_entity,
+                        player: main_player_entity,
+                    });
                 }
             }
         }
     }
 }
 
+fn process_collection_command(
+    mut commands: Commands,
+    mut collection_commands: EventReader<CollectionCommand>,
+    resource_sources: Query<&ResourceLevel>,
+) {
+    for collection_command in collection_commands.read() {
+        if let Ok(resource_level) = resource_sources.get(collection_command.resource_entity) {
+            commands
+                .entity(collection_command.collector)
+                .insert(Collector {
+                    resource: resource_level.resource_type, //TODO make adaptive
+                    resource_entity: EntityWrapper {
+                        entity: collection_command.resource_entity,
+                    },
+
+                    player: EntityWrapper {
+                        entity: collection_command.player,
+                    },
+                    collecting: CollectorState::Approaching,
+                });
+        }
+    }
+}
+
 fn check_collection_state(
     collector: &Collector,
     collector_transform: &Transform,
@@ -90,9 +120,38 @@ fn check_collection_state(
     } else if collector.collecting == CollectorState::Approaching && max_mining_dist > dist {
         return CollectorState::Collecting;
     }
-    return collector.collecting;
+    collector.collecting
 }
 
+/// Resources per second a unit with the given stats collects, including all boni.
+/// Units without any mining capability collect nothing, regardless of boni.
+pub fn collection_rate(
+    stats: &UnitStats,
+    resource: ResourceType,
+    civilisation_boni: &CivilisationBoni,
+    income_modifier: f32,
+) -> f32 {
+    let mut rate = 0.0;
+    for stat in &stats.0 {
+        match stat {
+            UnitStat::BaseMiningRate(bmr) => rate += *bmr,
+            UnitStat::BonusMiningRate((t, r)) if *t == resource => rate += r,
+            _ => {}
+        }
+    }
+    if rate <= 0.0 {
+        return 0.0;
+    }
+    for (t, r) in &civilisation_boni.eco_boni.resource_boni {
+        if *t == resource {
+            rate += r;
+        }
+    }
+    rate * income_modifier
+}
+
+/// Resource sources are where the simulation put them, which for orbiting ones is worked out in
+/// the same ticks from the match time, never from anything drawn
 fn collect(
     time: Res<Time>,
     mut collectors: Query<(Entity, &mut Collector, &Transform, &UnitInformation)>,
@@ -101,12 +160,14 @@ fn collect(
     mut stopwatch: Local<Stopwatch>,
     // mut resource_update_events: EventWriter<UIResourceUpdateEvent>,
     mut commands: Commands,
-    player_infos: Query<&PlayerInfo>,
+    player_infos: Query<(&PlayerInfo, Option<&IncomeModifier>)>,
     civilisation_boni_map: Res<CivilisationBoniMap>,
 ) {
     stopwatch.tick(time.delta());
-    if stopwatch.elapsed().as_secs() >= 1 {
-        stopwatch.reset();
+    if stopwatch.elapsed() >= COLLECTION_INTERVAL {
+        // Ticks rarely add up to the interval exactly, what went past it counts for the next one
+        let overshoot: Duration = stopwatch.elapsed() - COLLECTION_INTERVAL;
+        stopwatch.set_elapsed(overshoot);
         for (collector_entity, mut collector, collector_transform, unit_information) in
             collectors.iter_mut()
         {
@@ -117,31 +178,20 @@ fn collect(
                 unit_information,
             );
             // Calculate collection rate
-            let mut rate = 0.0;
-            for stat in &unit_information.stats.0 {
-                match stat {
-                    UnitStat::BaseMiningRate(bmr) => rate += *bmr,
-                    UnitStat::BonusMiningRate((t, r)) => {
-                        if *t == collector.resource {
-                            rate += r
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            if rate <= 0.0 {
-                collector.collecting = CollectorState::Cancelled;
-                println!("Collector apparantly incapable of mining resources");
-            }
-            let player_info = player_infos.get(collector.player.entity).unwrap();
+            let (player_info, income_modifier) = player_infos.get(collector.player.entity).unwrap();
             let civilisation_boni = civilisation_boni_map
                 .map
                 .get(&player_info.civilisation)
                 .unwrap();
-            for (t, r) in &civilisation_boni.eco_boni.resource_boni {
-                if *t == collector.resource {
-                    rate += r;
-                }
+            let rate: f32 = collection_rate(
+                &unit_information.stats,
+                collector.resource,
+                civilisation_boni,
+                income_modifier.map_or(1.0, |modifier| modifier.0),
+            );
+            if rate <= 0.0 {
+                collector.collecting = CollectorState::Cancelled;
+                println!("Collector apparantly incapable of mining resources");
             }
             // End
             if collector.collecting == CollectorState::Collecting {
@@ -16,7 +16,42 @@ impl ResourceStockpiles {
     pub fn get(&self, resource_type: &ResourceType) -> Option<&i32> {
         self.0.get(resource_type)
     }
+    pub fn can_afford<'a, I>(&self, cost: I) -> bool
+    where
+        I: IntoIterator<Item = (&'a ResourceType, &'a f32)>,
+    {
+        cost.into_iter().all(|(resource_type, amount)| {
+            *self.get(resource_type).unwrap_or(&0) >= amount.ceil() as i32
+        })
+    }
+    /// Deducts the cost if all resources are available. Returns whether it was paid.
+    pub fn try_pay<'a, I>(&mut self, cost: I) -> bool
+    where
+        I: IntoIterator<Item = (&'a ResourceType, &'a f32)> + Clone,
+    {
+        if !self.can_afford(cost.clone()) {
+            return false;
+        }
+        for (resource_type, amount) in cost {
+            if let Some(stockpile) = self.0.get_mut(resource_type) {
+                *stockpile -= amount.ceil() as i32;
+            }
+        }
+        true
+    }
 }
+/// Multiplier applied to everything a player collects
+#[derive(Component)]
+pub struct IncomeModifier(pub f32);
 
+/// Which system's scenario lists the resource source and where, the same every time the
+/// scenario is loaded
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
+pub struct ResourceSourceId {
+    pub system: usize,
+    pub index: usize,
+}
 #[derive(Component)]
-pub struct ResourceSource;
+pub struct ResourceSource {
+    pub id: ResourceSourceId,
+}
@@ -0,0 +1,850 @@
+use std::{error::Error, fs, path::Path};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::a_star::AStarParams;
+use crate::ai::{AiPlayer, AttackOrder, Difficulty};
+use crate::ai_script::AiScript;
+use crate::control_groups::ControlGroupMembership;
+use crate::galaxy::JumpRoute;
+use crate::key_bindings::{InputAction, InputActions};
+use crate::movable::{MoveCommand, MovementPath, PathNode};
+use crate::ownable::{Owner, PlayerColour};
+use crate::player_controller::{
+    Civilisation, ContextMenuAction, LocalPlayer, PlayerInfo, PlayerNumber, TechLevel,
+};
+use crate::production::{ProductionItem, ProductionQueue};
+use crate::resource_collection::{Collector, CollectorState};
+use crate::resources::{
+    ResourceLevel, ResourceSource, ResourceSourceId, ResourceStockpiles, ResourceType,
+};
+use crate::simulation::MatchTime;
+use crate::spawner::{
+    spawn, EntityWrapper, Health, InstanceSpawnRequest, Shield, UnitInformation, UnitType,
+};
+use crate::visibility::VisibilityGrid;
+
+/// Bumped whenever the save format changes in a way older saves can't be read with
+pub const SAVE_VERSION: u32 = 2;
+pub const QUICKSAVE_PATH: &str = "./saves/quicksave.ron";
+
+pub struct SaveLoad;
+impl Plugin for SaveLoad {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveRequest>()
+            .add_event::<LoadRequest>()
+            .add_systems(
+                Update,
+                (
+                    save_hotkeys,
+                    save_game.after(save_hotkeys),
+                    load_game.after(save_hotkeys),
+                ),
+            )
+            // The units are rebuilt by the spawner in the next tick
+            .add_systems(FixedUpdate, restore_units.after(spawn));
+    }
+}
+#[derive(Event)]
+pub struct SaveRequest {
+    pub path: String,
+}
+#[derive(Event)]
+pub struct LoadRequest {
+    pub path: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub local: bool,
+    pub ai: Option<SavedAi>,
+    pub colour: Option<[f32; 4]>,
+    pub civilisation: Civilisation,
+    pub tech_level: TechLevel,
+    pub context_menu_actions: HashMap<UnitType, Vec<ContextMenuAction>>,
+    pub researched: HashSet<String>,
+    pub researching: HashSet<String>,
+    pub stockpiles: HashMap<ResourceType, i32>,
+}
+/// What a computer player has worked out so far. Units are referred to by their index in the
+/// save.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedAi {
+    pub difficulty: Difficulty,
+    pub script: AiScript,
+    pub step: usize,
+    pub performed: usize,
+    pub reaction_elapsed: f32,
+    pub scout: Option<usize>,
+    pub scout_waypoint: usize,
+    pub seen_stations: Vec<SavedSighting>,
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedSighting {
+    pub unit: usize,
+    pub position: Vec3,
+}
+/// Resource sources are part of the map, so only their remaining amount is saved
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedResource {
+    pub id: ResourceSourceId,
+    pub resource_type: ResourceType,
+    pub resource_amount: i32,
+}
+/// Players are referred to by their index in the save
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedCollector {
+    pub resource: ResourceType,
+    pub resource_source: ResourceSourceId,
+    pub player: usize,
+    pub collecting: CollectorState,
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedProductionItem {
+    pub action: ContextMenuAction,
+    pub player: usize,
+    pub remaining: f32,
+    pub total: f32,
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedAttackOrder {
+    pub target: usize,
+    pub destination: Vec2,
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedUnit {
+    pub unit_type: UnitType,
+    pub civilisation: Civilisation,
+    pub owner: usize,
+    pub transform: Transform,
+    pub health: Health,
+    pub shield: Option<Shield>,
+    pub collector: Option<SavedCollector>,
+    pub move_command: Option<Vec2>,
+    pub movement_path: Option<Vec<PathNode>>,
+    pub jump_route: Option<JumpRoute>,
+    pub attack_order: Option<SavedAttackOrder>,
+    pub production_queue: Vec<SavedProductionItem>,
+    pub produced: usize,
+    pub control_groups: ControlGroupMembership,
+}
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub players: Vec<SavedPlayer>,
+    pub resources: Vec<SavedResource>,
+    pub units: Vec<SavedUnit>,
+    /// Fog of war of the local player, see `VisibilityGrid::explored`
+    pub explored: Vec<String>,
+    /// Seconds into the match, which orbits are worked out from
+    pub match_time: f64,
+}
+
+impl SaveGame {
+    pub fn from_ron(text: &str) -> Result<SaveGame, Box<dyn Error>> {
+        let save: SaveGame = ron::from_str(text)?;
+        if save.version != SAVE_VERSION {
+            return Err(format!(
+                "save version {} is not supported, expected {}",
+                save.version, SAVE_VERSION
+            )
+            .into());
+        }
+        Ok(save)
+    }
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, PrettyConfig::default())
+    }
+    pub fn load(path: &str) -> Result<SaveGame, Box<dyn Error>> {
+        SaveGame::from_ron(&fs::read_to_string(path)?)
+    }
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(directory) = Path::new(path).parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+}
+
+type PlayerData = (
+    Entity,
+    &'static PlayerInfo,
+    &'static ResourceStockpiles,
+    Has<LocalPlayer>,
+    Option<&'static AiPlayer>,
+    Option<&'static PlayerColour>,
+);
+type UnitData = (
+    Entity,
+    &'static UnitInformation,
+    &'static Owner,
+    &'static Transform,
+    &'static Health,
+    Option<&'static Shield>,
+    Option<&'static Collector>,
+    Option<&'static MoveCommand>,
+    Option<&'static MovementPath>,
+    Option<&'static AStarParams>,
+    Option<&'static JumpRoute>,
+    Option<&'static AttackOrder>,
+    Option<&'static ProductionQueue>,
+    Option<&'static ControlGroupMembership>,
+);
+/// Everything that ends up in a save
+#[derive(SystemParam)]
+pub struct SaveableWorld<'w, 's> {
+    players: Query<'w, 's, PlayerData>,
+    units: Query<'w, 's, UnitData>,
+    resources: Query<'w, 's, (Entity, &'static ResourceSource, &'static ResourceLevel)>,
+    visibility: Option<Res<'w, VisibilityGrid>>,
+    match_time: Option<Res<'w, MatchTime>>,
+}
+impl SaveableWorld<'_, '_> {
+    pub fn capture(&self) -> SaveGame {
+        // Sorted by entity, so that a freshly loaded world saves in the same order
+        let mut players: Vec<_> = self.players.iter().collect();
+        players.sort_by_key(|player| player.0);
+        let mut resources: Vec<_> = self.resources.iter().collect();
+        resources.sort_by_key(|(_, source, _)| (source.id.system, source.id.index));
+        let mut units: Vec<_> = self.units.iter().collect();
+        units.sort_by_key(|unit| unit.0);
+        let player_index: HashMap<Entity, usize> = players
+            .iter()
+            .enumerate()
+            .map(|(index, player)| (player.0, index))
+            .collect();
+        // Units of players that aren't saved are left out, and the rest are numbered
+        units.retain(|unit| player_index.contains_key(&unit.2.player));
+        let unit_index: HashMap<Entity, usize> = units
+            .iter()
+            .enumerate()
+            .map(|(index, unit)| (unit.0, index))
+            .collect();
+        let resource_ids: HashMap<Entity, ResourceSourceId> = resources
+            .iter()
+            .map(|(entity, source, _)| (*entity, source.id))
+            .collect();
+        SaveGame {
+            version: SAVE_VERSION,
+            players: players
+                .iter()
+                .map(
+                    |(_, player_info, stockpiles, local, ai_player, colour)| SavedPlayer {
+                        local: *local,
+                        ai: ai_player.map(|ai_player| {
+                            ai_player.to_saved(|unit| unit_index.get(&unit).copied())
+                        }),
+                        colour: colour.map(|colour| colour.0.to_srgba().to_f32_array()),
+                        civilisation: player_info.civilisation.clone(),
+                        tech_level: player_info.tech_level,
+                        context_menu_actions: player_info.context_menu_actions.clone(),
+                        researched: player_info.researched.clone(),
+                        researching: player_info.researching.clone(),
+                        stockpiles: stockpiles.0.clone(),
+                    },
+                )
+                .collect(),
+            resources: resources
+                .iter()
+                .map(|(_, source, resource_level)| SavedResource {
+                    id: source.id,
+                    resource_type: resource_level.resource_type,
+                    resource_amount: resource_level.resource_amount,
+                })
+                .collect(),
+            units: units
+                .iter()
+                .filter_map(
+                    |(
+                        _,
+                        unit_information,
+                        owner,
+                        transform,
+                        health,
+                        shield,
+                        collector,
+                        move_command,
+                        movement_path,
+                        a_star_params,
+                        jump_route,
+                        attack_order,
+                        production_queue,
+                        control_groups,
+                    )| {
+                        // A path still being searched for is searched for again after loading,
+                        // including the route through the jump gates
+                        let pending_target: Option<Vec2> = a_star_params.map(|a_star_params| {
+                            jump_route.map_or(a_star_params.destination(), |route| route.target)
+                        });
+                        Some(SavedUnit {
+                            unit_type: unit_information.unit_type.clone(),
+                            civilisation: unit_information.civilisation.clone(),
+                            owner: *player_index.get(&owner.player)?,
+                            transform: **transform,
+                            health: **health,
+                            shield: shield.copied(),
+                            // Collection from something that is no longer a resource source is dropped
+                            collector: collector.and_then(|collector| {
+                                Some(SavedCollector {
+                                    resource: collector.resource,
+                                    resource_source: *resource_ids
+                                        .get(&collector.resource_entity.entity)?,
+                                    player: *player_index.get(&collector.player.entity)?,
+                                    collecting: collector.collecting,
+                                })
+                            }),
+                            move_command: move_command
+                                .map(|move_command| move_command.target)
+                                .or(pending_target),
+                            movement_path: movement_path
+                                .map(|movement_path| movement_path.path.clone()),
+                            jump_route: jump_route.filter(|_| pending_target.is_none()).copied(),
+                            attack_order: attack_order.and_then(|attack_order| {
+                                Some(SavedAttackOrder {
+                                    target: *unit_index.get(&attack_order.target)?,
+                                    destination: attack_order.destination,
+                                })
+                            }),
+                            production_queue: production_queue
+                                .iter()
+                                .flat_map(|queue| queue.items.iter())
+                                .filter_map(|item| {
+                                    Some(SavedProductionItem {
+                                        action: item.action.clone(),
+                                        player: *player_index.get(&item.player)?,
+                                        remaining: item.remaining,
+                                        total: item.total,
+                                    })
+                                })
+                                .collect(),
+                            produced: production_queue.map_or(0, |queue| queue.produced),
+                            control_groups: control_groups.copied().unwrap_or_default(),
+                        })
+                    },
+                )
+                .collect(),
+            explored: self
+                .visibility
+                .as_ref()
+                .map(|visibility| visibility.explored())
+                .unwrap_or_default(),
+            match_time: self
+                .match_time
+                .as_ref()
+                .map_or(0.0, |match_time| match_time.elapsed),
+        }
+    }
+}
+
+/// State of a loaded unit, applied once the spawner has built the unit itself
+#[derive(Component)]
+struct RestoredUnit {
+    transform: Transform,
+    health: Health,
+    shield: Option<Shield>,
+    collector: Option<Collector>,
+    move_command: Option<MoveCommand>,
+    movement_path: Option<MovementPath>,
+    jump_route: Option<JumpRoute>,
+    attack_order: Option<AttackOrder>,
+    production_queue: ProductionQueue,
+    control_groups: ControlGroupMembership,
+}
+
+/// Replaces all players and units with those of the save and rebuilds the units through the
+/// spawner. Resource sources belong to the map and only get their amounts restored.
+pub fn restore(
+    save: &SaveGame,
+    commands: &mut Commands,
+    resources: &mut Query<(Entity, &ResourceSource, &mut ResourceLevel)>,
+    visibility: Option<&mut VisibilityGrid>,
+    match_time: Option<&mut MatchTime>,
+    spawn_events: &mut EventWriter<InstanceSpawnRequest>,
+) {
+    let players: Vec<Entity> = save
+        .players
+        .iter()
+        .enumerate()
+        .map(|(index, saved)| {
+            let mut player = commands.spawn((
+                PlayerInfo {
+                    civilisation: saved.civilisation.clone(),
+                    tech_level: saved.tech_level,
+                    context_menu_actions: saved.context_menu_actions.clone(),
+                    researched: saved.researched.clone(),
+                    researching: saved.researching.clone(),
+                },
+                ResourceStockpiles(saved.stockpiles.clone()),
+                PlayerNumber(index + 1),
+            ));
+            if saved.local {
+                player.insert(LocalPlayer);
+            }
+            if let Some(colour) = saved.colour {
+                player.insert(PlayerColour(Srgba::from_f32_array(colour).into()));
+            }
+            player.id()
+        })
+        .collect();
+    let resource_sources: HashMap<ResourceSourceId, Entity> = resources
+        .iter()
+        .map(|(entity, source, _)| (source.id, entity))
+        .collect();
+    for saved in &save.resources {
+        match resource_sources
+            .get(&saved.id)
+            .and_then(|entity| resources.get_mut(*entity).ok())
+        {
+            Some((_, _, mut resource_level)) => {
+                resource_level.resource_type = saved.resource_type;
+                resource_level.resource_amount = saved.resource_amount;
+            }
+            None => println!("No resource source {:?} to restore", saved.id),
+        }
+    }
+    if let Some(visibility) = visibility {
+        visibility.restore_explored(&save.explored);
+    }
+    if let Some(match_time) = match_time {
+        match_time.elapsed = save.match_time;
+    }
+    // Reserved up front, as units and computer players refer to each other
+    let units: Vec<Option<Entity>> = save
+        .units
+        .iter()
+        .map(|saved| {
+            players
+                .get(saved.owner)
+                .map(|_| commands.spawn_empty().id())
+        })
+        .collect();
+    let unit = |index: usize| units.get(index).copied().flatten();
+    for (saved, player) in save.players.iter().zip(&players) {
+        if let Some(ai) = &saved.ai {
+            commands
+                .entity(*player)
+                .insert(AiPlayer::from_saved(ai, unit));
+        }
+    }
+    for (saved, entity) in save.units.iter().zip(&units) {
+        let (Some(&owner), Some(entity)) = (players.get(saved.owner), *entity) else {
+            println!(
+                "Unit {} has no owner {} in the save",
+                saved.unit_type, saved.owner
+            );
+            continue;
+        };
+        let collector: Option<Collector> = saved.collector.as_ref().and_then(|collector| {
+            Some(Collector {
+                resource: collector.resource,
+                resource_entity: EntityWrapper {
+                    entity: *resource_sources.get(&collector.resource_source)?,
+                },
+                player: EntityWrapper {
+                    entity: *players.get(collector.player)?,
+                },
+                collecting: collector.collecting,
+            })
+        });
+        let production_queue: ProductionQueue = ProductionQueue {
+            items: saved
+                .production_queue
+                .iter()
+                .filter_map(|item| {
+                    Some(ProductionItem {
+                        action: item.action.clone(),
+                        player: *players.get(item.player)?,
+                        remaining: item.remaining,
+                        total: item.total,
+                    })
+                })
+                .collect(),
+            produced: saved.produced,
+        };
+        commands.entity(entity).insert(RestoredUnit {
+            transform: saved.transform,
+            health: saved.health,
+            shield: saved.shield,
+            collector,
+            move_command: saved.move_command.map(|target| MoveCommand { target }),
+            movement_path: saved
+                .movement_path
+                .clone()
+                .map(|path| MovementPath { path }),
+            jump_route: saved.jump_route,
+            attack_order: saved.attack_order.as_ref().and_then(|attack_order| {
+                Some(AttackOrder {
+                    target: unit(attack_order.target)?,
+                    destination: attack_order.destination,
+                })
+            }),
+            production_queue,
+            control_groups: saved.control_groups,
+        });
+        spawn_events.send(InstanceSpawnRequest {
+            location: saved.transform.translation,
+            unit_type: saved.unit_type.clone(),
+            civilisation: saved.civilisation.clone(),
+            owner,
+            entity: Some(entity),
+        });
+    }
+}
+
+fn save_hotkeys(
+    actions: InputActions,
+    mut save_requests: EventWriter<SaveRequest>,
+    mut load_requests: EventWriter<LoadRequest>,
+) {
+    if actions.just_pressed(InputAction::QuickSave) {
+        save_requests.send(SaveRequest {
+            path: QUICKSAVE_PATH.into(),
+        });
+    }
+    if actions.just_pressed(InputAction::QuickLoad) {
+        load_requests.send(LoadRequest {
+            path: QUICKSAVE_PATH.into(),
+        });
+    }
+}
+
+fn save_game(mut save_requests: EventReader<SaveRequest>, world: SaveableWorld) {
+    for request in save_requests.read() {
+        match world.capture().save(&request.path) {
+            Ok(()) => println!("Saved game to {}", request.path),
+            Err(e) => println!("Could not save game to {}: {}", request.path, e),
+        }
+    }
+}
+
+fn load_game(
+    mut commands: Commands,
+    mut load_requests: EventReader<LoadRequest>,
+    players: Query<Entity, With<PlayerInfo>>,
+    units: Query<Entity, With<UnitInformation>>,
+    mut resources: Query<(Entity, &ResourceSource, &mut ResourceLevel)>,
+    mut visibility: Option<ResMut<VisibilityGrid>>,
+    mut match_time: Option<ResMut<MatchTime>>,
+    mut spawn_events: EventWriter<InstanceSpawnRequest>,
+) {
+    // Only the last request matters, every load replaces the whole match
+    let Some(request) = load_requests.read().last() else {
+        return;
+    };
+    let save: SaveGame = match SaveGame::load(&request.path) {
+        Ok(save) => save,
+        Err(e) => {
+            println!("Could not load game {}: {}", request.path, e);
+            return;
+        }
+    };
+    for entity in players.iter().chain(units.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    restore(
+        &save,
+        &mut commands,
+        &mut resources,
+        visibility.as_deref_mut(),
+        match_time.as_deref_mut(),
+        &mut spawn_events,
+    );
+    println!("Loaded game {}", request.path);
+}
+
+fn restore_units(
+    mut commands: Commands,
+    mut restored_units: Query<(Entity, &mut RestoredUnit), With<UnitInformation>>,
+    unbuilt_units: Query<Entity, (With<RestoredUnit>, Without<UnitInformation>)>,
+) {
+    // The spawner has had its go, whatever it didn't build won't be built
+    for entity in unbuilt_units.iter() {
+        println!("Could not rebuild a saved unit");
+        commands.entity(entity).despawn_recursive();
+    }
+    for (entity, mut restored) in restored_units.iter_mut() {
+        let mut unit = commands.entity(entity);
+        unit.insert((
+            restored.transform,
+            restored.health,
+            std::mem::take(&mut restored.production_queue),
+            restored.control_groups,
+        ))
+        .remove::<RestoredUnit>();
+        // Otherwise the spawner's full shield stays
+        if let Some(shield) = restored.shield {
+            unit.insert(shield);
+        }
+        if let Some(collector) = restored.collector.take() {
+            unit.insert(collector);
+        }
+        if let Some(move_command) = restored.move_command.take() {
+            unit.insert(move_command);
+        }
+        if let Some(movement_path) = restored.movement_path.take() {
+            unit.insert(movement_path);
+        }
+        if let Some(jump_route) = restored.jump_route.take() {
+            unit.insert(jump_route);
+        }
+        if let Some(attack_order) = restored.attack_order.take() {
+            unit.insert(attack_order);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::civilisation::{Civilisations, CIVILISATIONS_PATH};
+    use crate::environment::GridSettings;
+    use crate::movable::Heading;
+    use crate::research::TechTree;
+    use crate::spawner::InstanceSpawner;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::utils::hashbrown;
+
+    /// Just enough of the game for the spawner to build units, without a window or renderer
+    fn headless_app() -> App {
+        let civilisations: Civilisations = Civilisations::load(CIVILISATIONS_PATH).unwrap();
+        let mut app: App = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), InstanceSpawner))
+            .init_asset::<Mesh>()
+            .init_asset::<Image>()
+            .init_asset::<StandardMaterial>()
+            .init_asset::<Scene>()
+            .insert_resource(civilisations.civilisation_boni())
+            .insert_resource(civilisations.unit_specifications())
+            .insert_resource(TechTree {
+                techs: hashbrown::HashMap::new(),
+            })
+            .insert_resource(VisibilityGrid::new(&GridSettings {
+                cell_size: 10.0,
+                grid_width: 4,
+                grid_height: 3,
+                xy_offset: Vec2::new(2.0, 1.5),
+                density: 0.0,
+            }))
+            .init_resource::<MatchTime>()
+            .add_event::<LoadRequest>()
+            .add_systems(FixedUpdate, restore_units.after(spawn));
+        for index in 0..2 {
+            app.world_mut().spawn((
+                ResourceSource {
+                    id: ResourceSourceId { system: 0, index },
+                },
+                ResourceLevel {
+                    resource_type: ResourceType::Plotanium,
+                    resource_amount: 42_000,
+                },
+            ));
+        }
+        app
+    }
+
+    fn match_in_progress() -> SaveGame {
+        let player = |local: bool, ai: Option<SavedAi>| SavedPlayer {
+            local,
+            ai,
+            colour: Some([0.2, 0.8, 0.2, 1.0]),
+            civilisation: Civilisation("Greek".into()),
+            tech_level: TechLevel::L1,
+            context_menu_actions: HashMap::from([(
+                UnitType::Spacestation,
+                vec![ContextMenuAction::Build(UnitType::Cruiser)],
+            )]),
+            researched: HashSet::from(["mining_efficiency".to_string()]),
+            researching: HashSet::new(),
+            stockpiles: HashMap::from([(ResourceType::Plotanium, 1234)]),
+        };
+        let unit = |unit_type: UnitType, owner: usize, x: f32| SavedUnit {
+            unit_type,
+            civilisation: Civilisation("Greek".into()),
+            owner,
+            transform: Transform::from_xyz(x, 2.0, 3.5)
+                .with_rotation(Quat::from_rotation_y(0.3))
+                .with_scale(Vec3::splat(0.5)),
+            health: Health {
+                current: 42.5,
+                max: 200.0,
+            },
+            shield: None,
+            collector: None,
+            move_command: None,
+            movement_path: None,
+            jump_route: None,
+            attack_order: None,
+            production_queue: Vec::new(),
+            produced: 0,
+            control_groups: ControlGroupMembership::default(),
+        };
+        let mut station = unit(UnitType::Spacestation, 0, -3.0);
+        station.production_queue = vec![SavedProductionItem {
+            action: ContextMenuAction::Build(UnitType::Cruiser),
+            player: 0,
+            remaining: 4.5,
+            total: 10.0,
+        }];
+        station.produced = 3;
+        station.shield = Some(Shield {
+            current: 120.0,
+            max: 400.0,
+        });
+        let mut miner = unit(UnitType::MiningStation, 0, -4.0);
+        miner.collector = Some(SavedCollector {
+            resource: ResourceType::Plotanium,
+            resource_source: ResourceSourceId {
+                system: 0,
+                index: 1,
+            },
+            player: 0,
+            collecting: CollectorState::Collecting,
+        });
+        let mut cruiser = unit(UnitType::Cruiser, 1, 12.0);
+        cruiser.move_command = Some(Vec2::new(30.0, -8.0));
+        cruiser.movement_path = Some(vec![
+            PathNode {
+                xy: Vec2::new(13.0, 3.0),
+                h: Heading::E,
+            },
+            PathNode {
+                xy: Vec2::new(14.0, 2.0),
+                h: Heading::SE,
+            },
+        ]);
+        cruiser.control_groups.insert(2);
+        cruiser.shield = Some(Shield {
+            current: 15.0,
+            max: 60.0,
+        });
+        cruiser.jump_route = Some(JumpRoute {
+            gate: 0,
+            target: Vec2::new(10_030.0, -8.0),
+        });
+        cruiser.attack_order = Some(SavedAttackOrder {
+            target: 0,
+            destination: Vec2::new(-3.0, 3.5),
+        });
+        let mut scouting = unit(UnitType::Cruiser, 1, 16.0);
+        // Still waiting for its path
+        scouting.move_command = Some(Vec2::new(40.0, 40.0));
+        scouting.shield = Some(Shield {
+            current: 60.0,
+            max: 60.0,
+        });
+        let ai: SavedAi = SavedAi {
+            difficulty: Difficulty::Hard,
+            script: AiScript::built_in(2, 4),
+            step: 2,
+            performed: 1,
+            reaction_elapsed: 0.25,
+            scout: Some(3),
+            scout_waypoint: 1,
+            seen_stations: vec![SavedSighting {
+                unit: 0,
+                position: Vec3::new(-3.0, 2.0, 3.5),
+            }],
+        };
+        SaveGame {
+            version: SAVE_VERSION,
+            players: vec![player(true, None), player(false, Some(ai))],
+            resources: vec![
+                SavedResource {
+                    id: ResourceSourceId {
+                        system: 0,
+                        index: 0,
+                    },
+                    resource_type: ResourceType::Plotanium,
+                    resource_amount: 41_000,
+                },
+                SavedResource {
+                    id: ResourceSourceId {
+                        system: 0,
+                        index: 1,
+                    },
+                    resource_type: ResourceType::Plotanium,
+                    resource_amount: 17,
+                },
+            ],
+            units: vec![station, miner, cruiser, scouting],
+            explored: vec!["##.".into(), "#..".into(), "...".into(), "..#".into()],
+            match_time: 754.5,
+        }
+    }
+
+    fn load(app: &mut App, save: SaveGame) {
+        app.world_mut().run_system_once_with(
+            save,
+            |In(save): In<SaveGame>,
+             mut commands: Commands,
+             mut resources: Query<(Entity, &ResourceSource, &mut ResourceLevel)>,
+             mut visibility: Option<ResMut<VisibilityGrid>>,
+             mut match_time: Option<ResMut<MatchTime>>,
+             mut spawn_events: EventWriter<InstanceSpawnRequest>| {
+                restore(
+                    &save,
+                    &mut commands,
+                    &mut resources,
+                    visibility.as_deref_mut(),
+                    match_time.as_deref_mut(),
+                    &mut spawn_events,
+                )
+            },
+        );
+        // One tick for the spawner, the restored state is applied right after it
+        app.world_mut().run_schedule(FixedUpdate);
+    }
+
+    #[test]
+    fn save_survives_ron() {
+        let save: SaveGame = match_in_progress();
+        let text: String = save.to_ron().unwrap();
+        assert_eq!(SaveGame::from_ron(&text).unwrap(), save);
+    }
+
+    #[test]
+    fn saves_of_other_versions_are_rejected() {
+        let mut save: SaveGame = match_in_progress();
+        save.version = SAVE_VERSION + 1;
+        assert!(SaveGame::from_ron(&save.to_ron().unwrap()).is_err());
+    }
+
+    #[test]
+    fn loading_and_saving_round_trips() {
+        let save: SaveGame = match_in_progress();
+        let mut app: App = headless_app();
+        load(
+            &mut app,
+            SaveGame::from_ron(&save.to_ron().unwrap()).unwrap(),
+        );
+        let world = app.world_mut();
+        assert_eq!(world.query::<&RestoredUnit>().iter(world).count(), 0);
+        let saved_again: SaveGame = world.run_system_once(|world: SaveableWorld| world.capture());
+        assert_eq!(saved_again, save);
+
+        // And the rebuilt world loads into another one just the same
+        let mut other_app: App = headless_app();
+        load(&mut other_app, saved_again);
+        let world = other_app.world_mut();
+        assert_eq!(
+            world.run_system_once(|world: SaveableWorld| world.capture()),
+            save
+        );
+    }
+
+    #[test]
+    fn units_that_cannot_be_rebuilt_are_dropped() {
+        let mut save: SaveGame = match_in_progress();
+        save.units[2].civilisation = Civilisation("Atlantean".into());
+        let mut app: App = headless_app();
+        load(&mut app, save);
+        let world = app.world_mut();
+        assert_eq!(world.query::<&RestoredUnit>().iter(world).count(), 0);
+        assert_eq!(world.query::<&UnitInformation>().iter(world).count(), 3);
+    }
+}
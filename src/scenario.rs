@@ -0,0 +1,270 @@
+use std::{error::Error, fs, path::Path};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::{AiPlayer, Difficulty};
+use crate::ai_script::AiScript;
+use crate::civilisation::Civilisations;
+use crate::environment::{CostZone, GridArea, GridSettings};
+use crate::hazards::Hazard;
+use crate::orbits::Orbit;
+use crate::ownable::PlayerColour;
+use crate::player_controller::{Civilisation, LocalPlayer, PlayerNumber, StartPosition};
+use crate::research::TechTree;
+use crate::resources::{ResourceStockpiles, ResourceType};
+use crate::spawner::{InstanceSpawnRequest, UnitType};
+
+pub const DEFAULT_SCENARIO_PATH: &str = "./assets/scenarios/skirmish.ron";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioUnit {
+    pub unit_type: UnitType,
+    pub position: Vec3,
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioPlayer {
+    pub civilisation: Civilisation,
+    /// The player sitting in front of this machine. Exactly one player should be local.
+    #[serde(default)]
+    pub local: bool,
+    /// Played by the AI at the given difficulty
+    #[serde(default)]
+    pub ai: Option<Difficulty>,
+    /// sRGB
+    pub colour: [f32; 3],
+    /// Where the camera starts out for the local player
+    #[serde(default)]
+    pub start_position: Option<Vec3>,
+    pub starting_resources: HashMap<ResourceType, i32>,
+    pub units: Vec<ScenarioUnit>,
+}
+/// A model placed on the map, like a planet or an asteroid
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Prop {
+    pub name: String,
+    #[serde(default)]
+    pub info: String,
+    /// Relative to the assets directory
+    pub model: String,
+    #[serde(default = "default_scene")]
+    pub scene: String,
+    /// In the real layout of the system, which may be far beyond the play area
+    pub position: DVec3,
+    /// Degrees around the vertical axis
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Moves the prop along an orbit instead of keeping it at its position
+    #[serde(default)]
+    pub orbit: Option<Orbit>,
+}
+impl Prop {
+    /// The same prop, orbit and all, moved by the offset
+    pub fn shifted(&self, offset: DVec3) -> Prop {
+        Prop {
+            position: self.position + offset,
+            orbit: self.orbit.map(|orbit| Orbit {
+                centre: orbit.centre + offset,
+                ..orbit
+            }),
+            ..self.clone()
+        }
+    }
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioResourceSource {
+    pub prop: Prop,
+    pub resource_type: ResourceType,
+    pub resource_amount: i32,
+    pub radius: f32,
+}
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Lighting {
+    pub illuminance: f32,
+    pub position: Vec3,
+    /// Degrees the directional light is tilted around the x axis
+    pub pitch: f32,
+    /// Replaces the default ambient light if given
+    #[serde(default)]
+    pub ambient_brightness: Option<f32>,
+}
+/// Another star system of the map. Positions in it are relative to the system, which the galaxy
+/// places apart from the others.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioSystem {
+    pub name: String,
+    /// Where the system is shown on the galaxy view, from (0, 0) at the top left to (1, 1)
+    pub galaxy_position: Vec2,
+    pub grid: GridSettings,
+    #[serde(default)]
+    pub resource_sources: Vec<ScenarioResourceSource>,
+    #[serde(default)]
+    pub props: Vec<Prop>,
+    #[serde(default)]
+    pub obstacles: Vec<GridArea>,
+    #[serde(default)]
+    pub cost_zones: Vec<CostZone>,
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+}
+/// Connects two systems, 0 being the scenario's own and 1 onwards the entries of `systems`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioJumpGate {
+    pub systems: [usize; 2],
+    /// Within each of the two systems
+    pub positions: [Vec2; 2],
+}
+/// Everything a match starts out with, as read from a scenario file. The map itself is the first
+/// star system, further ones are listed in `systems`.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub galaxy_position: Vec2,
+    pub grid: GridSettings,
+    pub lighting: Lighting,
+    pub players: Vec<ScenarioPlayer>,
+    #[serde(default)]
+    pub resource_sources: Vec<ScenarioResourceSource>,
+    #[serde(default)]
+    pub props: Vec<Prop>,
+    #[serde(default)]
+    pub obstacles: Vec<GridArea>,
+    #[serde(default)]
+    pub cost_zones: Vec<CostZone>,
+    #[serde(default)]
+    pub hazards: Vec<Hazard>,
+    #[serde(default)]
+    pub systems: Vec<ScenarioSystem>,
+    #[serde(default)]
+    pub jump_gates: Vec<ScenarioJumpGate>,
+}
+/// How a match departs from the players listed in its scenario
+#[derive(Default)]
+pub struct PlayerOverrides {
+    /// For the local player
+    pub civilisation: Option<Civilisation>,
+    /// For everyone else
+    pub ai_civilisation: Option<Civilisation>,
+    /// Build order for each AI player in scenario order, `None` for the built-in one of its
+    /// difficulty. The last one is repeated for any players beyond the list.
+    pub ai_scripts: Vec<Option<AiScript>>,
+    /// Hands the players the scenario doesn't give an AI to one at this difficulty
+    pub ai_for_all: Option<Difficulty>,
+}
+/// The prop an entity was spawned from, so the map can be exported again
+#[derive(Component)]
+pub struct ScenarioProp(pub Prop);
+
+fn default_scene() -> String {
+    "Scene0".into()
+}
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Scenario, Box<dyn Error>> {
+        let scenario: Scenario = ron::from_str(&fs::read_to_string(path)?)?;
+        let local_players: usize = scenario.players.iter().filter(|p| p.local).count();
+        if local_players != 1 {
+            return Err(format!("expected one local player, found {}", local_players).into());
+        }
+        if let Some(gate) = scenario.jump_gates.iter().find(|gate| {
+            gate.systems
+                .iter()
+                .any(|&system| system > scenario.systems.len())
+        }) {
+            return Err(format!("jump gate between unknown systems {:?}", gate.systems).into());
+        }
+        Ok(scenario)
+    }
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(directory) = Path::new(path).parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(
+            path,
+            ron::ser::to_string_pretty(self, PrettyConfig::default())?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Spawns the players of the scenario, numbered in its order, and requests their starting units
+pub fn spawn_players(
+    commands: &mut Commands,
+    spawn_events: &mut EventWriter<InstanceSpawnRequest>,
+    scenario: &Scenario,
+    overrides: &PlayerOverrides,
+    civilisations: &Civilisations,
+    tech_tree: &TechTree,
+) {
+    let mut ai_players: usize = 0;
+    for (index, scenario_player) in scenario.players.iter().enumerate() {
+        let civilisation_override: &Option<Civilisation> = if scenario_player.local {
+            &overrides.civilisation
+        } else {
+            &overrides.ai_civilisation
+        };
+        let civilisation: Civilisation = civilisation_override
+            .clone()
+            .unwrap_or_else(|| scenario_player.civilisation.clone());
+        let [r, g, b] = scenario_player.colour;
+        let mut player = commands.spawn((
+            civilisations.player_info(tech_tree, &civilisation),
+            PlayerColour(Color::srgb(r, g, b)),
+            ResourceStockpiles(scenario_player.starting_resources.clone()),
+            PlayerNumber(index + 1),
+        ));
+        if scenario_player.local {
+            player.insert(LocalPlayer);
+        }
+        if let Some(position) = scenario_player.start_position {
+            player.insert(StartPosition(position));
+        }
+        if let Some(difficulty) = scenario_player.ai.or(overrides.ai_for_all) {
+            let script: Option<AiScript> = overrides
+                .ai_scripts
+                .get(ai_players)
+                .or(overrides.ai_scripts.last())
+                .cloned()
+                .flatten();
+            ai_players += 1;
+            player.insert(match script {
+                Some(script) => AiPlayer::with_script(difficulty, script),
+                None => AiPlayer::new(difficulty),
+            });
+        }
+        let owner: Entity = player.id();
+        for unit in &scenario_player.units {
+            spawn_events.send(InstanceSpawnRequest {
+                location: unit.position,
+                unit_type: unit.unit_type.clone(),
+                civilisation: civilisation.clone(),
+                owner,
+                entity: None,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_scenario_loads() {
+        let scenario: Scenario = Scenario::load(DEFAULT_SCENARIO_PATH).unwrap();
+        assert!(scenario.players.iter().any(|player| player.ai.is_some()));
+        assert!(!scenario.resource_sources.is_empty());
+        for gate in &scenario.jump_gates {
+            assert_ne!(gate.systems[0], gate.systems[1]);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use crate::key_bindings::{InputAction, InputActions};
+use crate::map_editor::map_editor_closed;
+use crate::movable::Movable;
+use crate::ownable::{Owner, Selectable, Selected, SelectionCircle};
+use crate::player_controller::{mouse_controller, CameraControllerSettings, LocalPlayer, RayHit};
+use crate::resource_collection::Collector;
+use crate::spawner::{UnitInformation, UnitType};
+use crate::ui::RayBlock;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use std::time::Duration;
+
+/// Drags shorter than this (in logical pixels) are treated as clicks
+const DRAG_THRESHOLD: f32 = 5.0;
+const SELECTION_BOX_BORDER: Color = Color::srgb(0x81 as f32 / 256.0, 0xC1 as f32 / 256.0, 0.1);
+/// Clicking the same unit twice within this time selects all units of its type on screen
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+const SELECTION_BOX_FILL: Color = Color::srgba(0x81 as f32 / 256.0, 0xC1 as f32 / 256.0, 0.1, 0.1);
+
+pub struct Selection;
+impl Plugin for Selection {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SelectionRequest>()
+            .init_resource::<DragSelection>()
+            .add_systems(Startup, spawn_selection_box)
+            .add_systems(
+                Update,
+                (
+                    drag_selection.run_if(map_editor_closed),
+                    draw_selection_box.after(drag_selection),
+                    double_click_selection,
+                    selection_hotkeys,
+                    // Runs after the click handling so bulk selections win over single clicks
+                    apply_selection
+                        .after(drag_selection)
+                        .after(double_click_selection)
+                        .after(selection_hotkeys)
+                        .after(mouse_controller),
+                ),
+            );
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+}
+impl SelectionMode {
+    pub fn from_modifiers(add: bool, subtract: bool) -> Self {
+        if subtract {
+            SelectionMode::Subtract
+        } else if add {
+            SelectionMode::Add
+        } else {
+            SelectionMode::Replace
+        }
+    }
+    /// Whether an entity ends up selected
+    pub fn apply(&self, selected: bool, listed: bool) -> bool {
+        match self {
+            SelectionMode::Replace => listed,
+            SelectionMode::Add => selected || listed,
+            SelectionMode::Subtract => selected && !listed,
+        }
+    }
+}
+/// Change the selection. Anything that selects units in bulk goes through this.
+#[derive(Event)]
+pub struct SelectionRequest {
+    pub entities: Vec<Entity>,
+    pub mode: SelectionMode,
+}
+/// The rectangle being dragged, in logical window coordinates
+#[derive(Resource, Default)]
+pub struct DragSelection {
+    pub start: Option<Vec2>,
+    pub current: Vec2,
+}
+#[derive(Component)]
+struct SelectionBox;
+
+/// Projects a world position into viewport coordinates (origin top left), or None if it lies
+/// outside the camera's frustum depth
+pub fn project_to_viewport(
+    clip_from_world: Mat4,
+    viewport_size: Vec2,
+    world_position: Vec3,
+) -> Option<Vec2> {
+    let ndc: Vec3 = clip_from_world.project_point3(world_position);
+    if ndc.is_nan() || ndc.z < 0.0 || ndc.z > 1.0 {
+        return None;
+    }
+    let mut viewport_position: Vec2 = (ndc.truncate() + Vec2::ONE) / 2.0 * viewport_size;
+    viewport_position.y = viewport_size.y - viewport_position.y;
+    Some(viewport_position)
+}
+/// Entities whose viewport position lies within the rectangle spanned by the two corners
+pub fn entities_in_rect<I>(corner_a: Vec2, corner_b: Vec2, candidates: I) -> Vec<Entity>
+where
+    I: IntoIterator<Item = (Entity, Vec2)>,
+{
+    let rect: Rect = Rect::from_corners(corner_a, corner_b);
+    candidates
+        .into_iter()
+        .filter(|(_, position)| rect.contains(*position))
+        .map(|(entity, _)| entity)
+        .collect()
+}
+pub fn set_selected(
+    commands: &mut Commands,
+    entity: Entity,
+    children: &Children,
+    selection_circles: &mut Query<&mut Visibility, With<SelectionCircle>>,
+    selected: bool,
+) {
+    for child in children.iter() {
+        if let Ok(mut visibility) = selection_circles.get_mut(*child) {
+            *visibility = if selected {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+    if selected {
+        commands.entity(entity).insert(Selected {});
+    } else {
+        commands.entity(entity).remove::<Selected>();
+    }
+}
+
+fn spawn_selection_box(mut commands: Commands) {
+    commands.spawn((
+        SelectionBox,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            border_color: SELECTION_BOX_BORDER.into(),
+            background_color: SELECTION_BOX_FILL.into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+    ));
+}
+
+fn drag_selection(
+    mut drag_selection: ResMut<DragSelection>,
+    actions: InputActions,
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<CameraControllerSettings>>,
+    selectables: Query<(Entity, &GlobalTransform, &Owner), With<Selectable>>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+    rayblock: Query<Entity, With<RayBlock>>,
+    mut selection_requests: EventWriter<SelectionRequest>,
+) {
+    let (Ok(primary), Ok((camera, camera_transform)), Ok(local_player)) = (
+        primary_query.get_single(),
+        cameras.get_single(),
+        local_player.get_single(),
+    ) else {
+        return;
+    };
+    let Some(cursor) = primary.cursor_position() else {
+        return;
+    };
+    if actions.just_pressed(InputAction::Select) && rayblock.is_empty() {
+        drag_selection.start = Some(cursor);
+    }
+    drag_selection.current = cursor;
+    if !actions.just_released(InputAction::Select) {
+        return;
+    }
+    let Some(start) = drag_selection.start.take() else {
+        return;
+    };
+    if start.distance(cursor) < DRAG_THRESHOLD {
+        return;
+    }
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return;
+    };
+    let clip_from_world: Mat4 =
+        camera.clip_from_view() * camera_transform.compute_matrix().inverse();
+    let candidates = selectables
+        .iter()
+        .filter(|(_, _, owner)| owner.player == local_player)
+        .filter_map(|(entity, transform, _)| {
+            project_to_viewport(clip_from_world, viewport_size, transform.translation())
+                .map(|position| (entity, position))
+        });
+    selection_requests.send(SelectionRequest {
+        entities: entities_in_rect(start, cursor, candidates),
+        mode: SelectionMode::from_modifiers(
+            actions.pressed(InputAction::AddToSelection),
+            actions.pressed(InputAction::SubtractFromSelection),
+        ),
+    });
+}
+
+fn draw_selection_box(
+    drag_selection: Res<DragSelection>,
+    mut selection_box: Query<(&mut Style, &mut Visibility), With<SelectionBox>>,
+) {
+    let Ok((mut style, mut visibility)) = selection_box.get_single_mut() else {
+        return;
+    };
+    match drag_selection.start {
+        Some(start) if start.distance(drag_selection.current) >= DRAG_THRESHOLD => {
+            let rect: Rect = Rect::from_corners(start, drag_selection.current);
+            style.left = Val::Px(rect.min.x);
+            style.top = Val::Px(rect.min.y);
+            style.width = Val::Px(rect.width());
+            style.height = Val::Px(rect.height());
+            *visibility = Visibility::Visible;
+        }
+        _ => *visibility = Visibility::Hidden,
+    }
+}
+
+#[derive(Default)]
+struct LastClick {
+    unit: Option<Entity>,
+    at: Duration,
+}
+/// Units of the local player whose viewport position is on screen
+fn units_on_screen<'a, I>(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    units: I,
+) -> Vec<Entity>
+where
+    I: IntoIterator<Item = (Entity, &'a GlobalTransform)>,
+{
+    let Some(viewport_size) = camera.logical_viewport_size() else {
+        return Vec::new();
+    };
+    let clip_from_world: Mat4 =
+        camera.clip_from_view() * camera_transform.compute_matrix().inverse();
+    let candidates = units.into_iter().filter_map(|(entity, transform)| {
+        project_to_viewport(clip_from_world, viewport_size, transform.translation())
+            .map(|position| (entity, position))
+    });
+    entities_in_rect(Vec2::ZERO, viewport_size, candidates)
+}
+
+fn double_click_selection(
+    time: Res<Time>,
+    actions: InputActions,
+    mut ray_hit_event: EventReader<RayHit>,
+    mut last_click: Local<LastClick>,
+    cameras: Query<(&Camera, &GlobalTransform), With<CameraControllerSettings>>,
+    units: Query<(Entity, &GlobalTransform, &Owner, &UnitInformation), With<Selectable>>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+    mut selection_requests: EventWriter<SelectionRequest>,
+) {
+    let (Ok((camera, camera_transform)), Ok(local_player)) =
+        (cameras.get_single(), local_player.get_single())
+    else {
+        return;
+    };
+    for hit in ray_hit_event.read() {
+        if !hit.mouse_key_enable_mouse {
+            continue;
+        }
+        let now: Duration = time.elapsed();
+        let double_click: bool =
+            last_click.unit == Some(hit.hit_entity) && now - last_click.at < DOUBLE_CLICK;
+        *last_click = LastClick {
+            unit: if double_click {
+                None
+            } else {
+                Some(hit.hit_entity)
+            },
+            at: now,
+        };
+        if !double_click {
+            continue;
+        }
+        let Ok((_, _, owner, unit_information)) = units.get(hit.hit_entity) else {
+            continue;
+        };
+        if owner.player != local_player {
+            continue;
+        }
+        let unit_type: &UnitType = &unit_information.unit_type;
+        let same_type = units
+            .iter()
+            .filter(|(_, _, owner, other)| {
+                owner.player == local_player && other.unit_type == *unit_type
+            })
+            .map(|(entity, transform, _, _)| (entity, transform));
+        selection_requests.send(SelectionRequest {
+            entities: units_on_screen(camera, camera_transform, same_type),
+            mode: SelectionMode::from_modifiers(
+                actions.pressed(InputAction::AddToSelection),
+                false,
+            ),
+        });
+    }
+}
+
+/// Select every unit of the selected types, every idle collector or every military unit
+/// of the local player, wherever they are on the map
+fn selection_hotkeys(
+    actions: InputActions,
+    units: Query<
+        (
+            Entity,
+            &Owner,
+            &UnitInformation,
+            Has<Movable>,
+            Has<Collector>,
+            Has<Selected>,
+        ),
+        With<Selectable>,
+    >,
+    local_player: Query<Entity, With<LocalPlayer>>,
+    mut selection_requests: EventWriter<SelectionRequest>,
+) {
+    let Ok(local_player) = local_player.get_single() else {
+        return;
+    };
+    let own_units: Vec<(Entity, &UnitInformation, bool, bool, bool)> = units
+        .iter()
+        .filter(|(_, owner, _, _, _, _)| owner.player == local_player)
+        .map(
+            |(entity, _, unit_information, movable, collecting, selected)| {
+                (entity, unit_information, movable, collecting, selected)
+            },
+        )
+        .collect();
+    let entities: Vec<Entity> = if actions.just_pressed(InputAction::SelectAllOfType) {
+        let selected_types: Vec<&UnitType> = own_units
+            .iter()
+            .filter(|(_, _, _, _, selected)| *selected)
+            .map(|(_, unit_information, _, _, _)| &unit_information.unit_type)
+            .collect();
+        own_units
+            .iter()
+            .filter(|(_, unit_information, _, _, _)| {
+                selected_types.contains(&&unit_information.unit_type)
+            })
+            .map(|(entity, _, _, _, _)| *entity)
+            .collect()
+    } else if actions.just_pressed(InputAction::SelectIdleCollectors) {
+        own_units
+            .iter()
+            .filter(|(_, unit_information, _, collecting, _)| {
+                unit_information.stats.is_collector() && !collecting
+            })
+            .map(|(entity, _, _, _, _)| *entity)
+            .collect()
+    } else if actions.just_pressed(InputAction::SelectMilitary) {
+        own_units
+            .iter()
+            .filter(|(_, unit_information, movable, _, _)| {
+                *movable && !unit_information.stats.is_collector()
+            })
+            .map(|(entity, _, _, _, _)| *entity)
+            .collect()
+    } else {
+        return;
+    };
+    if entities.is_empty() {
+        return;
+    }
+    selection_requests.send(SelectionRequest {
+        entities,
+        mode: SelectionMode::from_modifiers(actions.pressed(InputAction::AddToSelection), false),
+    });
+}
+
+fn apply_selection(
+    mut commands: Commands,
+    mut selection_requests: EventReader<SelectionRequest>,
+    selectables: Query<(Entity, &Children, Has<Selected>), With<Selectable>>,
+    mut selection_circles: Query<&mut Visibility, With<SelectionCircle>>,
+) {
+    for request in selection_requests.read() {
+        for (entity, children, selected) in selectables.iter() {
+            let select: bool = request
+                .mode
+                .apply(selected, request.entities.contains(&entity));
+            if select != selected {
+                set_selected(
+                    &mut commands,
+                    entity,
+                    children,
+                    &mut selection_circles,
+                    select,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn top_down_camera() -> (Mat4, Vec2) {
+        let viewport_size: Vec2 = Vec2::new(800.0, 600.0);
+        let view: Mat4 = Transform::from_xyz(0.0, 10.0, 0.0)
+            .looking_at(Vec3::ZERO, Vec3::Z)
+            .compute_matrix();
+        let projection: Mat4 = Mat4::perspective_infinite_reverse_rh(
+            std::f32::consts::FRAC_PI_4,
+            viewport_size.x / viewport_size.y,
+            0.1,
+        );
+        (projection * view.inverse(), viewport_size)
+    }
+
+    #[test]
+    fn point_below_camera_projects_to_centre() {
+        let (clip_from_world, viewport_size) = top_down_camera();
+        let position: Vec2 =
+            project_to_viewport(clip_from_world, viewport_size, Vec3::ZERO).unwrap();
+        assert!(position.distance(viewport_size / 2.0) < 1e-3);
+    }
+
+    #[test]
+    fn point_behind_camera_is_not_projected() {
+        let (clip_from_world, viewport_size) = top_down_camera();
+        assert!(
+            project_to_viewport(clip_from_world, viewport_size, Vec3::new(0.0, 20.0, 0.0))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rect_contains_projected_units_regardless_of_drag_direction() {
+        let (clip_from_world, viewport_size) = top_down_camera();
+        let inside: Entity = Entity::from_raw(1);
+        let outside: Entity = Entity::from_raw(2);
+        let candidates = [(inside, Vec3::ZERO), (outside, Vec3::new(3.0, 0.0, 3.0))].map(
+            |(entity, world_position)| {
+                (
+                    entity,
+                    project_to_viewport(clip_from_world, viewport_size, world_position).unwrap(),
+                )
+            },
+        );
+        let centre: Vec2 = viewport_size / 2.0;
+        let selected = entities_in_rect(centre + 20.0, centre - 20.0, candidates);
+        assert_eq!(selected, vec![inside]);
+    }
+
+    #[test]
+    fn modifiers_add_and_subtract() {
+        assert!(SelectionMode::from_modifiers(true, false).apply(true, false));
+        assert!(SelectionMode::from_modifiers(true, false).apply(false, true));
+        assert!(!SelectionMode::from_modifiers(false, true).apply(true, true));
+        assert!(SelectionMode::from_modifiers(false, true).apply(true, false));
+        assert!(!SelectionMode::from_modifiers(false, false).apply(true, false));
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use bevy::ecs::event::{EventRegistry, ShouldUpdateEvents};
+use bevy::ecs::schedule::ExecutorKind;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::movable::MovementPath;
+use crate::ownable::Owner;
+use crate::player_controller::PlayerNumber;
+use crate::resource_collection::Collector;
+use crate::resources::{ResourceLevel, ResourceSource, ResourceStockpiles};
+use crate::spawner::{Health, SpawnOrder};
+
+/// Simulation ticks per second, unless set with `--tick-rate`
+pub const DEFAULT_TICK_RATE: f64 = 30.0;
+/// Further than any ship flies in a tick. Units moving more than this were teleported, e.g. by a
+/// jump gate, and aren't drawn flying across the map.
+const TELEPORT_DISTANCE: f32 = 10.0;
+
+/// Runs the gameplay systems in `FixedUpdate`, so a match plays out the same at any frame rate.
+/// Units are drawn in between their last two simulated positions.
+pub struct Simulation;
+impl Plugin for Simulation {
+    fn build(&self, app: &mut App) {
+        // Systems that aren't ordered against each other would otherwise run in whichever order
+        // the threads get to them
+        app.edit_schedule(FixedUpdate, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+        // Events are otherwise dropped after two frames until the first tick has run, taking the
+        // requests for the starting units with them
+        app.world_mut()
+            .get_resource_or_insert_with(EventRegistry::default)
+            .should_update = ShouldUpdateEvents::Waiting;
+        app.init_resource::<StateHash>()
+            .init_resource::<MatchTime>()
+            .add_systems(
+                FixedFirst,
+                (restore_simulated_transforms, advance_match_time),
+            )
+            .add_systems(
+                FixedLast,
+                (
+                    store_simulated_transforms,
+                    hash_state.after(store_simulated_transforms),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    start_interpolating,
+                    interpolate_transforms.after(start_interpolating),
+                )
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+/// Where a unit was after the last two ticks, and where it was last drawn
+#[derive(Component)]
+struct InterpolatedTransform {
+    previous: Transform,
+    current: Transform,
+    rendered: Transform,
+}
+/// Fingerprint of the simulated state after the latest tick. Runs with the same inputs have the
+/// same hash after the same number of ticks.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StateHash {
+    pub tick: u64,
+    pub hash: u64,
+}
+
+/// Seconds of the match simulated so far. Unlike the fixed clock it can be set, so that a loaded
+/// match carries on from when it was saved.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Debug)]
+pub struct MatchTime {
+    pub elapsed: f64,
+}
+
+fn advance_match_time(time: Res<Time>, mut match_time: ResMut<MatchTime>) {
+    match_time.elapsed += time.delta_seconds_f64();
+}
+
+fn start_interpolating(
+    mut commands: Commands,
+    units: Query<(Entity, &Transform), (With<Owner>, Without<InterpolatedTransform>)>,
+) {
+    for (entity, transform) in units.iter() {
+        commands.entity(entity).insert(InterpolatedTransform {
+            previous: *transform,
+            current: *transform,
+            rendered: *transform,
+        });
+    }
+}
+
+fn restore_simulated_transforms(mut units: Query<(&mut Transform, &mut InterpolatedTransform)>) {
+    for (mut transform, mut interpolated) in units.iter_mut() {
+        interpolated.previous = interpolated.current;
+        *transform = interpolated.current;
+    }
+}
+
+fn store_simulated_transforms(mut units: Query<(&Transform, &mut InterpolatedTransform)>) {
+    for (transform, mut interpolated) in units.iter_mut() {
+        interpolated.current = *transform;
+        if transform
+            .translation
+            .distance(interpolated.previous.translation)
+            > TELEPORT_DISTANCE
+        {
+            interpolated.previous = *transform;
+        }
+    }
+}
+
+fn interpolate_transforms(
+    fixed_time: Res<Time<Fixed>>,
+    mut units: Query<(&mut Transform, &mut InterpolatedTransform)>,
+) {
+    let fraction: f32 = fixed_time.overstep_fraction().clamp(0.0, 1.0);
+    for (mut transform, mut interpolated) in units.iter_mut() {
+        // Placed outside of the simulation, like a loaded unit, so there is nothing to blend
+        if *transform != interpolated.current && *transform != interpolated.rendered {
+            interpolated.previous = *transform;
+            interpolated.current = *transform;
+        }
+        let previous: Transform = interpolated.previous;
+        let current: Transform = interpolated.current;
+        *transform = Transform {
+            translation: previous.translation.lerp(current.translation, fraction),
+            rotation: previous.rotation.slerp(current.rotation, fraction),
+            scale: previous.scale.lerp(current.scale, fraction),
+        };
+        interpolated.rendered = *transform;
+    }
+}
+
+type HashedUnit = (
+    &'static SpawnOrder,
+    &'static Owner,
+    &'static Transform,
+    Option<&'static Health>,
+    Option<&'static MovementPath>,
+    Option<&'static Collector>,
+);
+/// Everything the simulation decides, as far as it ends up in the state hash. Units, players and
+/// resources go by their spawn order, number and id, the entities depend on what else was
+/// spawned in the meantime.
+#[derive(SystemParam)]
+struct SimulatedState<'w, 's> {
+    units: Query<'w, 's, HashedUnit>,
+    players: Query<'w, 's, (&'static PlayerNumber, &'static ResourceStockpiles)>,
+    resource_levels: Query<'w, 's, (&'static ResourceSource, &'static ResourceLevel)>,
+}
+impl SimulatedState<'_, '_> {
+    fn hash(&self) -> u64 {
+        let mut hasher: DefaultHasher = DefaultHasher::new();
+        let mut units: Vec<_> = self.units.iter().collect();
+        units.sort_by_key(|(spawn_order, ..)| **spawn_order);
+        for (spawn_order, owner, transform, health, movement_path, collector) in units {
+            spawn_order.hash(&mut hasher);
+            self.players
+                .get(owner.player)
+                .ok()
+                .map(|(number, _)| *number)
+                .hash(&mut hasher);
+            for value in transform
+                .translation
+                .to_array()
+                .into_iter()
+                .chain(transform.rotation.to_array())
+            {
+                value.to_bits().hash(&mut hasher);
+            }
+            health
+                .map(|health| health.current.to_bits())
+                .hash(&mut hasher);
+            movement_path
+                .map(|movement_path| movement_path.path.len())
+                .hash(&mut hasher);
+            collector
+                .map(|collector| collector.collecting as u8)
+                .hash(&mut hasher);
+        }
+        let mut players: Vec<_> = self.players.iter().collect();
+        players.sort_by_key(|(number, _)| **number);
+        for (number, stockpile) in players {
+            number.hash(&mut hasher);
+            // Summed up, so the order the map hands out its entries in doesn't matter
+            let mut entries: u64 = 0;
+            for entry in stockpile.0.iter() {
+                let mut entry_hasher: DefaultHasher = DefaultHasher::new();
+                entry.hash(&mut entry_hasher);
+                entries = entries.wrapping_add(entry_hasher.finish());
+            }
+            entries.hash(&mut hasher);
+        }
+        let mut resource_levels: Vec<_> = self.resource_levels.iter().collect();
+        resource_levels.sort_by_key(|(source, _)| (source.id.system, source.id.index));
+        for (source, resource_level) in resource_levels {
+            source.id.hash(&mut hasher);
+            resource_level.resource_amount.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+fn hash_state(mut state_hash: ResMut<StateHash>, state: SimulatedState) {
+    state_hash.tick += 1;
+    state_hash.hash = state.hash();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::ai::{AiOpponent, AiPlayer, Difficulty};
+    use crate::civilisation::{CivilisationPlugin, Civilisations};
+    use crate::environment::{GridSettings, MovementGrid};
+    use crate::galaxy::Galaxy;
+    use crate::large_world::{Impostor, LargeWorld, LargeWorldPosition};
+    use crate::movable::{Heading, MoveCommand, PathNode, UnitMovement};
+    use crate::orbits::{Orbit, Orbiting, Orbits};
+    use crate::player_controller::{
+        Alert, CameraControllerSettings, Civilisation, ContextMenuAction, LocalPlayer,
+    };
+    use crate::production::{Production, ProductionRequest};
+    use crate::research::{Research, TechTree};
+    use crate::resource_collection::{CollectorState, ResourceCollection};
+    use crate::resources::{ResourceSourceId, ResourceType};
+    use crate::spawner::{
+        EntityWrapper, InstanceSpawnRequest, InstanceSpawner, UnitInformation, UnitSpecifications,
+        UnitType,
+    };
+    use bevy::math::DVec3;
+    use bevy::time::TimeUpdateStrategy;
+    use bevy::utils::HashMap;
+
+    /// Sends two units along a set path and a third one to find its own, for a second of match time
+    /// at 50 ticks per second
+    fn run(frame_time: Duration) -> StateHash {
+        let mut app: App = App::new();
+        let mut movement_grid: MovementGrid = MovementGrid::new(GridSettings {
+            cell_size: 1.0,
+            grid_width: 20,
+            grid_height: 20,
+            xy_offset: Vec2::splat(10.0),
+            density: 0.0,
+        });
+        movement_grid.rebuild(&[], &[]);
+        app.add_plugins((MinimalPlugins, UnitMovement, Simulation))
+            .insert_resource(Time::<Fixed>::from_hz(50.0))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame_time))
+            .insert_resource(movement_grid)
+            .insert_resource(Galaxy::default());
+        let player: Entity = app.world_mut().spawn_empty().id();
+        for (order, start) in [Vec3::new(0.0, 2.0, 0.0), Vec3::new(-3.0, 2.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            app.world_mut().spawn((
+                SpawnOrder(order as u64),
+                Transform::from_translation(start),
+                Owner { player },
+                MovementPath {
+                    path: vec![
+                        PathNode {
+                            xy: Vec2::new(5.0, 5.0),
+                            h: Heading::N,
+                        },
+                        PathNode {
+                            xy: start.xz() + Vec2::new(0.5, 0.5),
+                            h: Heading::N,
+                        },
+                    ],
+                },
+            ));
+        }
+        app.world_mut().spawn((
+            SpawnOrder(2),
+            Transform::from_xyz(2.0, 2.0, -1.0),
+            Owner { player },
+            MoveCommand {
+                target: Vec2::new(-6.0, 4.0),
+            },
+        ));
+        // The first update only starts the clock
+        let frames: u32 = (Duration::from_secs(1).as_nanos() / frame_time.as_nanos()) as u32 + 1;
+        for _ in 0..frames {
+            app.update();
+        }
+        *app.world().resource::<StateHash>()
+    }
+
+    #[test]
+    fn the_same_inputs_give_the_same_state_at_any_frame_rate() {
+        let first: StateHash = run(Duration::from_millis(20));
+        assert_eq!(first.tick, 50);
+        assert_eq!(first, run(Duration::from_millis(20)));
+        assert_eq!(first, run(Duration::from_millis(10)));
+        assert_eq!(first, run(Duration::from_millis(5)));
+    }
+
+    fn resource_source(
+        app: &mut App,
+        index: usize,
+        position: Vec3,
+        orbit: Option<Orbit>,
+    ) -> Entity {
+        let mut resource = app.world_mut().spawn((
+            Transform::from_translation(position),
+            LargeWorldPosition {
+                position: position.as_dvec3(),
+                scale: 1.0,
+            },
+            ResourceSource {
+                id: ResourceSourceId { system: 0, index },
+            },
+            ResourceLevel {
+                resource_type: ResourceType::Plotanium,
+                resource_amount: 10_000,
+            },
+        ));
+        if let Some(orbit) = orbit {
+            resource.insert(Orbiting(orbit));
+        }
+        let body: Entity = resource.id();
+        app.world_mut()
+            .spawn((Impostor { body }, Transform::from_translation(position)));
+        body
+    }
+
+    /// A local player mining an orbiting asteroid against a computer player, for three seconds of
+    /// match time at 50 ticks per second, while the camera flies off at the given speed
+    fn run_match(frame_time: Duration, camera_speed: f32) -> (StateHash, i32) {
+        let mut app: App = App::new();
+        let mut movement_grid: MovementGrid = MovementGrid::new(GridSettings {
+            cell_size: 1.0,
+            grid_width: 60,
+            grid_height: 60,
+            xy_offset: Vec2::splat(30.0),
+            density: 0.0,
+        });
+        movement_grid.rebuild(&[], &[]);
+        app.add_plugins((MinimalPlugins, CivilisationPlugin, Research))
+            .add_plugins((
+                UnitMovement,
+                ResourceCollection,
+                Production,
+                AiOpponent,
+                Orbits,
+                LargeWorld,
+                InstanceSpawner,
+                Simulation,
+            ))
+            .insert_resource(Time::<Fixed>::from_hz(50.0))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame_time))
+            .insert_resource(movement_grid)
+            .insert_resource(Galaxy::default())
+            .add_event::<Alert>()
+            .add_systems(
+                Update,
+                move |mut camera: Query<&mut Transform, With<CameraControllerSettings>>| {
+                    for mut transform in camera.iter_mut() {
+                        transform.translation += Vec3::new(1.0, 4.0, -2.0) * camera_speed;
+                    }
+                },
+            );
+        app.world_mut().spawn((
+            CameraControllerSettings::default(),
+            Transform::from_xyz(0.0, 20.0, 0.0),
+        ));
+        // Slow enough for the docked miner to stay in reach
+        let orbit: Orbit = Orbit {
+            centre: DVec3::new(-13.0, 2.0, 0.0),
+            semi_major_axis: 3.0,
+            eccentricity: 0.0,
+            period: 600.0,
+            phase: 0.0,
+            periapsis: 0.0,
+            obstacle_radius: None,
+        };
+        let asteroid: Entity =
+            resource_source(&mut app, 0, orbit.position_at(0.0).as_vec3(), Some(orbit));
+        resource_source(&mut app, 1, Vec3::new(14.0, 2.0, 10.0), None);
+
+        let civilisation: Civilisation = Civilisation("Greek".into());
+        let player_info = app
+            .world()
+            .resource::<Civilisations>()
+            .player_info(app.world().resource::<TechTree>(), &civilisation);
+        let stockpiles = || ResourceStockpiles(HashMap::from([(ResourceType::Plotanium, 100)]));
+        let player: Entity = app
+            .world_mut()
+            .spawn((
+                player_info.clone(),
+                stockpiles(),
+                PlayerNumber(1),
+                LocalPlayer,
+            ))
+            .id();
+        let ai_player: Entity = app
+            .world_mut()
+            .spawn((
+                player_info,
+                stockpiles(),
+                PlayerNumber(2),
+                AiPlayer::new(Difficulty::Hard),
+            ))
+            .id();
+        let miner: Entity = app
+            .world_mut()
+            .spawn(Collector {
+                resource: ResourceType::Plotanium,
+                resource_entity: EntityWrapper { entity: asteroid },
+                player: EntityWrapper { entity: player },
+                collecting: CollectorState::Approaching,
+            })
+            .id();
+        for (unit_type, location, owner, entity) in [
+            (
+                UnitType::MiningStation,
+                Vec3::new(-9.0, 2.0, 0.0),
+                player,
+                Some(miner),
+            ),
+            (
+                UnitType::Spacestation,
+                Vec3::new(10.0, 2.0, 10.0),
+                ai_player,
+                None,
+            ),
+            (
+                UnitType::MiningStation,
+                Vec3::new(12.0, 2.0, 10.0),
+                ai_player,
+                None,
+            ),
+        ] {
+            app.world_mut().send_event(InstanceSpawnRequest {
+                location,
+                unit_type,
+                civilisation: civilisation.clone(),
+                owner,
+                entity,
+            });
+        }
+        let frames: u32 = (Duration::from_secs(3).as_nanos() / frame_time.as_nanos()) as u32 + 1;
+        for _ in 0..frames {
+            app.update();
+        }
+        let world: &World = app.world();
+        assert!(world.get::<UnitInformation>(miner).is_some());
+        let collected: i32 =
+            world.get::<ResourceStockpiles>(player).unwrap().0[&ResourceType::Plotanium] - 100;
+        (*world.resource::<StateHash>(), collected)
+    }
+
+    #[test]
+    fn matches_play_out_the_same_whatever_the_camera_does() {
+        let (first, collected) = run_match(Duration::from_millis(20), 0.0);
+        assert_eq!(first.tick, 150);
+        // The miner stayed docked to the moving asteroid the whole time
+        assert!(collected > 0);
+        for (frame_time, camera_speed) in [(20, 0.0), (20, 5_000.0), (10, 100.0), (5, 50_000.0)] {
+            assert_eq!(
+                run_match(Duration::from_millis(frame_time), camera_speed),
+                (first, collected)
+            );
+        }
+    }
+
+    /// Builds two cruisers at a space station for a second of match time at 50 ticks per second,
+    /// while something outside of the simulation spawns an entity every frame like the UI does.
+    /// Returns the cruisers along with the state.
+    fn run_production(frame_time: Duration) -> (StateHash, Vec<Entity>) {
+        let mut app: App = App::new();
+        app.add_plugins((MinimalPlugins, CivilisationPlugin, Research))
+            .add_plugins((Production, InstanceSpawner, Simulation))
+            .insert_resource(Time::<Fixed>::from_hz(50.0))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(frame_time))
+            .add_event::<Alert>()
+            .add_systems(Update, |mut commands: Commands| {
+                commands.spawn_empty();
+            });
+        let civilisation: Civilisation = Civilisation("Greek".into());
+        // Quick enough for both to be done within the second
+        app.world_mut()
+            .resource_mut::<UnitSpecifications>()
+            .unit_specifications
+            .get_mut(&(civilisation.clone(), UnitType::Cruiser))
+            .unwrap()
+            .build_time = 0.3;
+        let player_info = app
+            .world()
+            .resource::<Civilisations>()
+            .player_info(app.world().resource::<TechTree>(), &civilisation);
+        let player: Entity = app
+            .world_mut()
+            .spawn((
+                player_info,
+                ResourceStockpiles(HashMap::from([(ResourceType::Plotanium, 100)])),
+                PlayerNumber(1),
+            ))
+            .id();
+        let station: Entity = app.world_mut().spawn_empty().id();
+        app.world_mut().send_event(InstanceSpawnRequest {
+            location: Vec3::ZERO,
+            unit_type: UnitType::Spacestation,
+            civilisation,
+            owner: player,
+            entity: Some(station),
+        });
+        let frames: u32 = (Duration::from_secs(1).as_nanos() / frame_time.as_nanos()) as u32 + 1;
+        let mut ordered: bool = false;
+        for _ in 0..frames {
+            app.update();
+            if !ordered && app.world().get::<UnitInformation>(station).is_some() {
+                for _ in 0..2 {
+                    app.world_mut().send_event(ProductionRequest {
+                        producer: station,
+                        player,
+                        action: ContextMenuAction::Build(UnitType::Cruiser),
+                    });
+                }
+                ordered = true;
+            }
+        }
+        let world = app.world_mut();
+        let mut units = world.query::<(Entity, &SpawnOrder, &UnitInformation)>();
+        let mut cruisers: Vec<(SpawnOrder, Entity)> = units
+            .iter(world)
+            .filter(|(_, _, unit_information)| unit_information.unit_type == UnitType::Cruiser)
+            .map(|(entity, spawn_order, _)| (*spawn_order, entity))
+            .collect();
+        cruisers.sort();
+        (
+            *world.resource::<StateHash>(),
+            cruisers.into_iter().map(|(_, entity)| entity).collect(),
+        )
+    }
+
+    #[test]
+    fn units_built_in_the_match_hash_the_same_at_any_frame_rate() {
+        let (first, cruisers) = run_production(Duration::from_millis(20));
+        assert_eq!(first.tick, 50);
+        assert_eq!(cruisers.len(), 2);
+        let (second, other_cruisers) = run_production(Duration::from_millis(5));
+        // More frames means more entities spawned in between, the cruisers got different ones
+        assert_ne!(cruisers, other_cruisers);
+        assert_eq!(first, second);
+    }
+}
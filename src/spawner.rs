@@ -1,14 +1,17 @@
 use crate::{
+    ai::think,
+    civilisation::CivilisationBoniMap,
+    minimap::{MinimapMarker, NEUTRAL_MARKER},
     movable::Movable,
-    ownable::{Selectable, SelectionCircle},
-    player_controller::{Civilisation, RenderLayerMap},
+    ownable::{Owner, PlayerColour, Selectable, SelectionCircle},
+    player_controller::{Civilisation, PlayerInfo, RenderLayerMap},
+    production::{advance_production, ProductionQueue},
+    research::TechTree,
     resources::ResourceType,
     utils::ShapeTypeSerializable,
 };
 use bevy::{
-    prelude::*,
-    render::view::RenderLayers,
-    utils::{hashbrown::HashMap},
+    ecs::system::EntityCommands, prelude::*, render::view::RenderLayers, utils::hashbrown::HashMap,
 };
 use bevy_rapier3d::{prelude::*, rapier::prelude::ShapeType};
 use serde::{Deserialize, Serialize};
@@ -18,9 +21,7 @@ use std::fmt;
 // Create some sort of unit map with regards to civ
 impl fmt::Display for Civilisation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Civilisation::Greek => write!(f, "Greek"),
-        }
+        write!(f, "{}", self.0)
     }
 }
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -28,6 +29,12 @@ pub enum UnitStat {
     MaxMiningDist(f32),
     BaseMiningRate(f32),
     BonusMiningRate((ResourceType, f32)),
+    VisionRange(f32),
+    MaxHealth(f32),
+    MaxShield(f32),
+    /// Per second
+    WeaponDamage(f32),
+    WeaponRange(f32),
 }
 #[derive(Clone, Serialize, Deserialize)]
 pub struct UnitStats(pub Vec<UnitStat>);
@@ -44,8 +51,95 @@ impl DerefMut for UnitStats {
         &mut self.0
     }
 }
+impl UnitStats {
+    pub fn is_collector(&self) -> bool {
+        self.0
+            .iter()
+            .any(|stat| matches!(stat, UnitStat::BaseMiningRate(_)))
+    }
+    pub fn vision_range(&self) -> f32 {
+        let mut vision_range: f32 = 0.0;
+        for stat in &self.0 {
+            if let UnitStat::VisionRange(v) = stat {
+                vision_range = *v;
+            }
+        }
+        vision_range
+    }
+    pub fn max_health(&self) -> f32 {
+        let mut max_health: f32 = DEFAULT_MAX_HEALTH;
+        for stat in &self.0 {
+            if let UnitStat::MaxHealth(h) = stat {
+                max_health = *h;
+            }
+        }
+        max_health
+    }
+    pub fn max_shield(&self) -> f32 {
+        let mut max_shield: f32 = 0.0;
+        for stat in &self.0 {
+            if let UnitStat::MaxShield(s) = stat {
+                max_shield = *s;
+            }
+        }
+        max_shield
+    }
+    pub fn weapon(&self) -> Option<Weapon> {
+        let mut damage: Option<f32> = None;
+        let mut range: f32 = DEFAULT_WEAPON_RANGE;
+        for stat in &self.0 {
+            match stat {
+                UnitStat::WeaponDamage(d) => damage = Some(*d),
+                UnitStat::WeaponRange(r) => range = *r,
+                _ => {}
+            }
+        }
+        damage.map(|damage| Weapon { damage, range })
+    }
+}
+/// Used for units whose specification doesn't state a `MaxHealth`
+const DEFAULT_MAX_HEALTH: f32 = 100.0;
+/// Used for armed units whose specification doesn't state a `WeaponRange`
+const DEFAULT_WEAPON_RANGE: f32 = 8.0;
+#[derive(Component, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+impl Health {
+    pub fn full(max: f32) -> Self {
+        Health { current: max, max }
+    }
+    pub fn fraction(&self) -> f32 {
+        if self.max <= 0.0 {
+            return 0.0;
+        }
+        (self.current / self.max).clamp(0.0, 1.0)
+    }
+}
+/// Takes damage before the hull does and recharges over time. Only units with a `MaxShield` have one.
+#[derive(Component, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Shield {
+    pub current: f32,
+    pub max: f32,
+}
+impl Shield {
+    /// Takes what it can of the damage and returns the rest
+    pub fn absorb(&mut self, damage: f32) -> f32 {
+        let absorbed: f32 = damage.min(self.current);
+        self.current -= absorbed;
+        damage - absorbed
+    }
+}
+/// Fires at the closest enemy in range. Only units with a `WeaponDamage` have one.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Weapon {
+    /// Per second
+    pub damage: f32,
+    pub range: f32,
+}
 
-#[derive(Eq, Hash, PartialEq, Clone)]
+#[derive(Eq, Hash, PartialEq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub enum UnitType {
     Cruiser,
     Spacestation,
@@ -79,6 +173,7 @@ pub struct UnitSpecification {
     pub base_stats: UnitStats,
     pub unit_info: String,
     pub unit_cost: HashMap<ResourceType, f32>,
+    pub build_time: f32,
 }
 pub struct InstanceSpawner;
 #[derive(Event)]
@@ -86,8 +181,15 @@ pub struct InstanceSpawnRequest {
     pub location: Vec3,
     pub unit_type: UnitType,
     pub civilisation: Civilisation,
+    pub owner: Entity,
+    /// Build the unit onto this entity instead of a new one, e.g. to keep state restored from a save
+    pub entity: Option<Entity>,
 }
 
+/// How many units the spawner had built before this one. Unlike the entity it doesn't depend on
+/// what else was spawned in between, so it identifies the unit in the state hash.
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SpawnOrder(pub u64);
 #[derive(Component)]
 pub struct UnitInformation {
     pub unit_name: String,
@@ -100,187 +202,208 @@ pub struct UnitInformation {
 }
 impl Plugin for InstanceSpawner {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, populate_units)
-            .add_systems(Update, spawn)
-            .add_event::<InstanceSpawnRequest>();
+        // Right after whatever asked for units in the same tick
+        app.add_event::<InstanceSpawnRequest>()
+            .add_systems(FixedUpdate, spawn.after(advance_production).after(think));
+    }
+}
+/// Gives the units the simulation builds their models, selection circles and minimap markers
+pub struct UnitModels;
+impl Plugin for UnitModels {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, attach_models);
         // .add_systems(Update, update_emissiveness.before(spawn));
         // populate_units(app);
     }
 }
-fn populate_units(mut commands: Commands) {
-    let mut unit_specifications: UnitSpecifications = UnitSpecifications {
-        unit_specifications: HashMap::new(),
-    };
-    // todo!("Read from file");
-    unit_specifications.unit_specifications.insert(
-        (Civilisation::Greek, UnitType::Cruiser),
-        UnitSpecification {
-            file_path: "./assets/3d_models/units/greek/cruiser/greek_cruiser.gltf".into(),
-            scene: "Scene0".to_owned(),
-            icon_path: "./3d_models/units/greek/cruiser/greek_cruiser_thumbnail.png".into(),
-            unit_name: "Andreia Class Cruiser".into(),
-            movable: true,
-            shape: ShapeTypeSerializable(ShapeType::Capsule),
-            dimensions: Vec3 {
-                x: 1.0,
-                y: 1.0,
-                z: 2.0,
-            },
-            prescaling: 0.1,
-            base_stats: UnitStats(Vec::new()),
-            unit_info: "The basic cruiser type used by the Greek Empire".into(),
-            unit_cost: HashMap::from_iter(vec![(ResourceType::Plotanium, 22.0)]),
-        },
-    );
-    unit_specifications.unit_specifications.insert(
-        (Civilisation::Greek, UnitType::MiningStation),
-        UnitSpecification {
-            file_path: "./assets/3d_models/units/greek/mining_rig/mining_rig.gltf".into(),
-            scene: "Scene0".to_owned(),
-            icon_path: "./3d_models/units/greek/mining_rig/mining_rig_thumbnail.png".into(),
-            unit_name: "Hephaestus Mining Station".into(),
-            movable: true,
-            shape: ShapeTypeSerializable(ShapeType::Capsule),
-            dimensions: Vec3 {
-                x: 1.0,
-                y: 1.0,
-                z: 2.0,
-            },
-            prescaling: 0.05,
-            base_stats: UnitStats(vec![
-                UnitStat::MaxMiningDist(1.5),
-                UnitStat::BaseMiningRate(24.0),
-                UnitStat::BonusMiningRate((ResourceType::Plotanium, 5.0)),
-            ]),
-            unit_info: "The mining station used by most empires.".into(),
-            unit_cost: HashMap::from_iter(vec![(ResourceType::Plotanium, 22.0)]),
-        },
-    );
-    unit_specifications.unit_specifications.insert(
-        (Civilisation::Greek, UnitType::Spacestation),
-        UnitSpecification {
-            file_path: "./assets/3d_models/buildings/greek/spacestation.glb".into(),
-            scene: "Scene0".to_owned(),
-            icon_path: "./3d_models/buildings/greek/spacestation_thumbnail.png".into(),
-            unit_name: "Akinetos Space Station".into(),
-            movable: false,
-            shape: ShapeTypeSerializable(ShapeType::Ball),
-            dimensions: Vec3 {
-                x: 50.0,
-                y: 50.0,
-                z: 30.0,
-            },
-            prescaling: 0.02,
-            base_stats: UnitStats(Vec::new()),
-            unit_info: "A mighty spacestation, used to construct ships and defend systems".into(),
-            unit_cost: HashMap::from_iter(vec![(ResourceType::Plotanium, 22.0)]),
-        },
-    );
-
-    commands.insert_resource(unit_specifications);
-}
 #[derive(Component)]
 pub struct EntityWrapper {
     pub entity: Entity,
 }
-fn spawn(
+/// Builds units with everything the simulation needs, the models are attached by `attach_models`
+pub fn spawn(
     mut spawn_requests: EventReader<InstanceSpawnRequest>,
     mut commands: Commands,
     unit_specifications: Res<UnitSpecifications>,
+    player_infos: Query<&PlayerInfo>,
+    tech_tree: Res<TechTree>,
+    civilisation_boni_map: Res<CivilisationBoniMap>,
+    mut spawned: Local<u64>,
+) {
+    for spawn_request in spawn_requests.read() {
+        let Some(unit_specification) = unit_specifications.unit_specifications.get(&(
+            spawn_request.civilisation.clone(),
+            spawn_request.unit_type.clone(),
+        )) else {
+            continue;
+        };
+        let collider: Collider = match unit_specification.shape.0 {
+            ShapeType::Ball => Collider::ball(unit_specification.dimensions.max_element()),
+            ShapeType::Capsule => Collider::capsule_z(
+                unit_specification.dimensions.max_element() / 2.0,
+                unit_specification.dimensions.min_element(),
+            ),
+            shape => {
+                println!("Shape {:?} not supported", shape);
+                continue;
+            }
+        };
+        let stats: UnitStats = unit_stats(
+            unit_specification,
+            spawn_request,
+            &civilisation_boni_map,
+            &player_infos,
+            &tech_tree,
+        );
+        let mut unit = match spawn_request.entity {
+            Some(entity) => commands.entity(entity),
+            None => commands.spawn_empty(),
+        };
+        insert_simulated_unit(&mut unit, unit_specification, spawn_request, stats);
+        unit.insert((
+            TransformBundle::from_transform(
+                Transform::from_translation(spawn_request.location)
+                    .with_scale(Vec3::splat(unit_specification.prescaling)),
+            ),
+            RigidBody::KinematicPositionBased,
+            collider,
+            GravityScale(0.0),
+            SpawnOrder(*spawned),
+        ));
+        *spawned += 1;
+    }
+}
+/// Everything of the units built since the last frame that is only there to be seen
+fn attach_models(
+    mut commands: Commands,
+    units: Query<(Entity, &Owner, &UnitInformation), Added<UnitInformation>>,
+    unit_specifications: Res<UnitSpecifications>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
+    player_colours: Query<&PlayerColour>,
 ) {
-    for spawn_request in spawn_requests.read() {
-        if let Some(unit_specification) = unit_specifications
-            .unit_specifications
-            .get(&(spawn_request.civilisation, spawn_request.unit_type.clone()))
-        {
-            let texture_handle = asset_server.load("textures/selection_texture.png");
-            let material_handle = materials.add(StandardMaterial {
-                base_color_texture: Some(texture_handle),
-                alpha_mode: AlphaMode::Blend,
-                ..default()
-            });
-            let collider: Collider = match unit_specification.shape.0 {
-                ShapeType::Ball => Collider::ball(unit_specification.dimensions.max_element()),
-                ShapeType::Capsule => Collider::capsule_z(
-                    unit_specification.dimensions.max_element() / 2.0,
-                    unit_specification.dimensions.min_element(),
-                ),
-                shape => {
-                    println!("Shape {:?} not supported", shape);
-                    continue;
-                }
-            };
-            let parent_id = commands
-                .spawn((
-                    SceneBundle {
-                        transform: Transform::from_xyz(
-                            spawn_request.location.x,
-                            spawn_request.location.y,
-                            spawn_request.location.z,
-                        )
-                        .with_scale(Vec3::splat(unit_specification.prescaling)),
-                        scene: asset_server.load(
-                            unit_specification
-                                .file_path
-                                .clone()
-                                .replace("./assets/", "")
-                                + "#"
-                                + &unit_specification.scene,
-                        ),
+    for (entity, owner, unit_information) in units.iter() {
+        let Some(unit_specification) = unit_specifications.unit_specifications.get(&(
+            unit_information.civilisation.clone(),
+            unit_information.unit_type.clone(),
+        )) else {
+            continue;
+        };
+        let texture_handle = asset_server.load("textures/selection_texture.png");
+        let marker_colour: Color = player_colours
+            .get(owner.player)
+            .map_or(NEUTRAL_MARKER, |colour| colour.0);
+        let material_handle = materials.add(StandardMaterial {
+            base_color_texture: Some(texture_handle),
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+        let scene: Handle<Scene> = asset_server.load(
+            unit_specification
+                .file_path
+                .clone()
+                .replace("./assets/", "")
+                + "#"
+                + &unit_specification.scene,
+        );
+        commands
+            .entity(entity)
+            .insert((
+                scene,
+                VisibilityBundle::default(),
+                Selectable {},
+                RenderLayers::layer(RenderLayerMap::Main as usize),
+                // ContextMenuActions {},
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    MaterialMeshBundle {
+                        mesh: meshes.add(Plane3d::default().mesh().size(
+                            2.5 * unit_specification.dimensions.max_element(),
+                            2.5 * unit_specification.dimensions.max_element(),
+                        )),
+                        material: material_handle,
+                        transform: Transform::from_scale(Vec3::splat(1.0)),
+                        visibility: Visibility::Hidden,
                         ..default()
                     },
-                    Selectable {},
-                    UnitInformation {
-                        unit_name: unit_specification.unit_name.clone(),
-                        unit_type: spawn_request.unit_type.clone(),
-                        civilisation: spawn_request.civilisation,
-                        thumbnail: unit_specification.icon_path.clone(),
-                        stats: unit_specification.base_stats.clone(),
-                        unit_info: unit_specification.unit_info.clone(),
-                        unit_cost: unit_specification.unit_cost.clone(),
-                    },
-                    RigidBody::KinematicPositionBased,
-                    collider,
-                    GravityScale(0.0),
+                    SelectionCircle,
                     RenderLayers::layer(RenderLayerMap::Main as usize),
-                    // ContextMenuActions {},
-                ))
-                .with_children(|parent| {
-                    parent.spawn((
-                        MaterialMeshBundle {
-                            mesh: meshes.add(Plane3d::default().mesh().size(
-                                2.5 * unit_specification.dimensions.max_element(),
-                                2.5 * unit_specification.dimensions.max_element(),
-                            )),
-                            material: material_handle,
-                            transform: Transform::from_scale(Vec3::splat(1.0)),
-                            visibility: Visibility::Hidden,
-                            ..default()
-                        },
-                        SelectionCircle,
-                        RenderLayers::layer(RenderLayerMap::Main as usize),
-                    ));
-                    parent.spawn((
-                        MaterialMeshBundle {
-                            mesh: meshes.add(Plane3d::default().mesh().size(10.0, 10.0)),
-                            material: materials.add(StandardMaterial {
-                                base_color: Color::srgba(0.0, 1.0, 0.0, 0.5),
-                                ..Default::default()
-                            }),
-                            ..default()
-                        },
-                        RenderLayers::layer(RenderLayerMap::Minimap as usize),
-                    ));
-                })
-                .id();
-
-            if unit_specification.movable {
-                commands.entity(parent_id).insert(Movable {});
+                ));
+                parent.spawn((
+                    MaterialMeshBundle {
+                        mesh: meshes.add(Plane3d::default().mesh().size(10.0, 10.0)),
+                        material: materials.add(StandardMaterial {
+                            base_color: marker_colour,
+                            unlit: true,
+                            ..Default::default()
+                        }),
+                        ..default()
+                    },
+                    MinimapMarker,
+                    RenderLayers::layer(RenderLayerMap::Minimap as usize),
+                ));
+            });
+    }
+}
+/// The specification's stats with the boni of the civilisation and the owner's research on top
+fn unit_stats(
+    unit_specification: &UnitSpecification,
+    spawn_request: &InstanceSpawnRequest,
+    civilisation_boni_map: &CivilisationBoniMap,
+    player_infos: &Query<&PlayerInfo>,
+    tech_tree: &TechTree,
+) -> UnitStats {
+    let mut stats: UnitStats = unit_specification.base_stats.clone();
+    if let Some(civilisation_boni) = civilisation_boni_map.map.get(&spawn_request.civilisation) {
+        for (unit_type, modifier) in &civilisation_boni.combat_boni.stat_modifiers {
+            if *unit_type == spawn_request.unit_type {
+                modifier.apply(&mut stats);
             }
         }
-        // commands.entity(entity).remove::<InstanceSpawnRequest>();
     }
+    if let Ok(player_info) = player_infos.get(spawn_request.owner) {
+        for modifier in tech_tree.stat_modifiers(player_info, &spawn_request.unit_type) {
+            modifier.apply(&mut stats);
+        }
+    }
+    stats
+}
+/// Everything of a unit that the simulation works with
+fn insert_simulated_unit(
+    unit: &mut EntityCommands,
+    unit_specification: &UnitSpecification,
+    spawn_request: &InstanceSpawnRequest,
+    stats: UnitStats,
+) {
+    let max_shield: f32 = stats.max_shield();
+    if max_shield > 0.0 {
+        unit.insert(Shield {
+            current: max_shield,
+            max: max_shield,
+        });
+    }
+    if let Some(weapon) = stats.weapon() {
+        unit.insert(weapon);
+    }
+    if unit_specification.movable {
+        unit.insert(Movable {});
+    }
+    unit.insert((
+        Owner {
+            player: spawn_request.owner,
+        },
+        Health::full(stats.max_health()),
+        UnitInformation {
+            unit_name: unit_specification.unit_name.clone(),
+            unit_type: spawn_request.unit_type.clone(),
+            civilisation: spawn_request.civilisation.clone(),
+            thumbnail: unit_specification.icon_path.clone(),
+            stats,
+            unit_info: unit_specification.unit_info.clone(),
+            unit_cost: unit_specification.unit_cost.clone(),
+        },
+        ProductionQueue::default(),
+    ));
 }
@@ -1,25 +1,29 @@
 use std::process;
 
-use crate::ownable::{Selectable, Selected};
-use crate::player_controller::{ContextMenuAction, LocalPlayer, PlayerInfo};
-use crate::player_controller::{DeselectEvent, RayHit, RenderLayerMap};
+use crate::abilities::UnitActionRequest;
+use crate::civilisation::{Civilisations, ContextMenuEntry};
+use crate::environment::MovementGrid;
+use crate::hazards::{HazardKind, InHazard};
+use crate::key_bindings::{InputAction, InputActions, KeyBindings};
+use crate::minimap::{initialise_mini_map, MinimapSettings};
+use crate::ownable::{Owner, Selected};
+use crate::player_controller::{Alert, ContextMenuAction, LocalPlayer, PlayerInfo};
+use crate::production::{production_cost, ProductionQueue, ProductionRequest};
+use crate::research::TechTree;
 use crate::resources::{ResourceStockpiles, ResourceType};
-use crate::spawner::{
-    InstanceSpawnRequest, UnitInformation, UnitSpecification, UnitSpecifications,
-};
-use bevy::core_pipeline::Skybox;
+use crate::selection::{SelectionMode, SelectionRequest};
+use crate::spawner::{Health, UnitInformation, UnitSpecifications, UnitType};
 use bevy::diagnostic::DiagnosticsStore;
-use bevy::render::camera::ClearColorConfig;
-use bevy::render::camera::RenderTarget;
-use bevy::render::render_resource::{
-    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-};
-use bevy::render::view::RenderLayers;
 use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
 const ICON_BACKGROUND: Color = Color::srgb(12.0 / 256.0, 11.0 / 256.0, 13.0 / 256.0);
 const NORMAL_BUTTON: Color = Color::WHITE;
 const HOVERED_BUTTON: Color = Color::srgb(64.0 / 256.0, 99.0 / 256.0, 64.0 / 256.0);
 const PRESSED_BUTTON: Color = Color::srgb(75.0 / 256.0, 110.0 / 256.0, 75.0 / 256.0);
+const DISABLED_BUTTON: Color = Color::srgb(0.35, 0.35, 0.35);
+const HEALTH_HIGH: Color = Color::srgb(0.2, 0.8, 0.2);
+const HEALTH_MEDIUM: Color = Color::srgb(0.9, 0.75, 0.1);
+const HEALTH_LOW: Color = Color::srgb(0.85, 0.15, 0.1);
+const PORTRAIT_SIZE: f32 = 40.0;
 const MAIN_UI_BACKGROUND: Color = Color::srgba(
     0x81 as f32 / 256.0,
     0xC1 as f32 / 256.0,
@@ -27,6 +31,13 @@ const MAIN_UI_BACKGROUND: Color = Color::srgba(
     0xF0 as f32 / 256.0,
 );
 const MAIN_UI_TEXT: Color = Color::srgb(12.0 / 256.0, 11.0 / 256.0, 13.0 / 256.0);
+const HAZARD_WARNING_TEXT: Color = Color::srgb(1.0, 0.45, 0.1);
+const ALERT_TEXT: Color = Color::srgb(0.95, 0.85, 0.3);
+/// Seconds an alert stays on screen
+const ALERT_DURATION: f64 = 6.0;
+const MAX_ALERT_LINES: usize = 4;
+/// Seconds within which self-destruct has to be triggered a second time to go through
+const SELF_DESTRUCT_CONFIRMATION: f64 = 3.0;
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 enum UIType {
     MapUI,
@@ -44,103 +55,61 @@ enum UIContent {
 pub struct GameUI;
 impl Plugin for GameUI {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, game_overlay)
-            .add_systems(
-                Update,
-                (
-                    update_fps,
-                    populate_lower_ui,
-                    clear_ui.before(populate_lower_ui),
-                    catch_interaction,
-                    button_system,
-                    update_resources,
-                ),
-            )
-            .add_event::<RayHit>()
-            .add_event::<DeselectEvent>()
-            .add_plugins(FrameTimeDiagnosticsPlugin);
+        app.add_systems(
+            Startup,
+            (game_overlay, spawn_hazard_warning, spawn_alert_feed),
+        )
+        .add_systems(
+            Update,
+            (
+                update_fps,
+                populate_lower_ui,
+                selection_portrait_clicked,
+                update_health_bars,
+                catch_interaction,
+                button_system,
+                context_menu_hotkeys,
+                perform_context_menu_action
+                    .after(button_system)
+                    .after(context_menu_hotkeys),
+                refresh_context_menu,
+                update_resources,
+                update_hazard_warning,
+                show_alerts,
+            ),
+        )
+        .add_event::<ContextMenuTriggered>()
+        .add_plugins(FrameTimeDiagnosticsPlugin);
     }
 }
 #[derive(Component)]
 pub struct RayBlock;
-fn initialise_mini_map(
-    commands: &mut Commands,
-    mut images: ResMut<Assets<Image>>,
-    asset_server: &Res<AssetServer>,
-) -> Entity {
-    let size = Extent3d {
-        width: 256,
-        height: 256,
-        ..default()
-    };
-    let mut image = Image {
-        texture_descriptor: TextureDescriptor {
-            label: None,
-            size,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Bgra8UnormSrgb,
-            mip_level_count: 1,
-            sample_count: 1,
-            usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::COPY_DST
-                | TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        },
-        ..default()
-    };
-    image.resize(size);
-    let image_handle = images.add(image);
-
-    let skybox_handle: Handle<Image> = asset_server.load("textures/skybox/stacked.png");
-
-    commands.spawn((
-        Camera3dBundle {
-            camera_3d: Camera3d { ..default() },
-            camera: Camera {
-                clear_color: ClearColorConfig::Custom(Color::WHITE),
-                // render before the "main pass" camera
-                order: -1,
-                target: RenderTarget::Image(image_handle.clone()),
-                ..default()
-            },
-
-            transform: Transform::from_translation(Vec3::new(0.0, 200.0, 0.0))
-                .looking_at(Vec3::ZERO, Vec3::Z),
-            ..default()
-        },
-        Skybox {
-            image: skybox_handle.clone(),
-            brightness: 1000.0,
-        }, // UiCameraConfig { show_ui: false },
-        RenderLayers::from_layers(&[
-            RenderLayerMap::General as usize,
-            RenderLayerMap::Minimap as usize,
-        ]),
-    ));
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(65.0),
-                height: Val::Percent(80.0),
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::Center,
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent.spawn(ImageBundle {
-                image: UiImage::from(image_handle),
-                style: Style {
-                    width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
-                    ..Default::default()
-                },
-                ..default()
-            });
-        })
-        .id()
+/// State of a context menu button, kept up to date by `refresh_context_menu`
+#[derive(Component)]
+struct ContextMenuButton {
+    /// The selected unit types offering this entry
+    producers: Vec<UnitType>,
+    hotkey: Option<InputAction>,
+    disabled: bool,
+    reason_text: Entity,
+}
+/// A unit's thumbnail in the selection panel
+#[derive(Component)]
+struct SelectionPortrait {
+    unit: Entity,
+    unit_type: UnitType,
 }
+/// Filled part of a unit's health bar, its width follows the unit's health
+#[derive(Component)]
+struct HealthBar {
+    unit: Entity,
+}
+/// Shown while selected units are inside a hazard
+#[derive(Component)]
+struct HazardWarning;
+/// A context menu entry was clicked or its hotkey pressed
+#[derive(Event)]
+struct ContextMenuTriggered(ContextMenuAction);
 fn create_ui_segment(
     commands: &mut Commands,
     style: Style,
@@ -214,10 +183,17 @@ fn game_overlay(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     images: ResMut<Assets<Image>>,
+    movement_grid: Res<MovementGrid>,
+    minimap_settings: Res<MinimapSettings>,
 ) {
     let map_ui_content: Vec<Entity> = vec![
         // commands.spawn(NodeBundle::default()).id(),
-        initialise_mini_map(&mut commands, images, &asset_server),
+        initialise_mini_map(
+            &mut commands,
+            images,
+            &movement_grid.settings,
+            &minimap_settings,
+        ),
         // commands.spawn(NodeBundle::default()).id(),
     ];
     let default_column_style: Style = Style {
@@ -534,9 +510,46 @@ fn game_overlay(
                 .push_children(&lower_ui_elements);
         });
 }
+fn health_colour(health: &Health) -> Color {
+    match health.fraction() {
+        f if f > 0.6 => HEALTH_HIGH,
+        f if f > 0.3 => HEALTH_MEDIUM,
+        _ => HEALTH_LOW,
+    }
+}
+fn spawn_health_bar(commands: &mut Commands, unit: Entity, health: &Health) -> Entity {
+    let fill = commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(health.fraction() * 100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                background_color: health_colour(health).into(),
+                ..default()
+            },
+            HealthBar { unit },
+        ))
+        .id();
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Px(4.0),
+                ..default()
+            },
+            background_color: ICON_BACKGROUND.into(),
+            ..default()
+        })
+        .add_child(fill)
+        .id()
+}
 fn update_selection_info(
     commands: &mut Commands,
+    unit: Entity,
     unit_information: &UnitInformation,
+    health: &Health,
     asset_server: &Res<AssetServer>,
     selection_info_content: Entity,
 ) {
@@ -559,7 +572,8 @@ fn update_selection_info(
         .spawn(NodeBundle {
             style: Style {
                 width: Val::Px(60.0),
-                height: Val::Px(60.0),
+                height: Val::Px(64.0),
+                flex_direction: FlexDirection::Column,
                 ..default()
             },
             background_color: ICON_BACKGROUND.into(),
@@ -568,8 +582,8 @@ fn update_selection_info(
         .with_children(|parent| {
             parent.spawn(ImageBundle {
                 style: Style {
-                    width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
+                    width: Val::Px(60.0),
+                    height: Val::Px(60.0),
                     ..Default::default()
                 },
                 image: UiImage {
@@ -580,6 +594,8 @@ fn update_selection_info(
             });
         })
         .id();
+    let health_bar: Entity = spawn_health_bar(commands, unit, health);
+    commands.entity(thumbnail).add_child(health_bar);
     let container = commands
         .spawn(NodeBundle {
             style: Style {
@@ -596,6 +612,93 @@ fn update_selection_info(
         .id();
     commands.entity(selection_info_content).add_child(container);
 }
+/// Portraits of every selected unit, grouped by unit type
+fn update_selection_grid(
+    commands: &mut Commands,
+    selection: &[(Entity, &UnitInformation, &Owner, &Health)],
+    asset_server: &Res<AssetServer>,
+    selection_info_content: Entity,
+) {
+    let mut groups: Vec<Entity> = Vec::new();
+    for group in selection.chunk_by(|(_, a, _, _), (_, b, _, _)| a.unit_type == b.unit_type) {
+        let mut portraits: Vec<Entity> = Vec::new();
+        for (unit, unit_information, _, health) in group {
+            let health_bar: Entity = spawn_health_bar(commands, *unit, health);
+            let portrait: Entity = commands
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(PORTRAIT_SIZE),
+                            height: Val::Px(PORTRAIT_SIZE),
+                            margin: UiRect::all(Val::Px(1.0)),
+                            flex_direction: FlexDirection::Column,
+                            justify_content: JustifyContent::End,
+                            ..default()
+                        },
+                        image: UiImage {
+                            texture: asset_server.load(&unit_information.thumbnail),
+                            ..default()
+                        },
+                        background_color: ICON_BACKGROUND.into(),
+                        ..default()
+                    },
+                    SelectionPortrait {
+                        unit: *unit,
+                        unit_type: unit_information.unit_type.clone(),
+                    },
+                ))
+                .add_child(health_bar)
+                .id();
+            portraits.push(portrait);
+        }
+        let label: Entity = commands
+            .spawn(small_text(
+                asset_server,
+                format!("{} x{}", group[0].1.unit_type, group.len()),
+            ))
+            .id();
+        let row: Entity = commands
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    flex_wrap: FlexWrap::Wrap,
+                    ..default()
+                },
+                ..default()
+            })
+            .push_children(&portraits)
+            .id();
+        groups.push(
+            commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        margin: UiRect::right(Val::Px(6.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .push_children(&[label, row])
+                .id(),
+        );
+    }
+    let container = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Start,
+                flex_direction: FlexDirection::Row,
+                flex_wrap: FlexWrap::Wrap,
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            ..default()
+        })
+        .push_children(&groups)
+        .id();
+    commands.entity(selection_info_content).add_child(container);
+}
 fn catch_interaction(
     mut commands: Commands,
     mut interaction_query: Query<&Interaction, Changed<Interaction>>,
@@ -620,191 +723,600 @@ fn button_system(
         (
             &Interaction,
             &ContextMenuAction,
+            &ContextMenuButton,
             &mut BackgroundColor,
             &mut BorderColor,
         ),
         (Changed<Interaction>, With<Button>),
     >,
-    player_info: Query<&PlayerInfo, With<LocalPlayer>>,
-    selected_entities: Query<&Transform, With<Selected>>,
-    mut spawn_events: EventWriter<InstanceSpawnRequest>,
+    mut triggered_actions: EventWriter<ContextMenuTriggered>,
 ) {
-    if let Ok(player_info) = player_info.get_single() {
-        for transform in selected_entities.iter() {
-            for (interaction, action, mut background_color, mut border_color) in
-                &mut interaction_query
-            {
-                match *interaction {
-                    Interaction::Pressed => {
-                        match action {
-                            ContextMenuAction::Build(unit_type) => {
-                                spawn_events.send(InstanceSpawnRequest {
-                                    location: Vec3 {
-                                        x: transform.translation.x + 2.0,
-                                        y: 2.0,
-                                        z: transform.translation.z + 1.0,
-                                    },
-                                    unit_type: unit_type.clone(),
-                                    civilisation: player_info.civilisation,
-                                });
-                            }
-                        };
-                        *background_color = PRESSED_BUTTON.into();
-                        border_color.0 = Color::BLACK;
-                    }
-                    Interaction::Hovered => {
-                        border_color.0 = Color::BLACK;
-                        *background_color = HOVERED_BUTTON.into();
-                    }
-                    Interaction::None => {
-                        border_color.0 = Color::BLACK;
-                        *background_color = NORMAL_BUTTON.into();
+    for (interaction, action, button, mut background_color, mut border_color) in
+        &mut interaction_query
+    {
+        match *interaction {
+            Interaction::Pressed => {
+                if !button.disabled {
+                    triggered_actions.send(ContextMenuTriggered(action.clone()));
+                }
+                *background_color = PRESSED_BUTTON.into();
+                border_color.0 = Color::BLACK;
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::BLACK;
+                *background_color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                border_color.0 = Color::BLACK;
+                *background_color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+fn context_menu_hotkeys(
+    actions: InputActions,
+    buttons: Query<(&ContextMenuAction, &ContextMenuButton)>,
+    mut triggered_actions: EventWriter<ContextMenuTriggered>,
+) {
+    for (action, button) in buttons.iter() {
+        if button
+            .hotkey
+            .is_some_and(|hotkey| actions.just_pressed(hotkey))
+            && !button.disabled
+        {
+            triggered_actions.send(ContextMenuTriggered(action.clone()));
+        }
+    }
+}
+fn perform_context_menu_action(
+    mut triggered_actions: EventReader<ContextMenuTriggered>,
+    player_info: Query<(Entity, &PlayerInfo), With<LocalPlayer>>,
+    selected_entities: Query<
+        (
+            Entity,
+            &Transform,
+            &UnitInformation,
+            &Owner,
+            &ProductionQueue,
+        ),
+        With<Selected>,
+    >,
+    mut production_requests: EventWriter<ProductionRequest>,
+    mut unit_action_requests: EventWriter<UnitActionRequest>,
+    tech_tree: Res<TechTree>,
+    time: Res<Time>,
+    mut alerts: EventWriter<Alert>,
+    // When self-destruct was last triggered without going through
+    mut self_destruct_armed: Local<Option<f64>>,
+) {
+    let Ok((player_entity, player_info)) = player_info.get_single() else {
+        return;
+    };
+    for ContextMenuTriggered(action) in triggered_actions.read() {
+        let able: Vec<_> = selected_entities
+            .iter()
+            .filter(|(_, _, unit_information, owner, _)| {
+                owner.player == player_entity
+                    && tech_tree
+                        .lock_reason(action, &unit_information.unit_type, player_info)
+                        .is_none()
+            })
+            .collect();
+        match action {
+            // The least busy of the selected producers takes the order
+            ContextMenuAction::Build(_) | ContextMenuAction::Research(_) => {
+                if let Some((producer, ..)) =
+                    able.iter().min_by_key(|(.., queue)| queue.items.len())
+                {
+                    production_requests.send(ProductionRequest {
+                        producer: *producer,
+                        player: player_entity,
+                        action: action.clone(),
+                    });
+                }
+            }
+            ContextMenuAction::Ability(_) | ContextMenuAction::SelfDestruct => {
+                // Destroyed units are gone for good, so it takes a second press to confirm
+                if *action == ContextMenuAction::SelfDestruct {
+                    let now: f64 = time.elapsed_seconds_f64();
+                    let confirmed: bool = self_destruct_armed
+                        .is_some_and(|armed| now - armed <= SELF_DESTRUCT_CONFIRMATION);
+                    *self_destruct_armed = (!confirmed).then_some(now);
+                    if !confirmed {
+                        if let Some((_, transform, ..)) = able.first() {
+                            alerts.send(Alert {
+                                player: player_entity,
+                                position: transform.translation,
+                                message: "Trigger self-destruct again to confirm".into(),
+                            });
+                        }
+                        continue;
                     }
                 }
+                for (unit, ..) in able {
+                    unit_action_requests.send(UnitActionRequest {
+                        unit,
+                        player: player_entity,
+                        action: action.clone(),
+                    });
+                }
             }
         }
     }
 }
+/// Greys out entries the local player can't use right now and tells them why
+fn refresh_context_menu(
+    mut buttons: Query<(&ContextMenuAction, &mut ContextMenuButton, &mut UiImage)>,
+    mut texts: Query<&mut Text>,
+    player_info: Query<(&PlayerInfo, &ResourceStockpiles), With<LocalPlayer>>,
+    unit_specifications: Res<UnitSpecifications>,
+    tech_tree: Res<TechTree>,
+) {
+    let Ok((player_info, stockpiles)) = player_info.get_single() else {
+        return;
+    };
+    for (action, mut button, mut image) in buttons.iter_mut() {
+        // Usable as long as one of the selected unit types may use it
+        let lock_reasons: Vec<Option<String>> = button
+            .producers
+            .iter()
+            .map(|producer| tech_tree.lock_reason(action, producer, player_info))
+            .collect();
+        let lock_reason: Option<String> = if lock_reasons.iter().any(Option::is_none) {
+            None
+        } else {
+            lock_reasons.into_iter().flatten().next()
+        };
+        let reason: Option<String> = lock_reason.or_else(|| {
+            production_cost(
+                action,
+                &player_info.civilisation,
+                &unit_specifications,
+                &tech_tree,
+            )
+            .filter(|(cost, _)| !stockpiles.can_afford(*cost))
+            .map(|_| "Not enough resources".to_owned())
+        });
+        button.disabled = reason.is_some();
+        image.color = if button.disabled {
+            DISABLED_BUTTON
+        } else {
+            Color::WHITE
+        };
+        let reason: String = reason.unwrap_or_default();
+        if let Ok(mut text) = texts.get_mut(button.reason_text) {
+            if text.sections[0].value != reason {
+                text.sections[0].value = reason;
+            }
+        }
+    }
+}
+/// Self-destruct has an action of its own, other entries go by the key the game data gives them
+fn entry_hotkey(entry: &ContextMenuEntry) -> Option<InputAction> {
+    match entry.action {
+        ContextMenuAction::SelfDestruct => Some(InputAction::SelfDestruct),
+        _ => entry.hotkey.map(InputAction::ContextMenuKey),
+    }
+}
+fn hotkey_label(key_bindings: &KeyBindings, hotkey: InputAction) -> String {
+    key_bindings
+        .get(hotkey)
+        .iter()
+        .map(|binding| binding.to_string())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+fn small_text(asset_server: &Res<AssetServer>, value: String) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font: asset_server.load("fonts/android-insomnia-font/AndroidInsomniaRegular.ttf"),
+            font_size: 10.0,
+            color: MAIN_UI_TEXT,
+        },
+    )
+}
 
+/// The context menu of a unit type as laid out for the civilisation, plus anything granted on top
+fn context_menu_entries(
+    producer: &UnitType,
+    civilisations: &Civilisations,
+    tech_tree: &TechTree,
+    player_info: &PlayerInfo,
+) -> Vec<ContextMenuEntry> {
+    let mut entries: Vec<ContextMenuEntry> = civilisations
+        .context_menu(&player_info.civilisation, producer)
+        .to_vec();
+    // Actions that were granted without being listed for the civilisation still get a button
+    let locked_actions: Vec<ContextMenuAction> = tech_tree.locked_actions(player_info, producer);
+    for action in player_info
+        .context_menu_actions
+        .get(producer)
+        .into_iter()
+        .flatten()
+        .chain(locked_actions.iter())
+    {
+        if !entries.iter().any(|entry| entry.action == *action) {
+            entries.push(ContextMenuEntry {
+                action: action.clone(),
+                hotkey: None,
+                icon_path: None,
+            });
+        }
+    }
+    entries
+}
 fn update_context_menu(
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
+    key_bindings: &KeyBindings,
     context_menu_content: Entity,
-    context_menu_actions: &Vec<ContextMenuAction>,
+    producers: &[UnitType],
+    civilisations: &Res<Civilisations>,
     unit_specifications: &Res<UnitSpecifications>,
+    tech_tree: &Res<TechTree>,
     player_info: &PlayerInfo,
 ) {
-    // let mut buttons: Vec<Entity> = Vec::new();
-    // for action in context_menu_actions {
-    //     match action {
-    //         ContextMenuAction::Build(unit_type) => {
-    //             let unit_information: &UnitSpecification = &unit_specifications.unit_specifications
-    //                 [&(player_info.civilisation, unit_type.clone())];
-    //             buttons.push(
-    //                 commands
-    //                     .spawn(NodeBundle {
-    //                         style: Style {
-    //                             width: Val::Px(70.0),
-    //                             height: Val::Px(70.0),
-    //                             flex_direction: FlexDirection::ColumnReverse,
-    //                             ..default()
-    //                         },
-    //                         // background_color: ICON_BACKGROUND.into(),
-    //                         ..default()
-    //                     })
-    //                     .with_children(|parent| {
-    //                         parent.spawn((
-    //                             ButtonBundle {
-    //                                 style: Style {
-    //                                     width: Val::Px(65.0),
-    //                                     height: Val::Px(65.0),
-    //                                     justify_content: JustifyContent::Center,
-    //                                     align_items: AlignItems::Center,
-    //                                     border: UiRect::percent(5.0, 5.0, 5.0, 5.0),
-    //                                     ..default()
-    //                                 },
-    //                                 image: UiImage {
-    //                                     texture: asset_server.load(&unit_information.icon_path),
-    //                                     ..default()
-    //                                 },
-    //                                 background_color: NORMAL_BUTTON.into(),
-    //                                 border_color: Color::BLACK.into(),
-    //                                 ..default()
-    //                             },
-    //                             action.clone(),
-    //                         ));
-    //                     })
-    //                     .id(),
-    //             );
-    //             let container = commands
-    //                 .spawn(NodeBundle {
-    //                     style: Style {
-    //                         width: Val::Percent(80.0),
-    //                         height: Val::Percent(80.0),
-    //                         align_items: AlignItems::Start,
-    //                         justify_content: JustifyContent::Start,
-    //                         flex_direction: FlexDirection::Row,
-    //                         ..default()
-    //                     },
-    //                     ..default()
-    //                 })
-    //                 .push_children(&buttons)
-    //                 .id();
-    //             commands.entity(context_menu_content).add_child(container);
-    //         }
-    //     }
-    // }
+    let Some((first, others)) = producers.split_first() else {
+        return;
+    };
+    let others: Vec<Vec<ContextMenuEntry>> = others
+        .iter()
+        .map(|producer| context_menu_entries(producer, civilisations, tech_tree, player_info))
+        .collect();
+    // With several unit types selected only the actions they have in common are offered
+    let entries: Vec<ContextMenuEntry> =
+        context_menu_entries(first, civilisations, tech_tree, player_info)
+            .into_iter()
+            .filter(|entry| {
+                others
+                    .iter()
+                    .all(|other| other.iter().any(|o| o.action == entry.action))
+            })
+            .collect();
+    let mut buttons: Vec<Entity> = Vec::new();
+    for entry in entries {
+        let icon_path: Option<&str> = entry.icon_path.as_deref().or(match &entry.action {
+            ContextMenuAction::Build(unit_type) => unit_specifications
+                .unit_specifications
+                .get(&(player_info.civilisation.clone(), unit_type.clone()))
+                .map(|unit_specification| unit_specification.icon_path.as_str()),
+            ContextMenuAction::Research(tech) => tech_tree
+                .techs
+                .get(tech)
+                .map(|tech| tech.icon_path.as_str()),
+            ContextMenuAction::Ability(_) | ContextMenuAction::SelfDestruct => None,
+        });
+        let Some(icon_path) = icon_path else {
+            println!("No icon for {:?}", entry.action);
+            continue;
+        };
+        let cost: String = production_cost(
+            &entry.action,
+            &player_info.civilisation,
+            unit_specifications,
+            tech_tree,
+        )
+        .map(|(cost, _)| {
+            cost.iter()
+                .map(|(resource_type, amount)| format!("{} {:?}", amount.ceil(), resource_type))
+                .collect::<Vec<String>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+        let reason_text: Entity = commands.spawn(small_text(asset_server, String::new())).id();
+        let cost_text: Entity = commands.spawn(small_text(asset_server, cost)).id();
+        let button: Entity = commands
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(65.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Start,
+                        align_items: AlignItems::Start,
+                        border: UiRect::percent(5.0, 5.0, 5.0, 5.0),
+                        ..default()
+                    },
+                    image: UiImage {
+                        texture: asset_server.load(icon_path.to_string()),
+                        ..default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    border_color: Color::BLACK.into(),
+                    ..default()
+                },
+                entry.action.clone(),
+                ContextMenuButton {
+                    producers: producers.to_vec(),
+                    hotkey: entry_hotkey(&entry),
+                    disabled: false,
+                    reason_text,
+                },
+            ))
+            .with_children(|parent| {
+                if let Some(hotkey) = entry_hotkey(&entry) {
+                    parent.spawn(small_text(asset_server, hotkey_label(key_bindings, hotkey)));
+                }
+            })
+            .id();
+        buttons.push(
+            commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(70.0),
+                        height: Val::Px(100.0),
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .push_children(&[button, cost_text, reason_text])
+                .id(),
+        );
+    }
+    let container = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(80.0),
+                height: Val::Percent(80.0),
+                align_items: AlignItems::Start,
+                justify_content: JustifyContent::Start,
+                flex_direction: FlexDirection::Row,
+                flex_wrap: FlexWrap::Wrap,
+                ..default()
+            },
+            ..default()
+        })
+        .push_children(&buttons)
+        .id();
+    commands.entity(context_menu_content).add_child(container);
 }
+/// Rebuilds the selection info and context menu whenever the selection or the key bindings
+/// shown on the buttons change
 fn populate_lower_ui(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut ray_hit_event: EventReader<RayHit>,
-    mut unit_info: Query<&UnitInformation, With<Selectable>>,
+    key_bindings: Res<KeyBindings>,
+    newly_selected: Query<(), Added<Selected>>,
+    mut deselected: RemovedComponents<Selected>,
+    selected_units: Query<(Entity, &UnitInformation, &Owner, &Health), With<Selected>>,
     ui_elements: Query<(Entity, &UIContent)>,
-    player_info: Query<&PlayerInfo, With<LocalPlayer>>,
+    player_info: Query<(Entity, &PlayerInfo), With<LocalPlayer>>,
     unit_specifications: Res<UnitSpecifications>,
+    tech_tree: Res<TechTree>,
+    civilisations: Res<Civilisations>,
 ) {
-    if let Ok(player_info) = player_info.get_single() {
-        for hit in ray_hit_event.read() {
-            if hit.mouse_key_enable_mouse {
-                let (selection_info_content, _): (Entity, _) = ui_elements
-                    .into_iter()
-                    .find(|(_, content)| **content == UIContent::Content(UIType::SelectionInfo))
-                    .unwrap();
-                commands
-                    .entity(selection_info_content)
-                    .despawn_descendants();
-                let (context_menu_content, _): (Entity, _) = ui_elements
-                    .into_iter()
-                    .find(|(_, content)| **content == UIContent::Content(UIType::ContextMenu))
-                    .unwrap();
-                commands.entity(context_menu_content).despawn_descendants();
-                if let Ok(unit_information) = unit_info.get_mut(hit.hit_entity) {
-                    update_selection_info(
-                        &mut commands,
-                        unit_information,
-                        &asset_server,
-                        selection_info_content,
-                    );
-                    if let Some(contex_menu_actions) = player_info
-                        .context_menu_actions
-                        .get(&unit_information.unit_type)
-                    {
-                        update_context_menu(
-                            &mut commands,
-                            &asset_server,
-                            context_menu_content,
-                            contex_menu_actions,
-                            &unit_specifications,
-                            player_info,
-                        );
-                    }
-                } else {
-                    commands.entity(selection_info_content).push_children(&[]);
-                }
+    // Every side is always evaluated so the removals are read every frame
+    let changed: bool =
+        !newly_selected.is_empty() | (deselected.read().count() > 0) | key_bindings.is_changed();
+    if !changed {
+        return;
+    }
+    let Ok((player_entity, player_info)) = player_info.get_single() else {
+        return;
+    };
+    let (selection_info_content, _): (Entity, _) = ui_elements
+        .into_iter()
+        .find(|(_, content)| **content == UIContent::Content(UIType::SelectionInfo))
+        .unwrap();
+    commands
+        .entity(selection_info_content)
+        .despawn_descendants();
+    let (context_menu_content, _): (Entity, _) = ui_elements
+        .into_iter()
+        .find(|(_, content)| **content == UIContent::Content(UIType::ContextMenu))
+        .unwrap();
+    commands.entity(context_menu_content).despawn_descendants();
+    let mut selection: Vec<(Entity, &UnitInformation, &Owner, &Health)> =
+        selected_units.iter().collect();
+    selection.sort_by_key(|(entity, unit_information, _, _)| {
+        (unit_information.unit_type.clone(), *entity)
+    });
+    match selection.as_slice() {
+        [] => return,
+        [(unit, unit_information, _, health)] => update_selection_info(
+            &mut commands,
+            *unit,
+            unit_information,
+            health,
+            &asset_server,
+            selection_info_content,
+        ),
+        _ => update_selection_grid(
+            &mut commands,
+            &selection,
+            &asset_server,
+            selection_info_content,
+        ),
+    }
+    let mut producers: Vec<UnitType> = selection
+        .iter()
+        .filter(|(_, _, owner, _)| owner.player == player_entity)
+        .map(|(_, unit_information, _, _)| unit_information.unit_type.clone())
+        .collect();
+    producers.dedup();
+    if !producers.is_empty() {
+        update_context_menu(
+            &mut commands,
+            &asset_server,
+            &key_bindings,
+            context_menu_content,
+            &producers,
+            &civilisations,
+            &unit_specifications,
+            &tech_tree,
+            player_info,
+        );
+    }
+}
+/// Clicking a portrait narrows the selection down to that unit.
+/// While `SelectWholeType` is held all selected units of its type are kept, while
+/// `DropPortraitUnit` is held the unit is dropped instead.
+fn selection_portrait_clicked(
+    actions: InputActions,
+    portraits: Query<(&Interaction, &SelectionPortrait), Changed<Interaction>>,
+    selected_units: Query<(Entity, &UnitInformation), With<Selected>>,
+    mut selection_requests: EventWriter<SelectionRequest>,
+) {
+    for (interaction, portrait) in portraits.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if actions.pressed(InputAction::SelectWholeType) {
+            selection_requests.send(SelectionRequest {
+                entities: selected_units
+                    .iter()
+                    .filter(|(_, unit_information)| {
+                        unit_information.unit_type == portrait.unit_type
+                    })
+                    .map(|(entity, _)| entity)
+                    .collect(),
+                mode: SelectionMode::Replace,
+            });
+        } else if actions.pressed(InputAction::DropPortraitUnit) {
+            selection_requests.send(SelectionRequest {
+                entities: vec![portrait.unit],
+                mode: SelectionMode::Subtract,
+            });
+        } else {
+            selection_requests.send(SelectionRequest {
+                entities: vec![portrait.unit],
+                mode: SelectionMode::Replace,
+            });
+        }
+    }
+}
+fn update_health_bars(
+    mut health_bars: Query<(&HealthBar, &mut Style, &mut BackgroundColor)>,
+    healths: Query<&Health, Changed<Health>>,
+) {
+    for (health_bar, mut style, mut background_color) in health_bars.iter_mut() {
+        if let Ok(health) = healths.get(health_bar.unit) {
+            style.width = Val::Percent(health.fraction() * 100.0);
+            *background_color = health_colour(health).into();
+        }
+    }
+}
+fn spawn_hazard_warning(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/android-insomnia-font/AndroidInsomniaRegular.ttf"),
+                font_size: 18.0,
+                color: HAZARD_WARNING_TEXT,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_text_justify(JustifyText::Center),
+        HazardWarning,
+    ));
+}
+fn update_hazard_warning(
+    selected_units: Query<&InHazard, With<Selected>>,
+    mut warning: Query<&mut Text, With<HazardWarning>>,
+) {
+    let Ok(mut text) = warning.get_single_mut() else {
+        return;
+    };
+    let mut kinds: Vec<HazardKind> = Vec::new();
+    for in_hazard in selected_units.iter() {
+        for kind in &in_hazard.kinds {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
             }
         }
     }
+    let value: String = if kinds.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "Warning: selected units are inside a hazard ({})",
+            kinds
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    };
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+/// Column of the local player's latest alerts, below the hazard warning
+#[derive(Component)]
+struct AlertFeed;
+#[derive(Component)]
+struct AlertLine {
+    shown_at: f64,
 }
-// remove all children
-fn clear_ui(
+fn spawn_alert_feed(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(64.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        },
+        AlertFeed,
+    ));
+}
+/// Lists new alerts of the local player until they expire, dropping the oldest when full
+fn show_alerts(
     mut commands: Commands,
-    ui_elements: Query<(Entity, &UIContent)>,
-    deselect_event: EventReader<DeselectEvent>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut alerts: EventReader<Alert>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+    feed: Query<(Entity, Option<&Children>), With<AlertFeed>>,
+    lines: Query<&AlertLine>,
 ) {
-    if !deselect_event.is_empty() {
-        let (selection_info_content, _): (Entity, _) = ui_elements
-            .into_iter()
-            .find(|(_, content)| **content == UIContent::Content(UIType::SelectionInfo))
-            .unwrap();
-        commands
-            .entity(selection_info_content)
-            .despawn_descendants();
-        let (contect_menu_content, _): (Entity, _) = ui_elements
-            .into_iter()
-            .find(|(_, content)| **content == UIContent::Content(UIType::ContextMenu))
-            .unwrap();
-        commands.entity(contect_menu_content).despawn_descendants();
+    let (Ok((feed, children)), Ok(local_player)) = (feed.get_single(), local_player.get_single())
+    else {
+        alerts.clear();
+        return;
+    };
+    let now: f64 = time.elapsed_seconds_f64();
+    // Children are kept in the order they were added, oldest first
+    let mut shown: Vec<Entity> = Vec::new();
+    for child in children.into_iter().flatten() {
+        match lines.get(*child) {
+            Ok(line) if now - line.shown_at < ALERT_DURATION => shown.push(*child),
+            _ => commands.entity(*child).despawn_recursive(),
+        }
+    }
+    for alert in alerts.read().filter(|alert| alert.player == local_player) {
+        let line: Entity = commands
+            .spawn((
+                TextBundle::from_section(
+                    alert.message.clone(),
+                    TextStyle {
+                        font: asset_server
+                            .load("fonts/android-insomnia-font/AndroidInsomniaRegular.ttf"),
+                        font_size: 16.0,
+                        color: ALERT_TEXT,
+                    },
+                ),
+                AlertLine { shown_at: now },
+            ))
+            .id();
+        commands.entity(feed).add_child(line);
+        shown.push(line);
+    }
+    let surplus: usize = shown.len().saturating_sub(MAX_ALERT_LINES);
+    for line in shown.drain(..surplus) {
+        commands.entity(line).despawn_recursive();
     }
 }
 fn update_fps(diagnostics: Res<DiagnosticsStore>, mut query: Query<(&mut Text, &UIContent)>) {
@@ -829,7 +1341,7 @@ fn update_resources(
     if let Ok(resource_stockpiles) = localplayer.get_single() {
         for (mut text, ui_content) in &mut ui_elements {
             if let UIContent::Content(UIType::Resources(resource_type)) = ui_content {
-                if let Some(resource_amount) = resource_stockpiles.get(&resource_type) {
+                if let Some(resource_amount) = resource_stockpiles.get(resource_type) {
                     text.sections[0].value = format!("{}", *resource_amount);
                 }
             }
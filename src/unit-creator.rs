@@ -1,26 +1,13 @@
-mod a_star;
-mod civilisation;
-mod environment;
-mod movable;
-mod ownable;
-mod player_controller;
-mod resource_collection;
-mod resources;
-mod spawner;
-mod ui;
-mod utils;
-
 use std::{
     f32::consts::TAU,
     // fs,
     // io::BufWriter
 };
 
-use bevy::color::palettes::css::RED;
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 use bevy::{
-    color::palettes::css::{DARK_GRAY, DARK_OLIVEGREEN},
+    color::palettes::css::DARK_GRAY,
     core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping},
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     math::Vec3,
@@ -41,12 +28,18 @@ use bevy_lunex::prelude::*;
 // use spawner::{UnitSpecification, UnitStats};
 // use utils::ShapeTypeSerializable;
 
+// Kept for `button_system` and the file dialogs, which are switched off for now
+#[allow(dead_code)]
 const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
+#[allow(dead_code)]
 const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
+#[allow(dead_code)]
 const PRESSED_BUTTON: Color = Color::srgb(0.35, 0.75, 0.35);
-
+#[allow(dead_code)]
 struct TextFileContents;
+/// The model shown in the preview, by its path
 #[derive(Component)]
+#[allow(dead_code)]
 struct RenderedUnit(String);
 struct PrintFilePath;
 fn main() {
@@ -120,12 +113,7 @@ struct FpsText;
 //     // RenderLayers::from_layers(&[1]),
 // ));
 // }
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    images: ResMut<Assets<Image>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     // UI camera
     commands.spawn((
         MainUi,
@@ -342,6 +330,7 @@ fn rotate_rendered(mut cameras: Query<&mut Transform, With<RenderedUnit>>, timer
     }
 }
 
+#[allow(dead_code)]
 fn pick(mut commands: Commands) {
     commands.dialog().pick_file_path::<PrintFilePath>();
 }
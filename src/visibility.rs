@@ -0,0 +1,338 @@
+use crate::environment::{GridSettings, MovementGrid};
+use crate::ownable::Owner;
+use crate::player_controller::{LocalPlayer, RenderLayerMap};
+use crate::resources::ResourceSource;
+use crate::spawner::UnitInformation;
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        view::RenderLayers,
+    },
+};
+use std::time::Duration;
+
+const UNEXPLORED_ALPHA: u8 = 250;
+const EXPLORED_ALPHA: u8 = 150;
+const VISIBLE_ALPHA: u8 = 0;
+
+pub struct FogOfWar;
+impl Plugin for FogOfWar {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(VisibilityTimer(Timer::new(
+            Duration::from_millis(200),
+            TimerMode::Repeating,
+        )))
+        .add_systems(Startup, setup_fog_of_war)
+        .add_systems(
+            Update,
+            (
+                update_visibility,
+                apply_visibility.after(update_visibility),
+                update_fog_texture.after(update_visibility),
+            ),
+        );
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CellVisibility {
+    #[default]
+    Unexplored,
+    Explored,
+    Visible,
+}
+#[derive(Resource)]
+pub struct VisibilityGrid {
+    pub settings: GridSettings,
+    pub cells: Vec<Vec<CellVisibility>>,
+}
+#[derive(Resource)]
+struct VisibilityTimer(Timer);
+#[derive(Resource)]
+struct FogTexture(Handle<Image>);
+
+impl VisibilityGrid {
+    pub fn new(settings: &GridSettings) -> Self {
+        VisibilityGrid {
+            settings: settings.clone(),
+            cells: vec![
+                vec![CellVisibility::Unexplored; settings.grid_height as usize];
+                settings.grid_width as usize
+            ],
+        }
+    }
+    /// Visibility at the world position (x, z). Everything off the grid counts as unexplored.
+    pub fn get(&self, position: Vec2) -> CellVisibility {
+        match self.settings.world_to_cell(position) {
+            Some(cell) => self.cells[cell.x as usize][cell.y as usize],
+            None => CellVisibility::Unexplored,
+        }
+    }
+    pub fn is_visible(&self, position: Vec2) -> bool {
+        self.get(position) == CellVisibility::Visible
+    }
+    /// Recalculate which cells are currently visible from the given viewers (position, vision range).
+    /// Cells that were visible before but are not covered anymore fall back to explored.
+    pub fn update<I>(&mut self, viewers: I)
+    where
+        I: IntoIterator<Item = (Vec2, f32)>,
+    {
+        for column in self.cells.iter_mut() {
+            for cell in column.iter_mut() {
+                if *cell == CellVisibility::Visible {
+                    *cell = CellVisibility::Explored;
+                }
+            }
+        }
+        for (position, range) in viewers {
+            if range <= 0.0 {
+                continue;
+            }
+            let centre: IVec2 = (position / self.settings.cell_size + self.settings.xy_offset)
+                .floor()
+                .as_ivec2();
+            let radius: i32 = (range / self.settings.cell_size).ceil() as i32;
+            for x in (centre.x - radius).max(0)
+                ..(centre.x + radius + 1).min(self.settings.grid_width as i32)
+            {
+                for y in (centre.y - radius).max(0)
+                    ..(centre.y + radius + 1).min(self.settings.grid_height as i32)
+                {
+                    let cell: UVec2 = UVec2::new(x as u32, y as u32);
+                    if self.settings.cell_to_world(cell).distance(position) <= range {
+                        self.cells[x as usize][y as usize] = CellVisibility::Visible;
+                    }
+                }
+            }
+        }
+    }
+    /// Which cells have been seen, one string per column with `#` for explored and `.` for
+    /// unexplored. What is visible is worked out again from the units.
+    pub fn explored(&self) -> Vec<String> {
+        self.cells
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|cell| match cell {
+                        CellVisibility::Unexplored => '.',
+                        CellVisibility::Explored | CellVisibility::Visible => '#',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    /// Marks the cells `explored` gives as explored, ignoring anything beyond the grid
+    pub fn restore_explored(&mut self, explored: &[String]) {
+        for (column, saved) in self.cells.iter_mut().zip(explored) {
+            for (cell, saved) in column.iter_mut().zip(saved.chars()) {
+                *cell = if saved == '#' {
+                    CellVisibility::Explored
+                } else {
+                    CellVisibility::Unexplored
+                };
+            }
+        }
+    }
+}
+
+fn setup_fog_of_war(
+    mut commands: Commands,
+    movement_grid: Res<MovementGrid>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let settings: &GridSettings = &movement_grid.settings;
+    let image_handle: Handle<Image> = images.add(Image::new_fill(
+        Extent3d {
+            width: settings.grid_width,
+            height: settings.grid_height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, UNEXPLORED_ALPHA],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Plane3d::default().mesh().size(
+                settings.grid_width as f32 * settings.cell_size,
+                settings.grid_height as f32 * settings.cell_size,
+            )),
+            material: materials.add(StandardMaterial {
+                base_color_texture: Some(image_handle.clone()),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+            transform: Transform::from_xyz(0.0, 0.05, 0.0),
+            ..default()
+        },
+        RenderLayers::from_layers(&[
+            RenderLayerMap::Main as usize,
+            RenderLayerMap::Minimap as usize,
+        ]),
+    ));
+    commands.insert_resource(VisibilityGrid::new(settings));
+    commands.insert_resource(FogTexture(image_handle));
+}
+
+fn update_visibility(
+    time: Res<Time>,
+    mut timer: ResMut<VisibilityTimer>,
+    mut visibility_grid: ResMut<VisibilityGrid>,
+    units: Query<(&Transform, &UnitInformation, &Owner)>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Ok(local_player) = local_player.get_single() else {
+        return;
+    };
+    visibility_grid.update(
+        units
+            .iter()
+            .filter(|(_, _, owner)| owner.player == local_player)
+            .map(|(transform, unit_information, _)| {
+                (
+                    transform.translation.xz(),
+                    unit_information.stats.vision_range(),
+                )
+            }),
+    );
+}
+
+// Enemy units are only shown while in vision, resources once they have been explored
+fn apply_visibility(
+    visibility_grid: Res<VisibilityGrid>,
+    mut units: Query<(&Transform, &Owner, &mut Visibility)>,
+    mut resources: Query<(&Transform, &mut Visibility), (With<ResourceSource>, Without<Owner>)>,
+    local_player: Query<Entity, With<LocalPlayer>>,
+) {
+    if !visibility_grid.is_changed() {
+        return;
+    }
+    let Ok(local_player) = local_player.get_single() else {
+        return;
+    };
+    for (transform, owner, mut visibility) in units.iter_mut() {
+        let new_visibility: Visibility = if owner.player == local_player
+            || visibility_grid.is_visible(transform.translation.xz())
+        {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+    for (transform, mut visibility) in resources.iter_mut() {
+        let new_visibility: Visibility = match visibility_grid.get(transform.translation.xz()) {
+            CellVisibility::Unexplored => Visibility::Hidden,
+            _ => Visibility::Inherited,
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
+
+fn update_fog_texture(
+    visibility_grid: Res<VisibilityGrid>,
+    fog_texture: Res<FogTexture>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !visibility_grid.is_changed() {
+        return;
+    }
+    let Some(image) = images.get_mut(&fog_texture.0) else {
+        return;
+    };
+    let width: usize = visibility_grid.settings.grid_width as usize;
+    for (x, column) in visibility_grid.cells.iter().enumerate() {
+        for (y, cell) in column.iter().enumerate() {
+            // The plane's uv runs along +x and +z, so grid rows map onto texture rows
+            image.data[(y * width + x) * 4 + 3] = match cell {
+                CellVisibility::Unexplored => UNEXPLORED_ALPHA,
+                CellVisibility::Explored => EXPLORED_ALPHA,
+                CellVisibility::Visible => VISIBLE_ALPHA,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visibility_grid() -> VisibilityGrid {
+        VisibilityGrid::new(&GridSettings {
+            cell_size: 1.0,
+            grid_width: 40,
+            grid_height: 40,
+            xy_offset: Vec2::splat(20.0),
+            density: 0.0,
+        })
+    }
+
+    #[test]
+    fn cells_in_vision_range_are_visible() {
+        let mut visibility_grid: VisibilityGrid = visibility_grid();
+        visibility_grid.update([(Vec2::new(0.5, 0.5), 5.0)]);
+        assert!(visibility_grid.is_visible(Vec2::new(0.5, 0.5)));
+        assert!(visibility_grid.is_visible(Vec2::new(4.5, 0.5)));
+        assert!(visibility_grid.is_visible(Vec2::new(-2.5, 3.5)));
+        assert_eq!(
+            visibility_grid.get(Vec2::new(6.5, 0.5)),
+            CellVisibility::Unexplored
+        );
+        assert_eq!(
+            visibility_grid.get(Vec2::new(4.5, 4.5)),
+            CellVisibility::Unexplored
+        );
+        // Off the grid nothing is ever seen
+        visibility_grid.update([(Vec2::new(19.5, 0.5), 5.0)]);
+        assert_eq!(
+            visibility_grid.get(Vec2::new(22.5, 0.5)),
+            CellVisibility::Unexplored
+        );
+    }
+
+    #[test]
+    fn cells_out_of_range_stay_explored() {
+        let mut visibility_grid: VisibilityGrid = visibility_grid();
+        visibility_grid.update([(Vec2::new(-10.5, -10.5), 3.0)]);
+        visibility_grid.update([(Vec2::new(10.5, 10.5), 3.0)]);
+        assert_eq!(
+            visibility_grid.get(Vec2::new(-10.5, -10.5)),
+            CellVisibility::Explored
+        );
+        assert!(visibility_grid.is_visible(Vec2::new(10.5, 10.5)));
+        // Without any viewers everything seen so far is only explored
+        visibility_grid.update([]);
+        assert_eq!(
+            visibility_grid.get(Vec2::new(10.5, 10.5)),
+            CellVisibility::Explored
+        );
+        assert_eq!(
+            visibility_grid.get(Vec2::new(0.5, 0.5)),
+            CellVisibility::Unexplored
+        );
+    }
+
+    #[test]
+    fn explored_cells_survive_a_save() {
+        let mut seen: VisibilityGrid = visibility_grid();
+        seen.update([(Vec2::new(0.5, 0.5), 5.0)]);
+        let mut loaded: VisibilityGrid = visibility_grid();
+        loaded.restore_explored(&seen.explored());
+        assert_eq!(loaded.get(Vec2::new(0.5, 0.5)), CellVisibility::Explored);
+        assert_eq!(loaded.get(Vec2::new(6.5, 0.5)), CellVisibility::Unexplored);
+        assert_eq!(loaded.explored(), seen.explored());
+    }
+}